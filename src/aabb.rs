@@ -1,34 +1,51 @@
 use cgmath::{vec2, Vector2};
+use scalar::{real, Scalar};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub struct Aabb {
-    top_left: Vector2<f64>,
-    size: Vector2<f64>,
+pub struct Aabb<S> {
+    top_left: Vector2<S>,
+    size: Vector2<S>,
 }
 
-pub struct AabbSplitFour {
-    pub top_left: Aabb,
-    pub top_right: Aabb,
-    pub bottom_left: Aabb,
-    pub bottom_right: Aabb,
+pub struct AabbSplitFour<S> {
+    pub top_left: Aabb<S>,
+    pub top_right: Aabb<S>,
+    pub bottom_left: Aabb<S>,
+    pub bottom_right: Aabb<S>,
 }
 
-impl Aabb {
-    pub fn new(top_left: Vector2<f64>, size: Vector2<f64>) -> Self {
+impl<S: Scalar> Aabb<S> {
+    pub fn new(top_left: Vector2<S>, size: Vector2<S>) -> Self {
         Self { top_left, size }
     }
     pub fn from_centre_and_half_size(
-        centre: Vector2<f64>,
-        half_size: Vector2<f64>,
+        centre: Vector2<S>,
+        half_size: Vector2<S>,
     ) -> Self {
         let top_left = centre - half_size;
-        let size = half_size * 2.;
+        let size = half_size * real(2.);
         Self::new(top_left, size)
     }
-    fn bottom_right_coord(&self) -> Vector2<f64> {
+    pub fn from_points(points: &[Vector2<S>]) -> Self {
+        let first = points[0];
+        let (min, max) = points.iter().skip(1).fold((first, first), |(min, max), p| {
+            (
+                vec2(min.x.min(p.x), min.y.min(p.y)),
+                vec2(max.x.max(p.x), max.y.max(p.y)),
+            )
+        });
+        Self::new(min, max - min)
+    }
+    pub fn add_vector(&self, vector: Vector2<S>) -> Self {
+        Self::new(self.top_left + vector, self.size)
+    }
+    pub fn top_left(&self) -> Vector2<S> {
+        self.top_left
+    }
+    pub fn bottom_right_coord(&self) -> Vector2<S> {
         self.top_left + self.size
     }
-    pub fn from_union(a: &Aabb, b: &Aabb) -> Self {
+    pub fn from_union(a: &Self, b: &Self) -> Self {
         let top_left = vec2(
             a.top_left.x.min(b.top_left.x),
             a.top_left.y.min(b.top_left.y),
@@ -45,20 +62,53 @@ impl Aabb {
     pub fn union(&self, other: &Self) -> Self {
         Self::from_union(self, other)
     }
-    pub fn size(&self) -> Vector2<f64> {
+    pub fn size(&self) -> Vector2<S> {
         self.size
     }
-    pub fn is_intersecting(&self, other: &Aabb) -> bool {
+    pub fn is_intersecting(&self, other: &Self) -> bool {
         self.top_left.x + self.size.x >= other.top_left.x
             && other.top_left.x + other.size.x >= self.top_left.x
             && self.top_left.y + self.size.y >= other.top_left.y
             && other.top_left.y + other.size.y >= self.top_left.y
     }
-    pub fn centre(&self) -> Vector2<f64> {
-        self.top_left + self.size / 2.
+    pub fn is_containing(&self, other: &Self) -> bool {
+        let bottom_right_coord = self.bottom_right_coord();
+        let other_bottom_right_coord = other.bottom_right_coord();
+        other.top_left.x >= self.top_left.x && other.top_left.y >= self.top_left.y
+            && other_bottom_right_coord.x <= bottom_right_coord.x
+            && other_bottom_right_coord.y <= bottom_right_coord.y
+    }
+    pub fn is_intersecting_segment(&self, start: Vector2<S>, end: Vector2<S>) -> bool {
+        let delta = end - start;
+        let bottom_right_coord = self.bottom_right_coord();
+        let axes = [
+            (start.x, delta.x, self.top_left.x, bottom_right_coord.x),
+            (start.y, delta.y, self.top_left.y, bottom_right_coord.y),
+        ];
+        let mut min_multiplier: S = real(0.);
+        let mut max_multiplier: S = real(1.);
+        for &(start, delta, min, max) in axes.iter() {
+            if delta == real(0.) {
+                if start < min || start > max {
+                    return false;
+                }
+            } else {
+                let a = (min - start) / delta;
+                let b = (max - start) / delta;
+                min_multiplier = min_multiplier.max(a.min(b));
+                max_multiplier = max_multiplier.min(a.max(b));
+                if min_multiplier > max_multiplier {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+    pub fn centre(&self) -> Vector2<S> {
+        self.top_left + self.size / real(2.)
     }
-    pub fn split_four(&self) -> AabbSplitFour {
-        let size = self.size / 2.;
+    pub fn split_four(&self) -> AabbSplitFour<S> {
+        let size = self.size / real(2.);
         AabbSplitFour {
             top_left: Self::new(self.top_left, size),
             top_right: Self::new(vec2(self.top_left.x + size.x, self.top_left.y), size),
@@ -1,151 +1,124 @@
 use aabb::Aabb;
 use cgmath::{vec2, Vector2};
-use collide::{channels, flags, Collide, Edge};
-use left_solid_edge::EPSILON;
+use collide::{channels, flags, Collide, Edge, EdgeProperties};
+use scalar::{real, Scalar};
 
-#[derive(Debug, Clone, Copy)]
-enum Metadata {
-    Main,
-    Character,
-    FloorOnly,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RectEdgeProperties {
+    pub top: EdgeProperties,
+    pub right: EdgeProperties,
+    pub bottom: EdgeProperties,
+    pub left: EdgeProperties,
 }
 
-impl Metadata {
-    fn top_channel(self) -> u32 {
-        match self {
-            Metadata::FloorOnly => channels::FLOOR,
-            Metadata::Character => channels::MAIN,
-            _ => channels::MAIN,
-        }
-    }
-    fn bottom_channel(self) -> u32 {
-        match self {
-            Metadata::FloorOnly => 0,
-            Metadata::Character => channels::MAIN | channels::FLOOR,
-            _ => channels::MAIN,
-        }
-    }
-    fn left_channel(self) -> u32 {
-        match self {
-            Metadata::FloorOnly => 0,
-            Metadata::Character => channels::MAIN,
-            _ => channels::MAIN,
-        }
-    }
-    fn right_channel(self) -> u32 {
-        match self {
-            Metadata::FloorOnly => 0,
-            Metadata::Character => channels::MAIN,
-            _ => channels::MAIN,
-        }
-    }
-    fn top_flags(self) -> u32 {
-        match self {
-            _ => 0,
+impl RectEdgeProperties {
+    pub fn all(properties: EdgeProperties) -> Self {
+        Self {
+            top: properties,
+            right: properties,
+            bottom: properties,
+            left: properties,
         }
     }
-    fn bottom_flags(self) -> u32 {
-        match self {
-            _ => 0,
-        }
+    pub fn main() -> Self {
+        Self::all(EdgeProperties::default())
     }
-    fn left_flags(self) -> u32 {
-        match self {
-            Metadata::Character => flags::BUMP_START,
-            _ => 0,
+    pub fn character() -> Self {
+        Self {
+            top: EdgeProperties::new(channels::MAIN)
+                .with_flags(flags::CORNER_START | flags::CORNER_END),
+            right: EdgeProperties::new(channels::MAIN).with_flags(flags::BUMP_END),
+            bottom: EdgeProperties::new(channels::MAIN | channels::FLOOR),
+            left: EdgeProperties::new(channels::MAIN).with_flags(flags::BUMP_START),
         }
     }
-    fn right_flags(self) -> u32 {
-        match self {
-            Metadata::Character => flags::BUMP_END,
-            _ => 0,
+    pub fn floor_only() -> Self {
+        Self {
+            top: EdgeProperties::new(channels::FLOOR),
+            ..Self::all(EdgeProperties::new(0))
         }
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct AxisAlignedRect {
-    dimensions: Vector2<f64>,
-    metadata: Metadata,
+pub struct AxisAlignedRect<S> {
+    dimensions: Vector2<S>,
+    edge_properties: RectEdgeProperties,
 }
 
-impl AxisAlignedRect {
-    pub fn new(dimensions: Vector2<f64>) -> Self {
-        Self {
-            dimensions,
-            metadata: Metadata::Main,
-        }
+impl<S: Scalar> AxisAlignedRect<S> {
+    pub fn new(dimensions: Vector2<S>) -> Self {
+        Self::new_with_edge_properties(dimensions, RectEdgeProperties::main())
     }
-    pub fn new_character(dimensions: Vector2<f64>) -> Self {
-        Self {
-            dimensions,
-            metadata: Metadata::Character,
-        }
+    pub fn new_character(dimensions: Vector2<S>) -> Self {
+        Self::new_with_edge_properties(dimensions, RectEdgeProperties::character())
     }
-    pub fn new_floor_only(dimensions: Vector2<f64>) -> Self {
+    pub fn new_floor_only(dimensions: Vector2<S>) -> Self {
+        Self::new_with_edge_properties(dimensions, RectEdgeProperties::floor_only())
+    }
+    pub fn new_with_edge_properties(
+        dimensions: Vector2<S>,
+        edge_properties: RectEdgeProperties,
+    ) -> Self {
         Self {
             dimensions,
-            metadata: Metadata::FloorOnly,
+            edge_properties,
         }
     }
-    fn top_left(&self) -> Vector2<f64> {
-        vec2(0., 0.)
+    fn top_left(&self) -> Vector2<S> {
+        vec2(real(0.), real(0.))
     }
-    fn top_right(&self) -> Vector2<f64> {
-        vec2(self.dimensions.x, 0.)
+    fn top_right(&self) -> Vector2<S> {
+        vec2(self.dimensions.x, real(0.))
     }
-    fn bottom_left(&self) -> Vector2<f64> {
-        vec2(0., self.dimensions.y)
+    fn bottom_left(&self) -> Vector2<S> {
+        vec2(real(0.), self.dimensions.y)
     }
-    fn bottom_right(&self) -> Vector2<f64> {
+    fn bottom_right(&self) -> Vector2<S> {
         self.dimensions
     }
-    fn top(&self) -> Edge {
+    fn top(&self) -> Edge<S> {
         Edge::new(self.top_left(), self.top_right())
     }
-    fn right(&self) -> Edge {
+    fn right(&self) -> Edge<S> {
         Edge::new(self.top_right(), self.bottom_right())
     }
-    fn bottom(&self) -> Edge {
+    fn bottom(&self) -> Edge<S> {
         Edge::new(self.bottom_right(), self.bottom_left())
     }
-    fn left(&self) -> Edge {
+    fn left(&self) -> Edge<S> {
         Edge::new(self.bottom_left(), self.top_left())
     }
 
-    pub fn dimensions(&self) -> Vector2<f64> {
+    pub fn dimensions(&self) -> Vector2<S> {
         self.dimensions
     }
+    #[allow(dead_code)]
+    pub fn edge_properties(&self) -> &RectEdgeProperties {
+        &self.edge_properties
+    }
 }
 
-impl Collide for AxisAlignedRect {
-    fn aabb(&self, top_left: Vector2<f64>) -> Aabb {
+impl<S: Scalar> Collide<S> for AxisAlignedRect<S> {
+    fn aabb(&self, top_left: Vector2<S>) -> Aabb<S> {
         Aabb::new(top_left, self.dimensions)
     }
-    fn for_each_left_solid_edge_facing<F: FnMut(Edge)>(
+    fn for_each_left_solid_edge_facing<F: FnMut(Edge<S>)>(
         &self,
-        direction: Vector2<f64>,
+        direction: Vector2<S>,
         mut f: F,
     ) {
-        if direction.y > -EPSILON {
-            f(self.bottom()
-                .with_channels(self.metadata.bottom_channel())
-                .with_flags(self.metadata.bottom_flags()))
+        if direction.y >= real(0.) {
+            f(self.bottom().with_properties(self.edge_properties.bottom))
         }
-        if direction.y < EPSILON {
-            f(self.top()
-                .with_channels(self.metadata.top_channel())
-                .with_flags(self.metadata.top_flags()))
+        if direction.y <= real(0.) {
+            f(self.top().with_properties(self.edge_properties.top))
         }
-        if direction.x > -EPSILON {
-            f(self.right()
-                .with_channels(self.metadata.right_channel())
-                .with_flags(self.metadata.right_flags()))
+        if direction.x >= real(0.) {
+            f(self.right().with_properties(self.edge_properties.right))
         }
-        if direction.x < EPSILON {
-            f(self.left()
-                .with_channels(self.metadata.left_channel())
-                .with_flags(self.metadata.left_flags()))
+        if direction.x <= real(0.) {
+            f(self.left().with_properties(self.edge_properties.left))
         }
     }
 }
@@ -0,0 +1,171 @@
+use aabb::Aabb;
+use broadphase::{AnyBroadphase, Broadphase, BroadphaseHandle};
+use cgmath::{vec2, Vector2};
+use std::time::{Duration, Instant};
+
+// a large level of static tiles, spanning negative coordinates, with
+// bodies moving through it which query and raycast every frame
+const TILE_SIZE: f64 = 32.;
+const NUM_TILES_X: usize = 320;
+const NUM_TILES_Y: usize = 50;
+const LEVEL_TOP_LEFT: (f64, f64) = (-5120., -800.);
+const NUM_BODIES: usize = 1000;
+const BODY_SIZE: f64 = 24.;
+const MAX_SPEED: f64 = 8.;
+const RAY_LENGTH: f64 = 400.;
+const NUM_FRAMES: usize = 300;
+
+// deterministic so every broadphase sees the same scene
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> f64 {
+        self.0 = self.0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + self.next() * (max - min)
+    }
+}
+
+// leaves gaps so the bodies have somewhere to be
+fn is_tile(x: usize, y: usize) -> bool {
+    (x * 7 + y * 13) % 5 < 3
+}
+
+fn num_tiles() -> usize {
+    (0..NUM_TILES_Y)
+        .map(|y| (0..NUM_TILES_X).filter(|&x| is_tile(x, y)).count())
+        .sum()
+}
+
+struct Body {
+    position: Vector2<f64>,
+    velocity: Vector2<f64>,
+    handle: BroadphaseHandle,
+}
+
+fn aabb(position: Vector2<f64>, size: f64) -> Aabb<f64> {
+    Aabb::new(position, vec2(size, size))
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000. + duration.subsec_nanos() as f64 / 1_000_000.
+}
+
+struct BenchmarkResult {
+    build: Duration,
+    frames: Duration,
+    num_hits: usize,
+}
+
+fn run_scene(broadphase: &mut AnyBroadphase<usize, f64>) -> BenchmarkResult {
+    let mut rng = Lcg(0);
+    let level_size = vec2(
+        NUM_TILES_X as f64 * TILE_SIZE,
+        NUM_TILES_Y as f64 * TILE_SIZE,
+    );
+    let level_top_left = vec2(LEVEL_TOP_LEFT.0, LEVEL_TOP_LEFT.1);
+
+    let build_start = Instant::now();
+    let mut id = 0;
+    for y in 0..NUM_TILES_Y {
+        for x in 0..NUM_TILES_X {
+            if is_tile(x, y) {
+                let position = level_top_left + vec2(x as f64, y as f64) * TILE_SIZE;
+                broadphase.insert(aabb(position, TILE_SIZE), id);
+                id += 1;
+            }
+        }
+    }
+    let mut bodies = (0..NUM_BODIES)
+        .map(|_| {
+            let position = level_top_left
+                + vec2(
+                    rng.range(0., level_size.x - BODY_SIZE),
+                    rng.range(0., level_size.y - BODY_SIZE),
+                );
+            let velocity = vec2(
+                rng.range(-MAX_SPEED, MAX_SPEED),
+                rng.range(-MAX_SPEED, MAX_SPEED),
+            );
+            let handle = broadphase.insert(aabb(position, BODY_SIZE), id);
+            id += 1;
+            Body {
+                position,
+                velocity,
+                handle,
+            }
+        })
+        .collect::<Vec<_>>();
+    let build = build_start.elapsed();
+
+    let frames_start = Instant::now();
+    let mut num_hits = 0;
+    for _ in 0..NUM_FRAMES {
+        for body in bodies.iter_mut() {
+            body.position += body.velocity;
+            let min = level_top_left;
+            let max = level_top_left + level_size - vec2(BODY_SIZE, BODY_SIZE);
+            if body.position.x < min.x || body.position.x > max.x {
+                body.velocity.x = -body.velocity.x;
+            }
+            if body.position.y < min.y || body.position.y > max.y {
+                body.velocity.y = -body.velocity.y;
+            }
+            broadphase.update(body.handle, aabb(body.position, BODY_SIZE));
+        }
+        for body in bodies.iter() {
+            let movement_aabb = aabb(body.position, BODY_SIZE)
+                .union(&aabb(body.position + body.velocity, BODY_SIZE));
+            broadphase.for_each_intersection(movement_aabb, |_, _| num_hits += 1);
+        }
+        for body in bodies.iter().step_by(10) {
+            let start = body.position;
+            let end = start + vec2(RAY_LENGTH, RAY_LENGTH / 2.);
+            broadphase.for_each_segment_intersection(start, end, |_, _| num_hits += 1);
+        }
+    }
+    let frames = frames_start.elapsed();
+
+    BenchmarkResult {
+        build,
+        frames,
+        num_hits,
+    }
+}
+
+pub fn run() {
+    println!(
+        "{} static tiles, {} moving bodies, {} frames",
+        num_tiles(),
+        NUM_BODIES,
+        NUM_FRAMES
+    );
+    println!(
+        "{:<20}{:>12}{:>16}{:>12}",
+        "broadphase", "build (ms)", "per frame (ms)", "hits"
+    );
+    let mut expected_num_hits = None;
+    for name in AnyBroadphase::<usize, f64>::NAMES.iter() {
+        let mut broadphase = AnyBroadphase::from_name(name).expect("Unknown broadphase");
+        let result = run_scene(&mut broadphase);
+        println!(
+            "{:<20}{:>12.2}{:>16.3}{:>12}",
+            name,
+            millis(result.build),
+            millis(result.frames) / NUM_FRAMES as f64,
+            result.num_hits
+        );
+        match expected_num_hits {
+            Some(num_hits) => assert_eq!(
+                result.num_hits, num_hits,
+                "{} found different hits to the other broadphases",
+                name
+            ),
+            None => expected_num_hits = Some(result.num_hits),
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use aabb::Aabb;
+use cgmath::Vector2;
+use loose_quad_tree::LooseQuadTree;
+use scalar::{real, Scalar};
+use spatial_hash::SpatialHash;
+use sweep_and_prune::SweepAndPrune;
+
+const SPATIAL_HASH_CELL_SIZE: f64 = 64.;
+const SWEEP_AND_PRUNE_MAX_WIDTH: f64 = 128.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BroadphaseHandle(usize);
+
+pub trait Broadphase<T, S> {
+    fn insert(&mut self, aabb: Aabb<S>, t: T) -> BroadphaseHandle;
+    fn update(&mut self, handle: BroadphaseHandle, aabb: Aabb<S>);
+    fn remove(&mut self, handle: BroadphaseHandle) -> T;
+    fn clear(&mut self);
+    fn for_each_intersection<F: FnMut(&Aabb<S>, &T)>(&self, aabb: Aabb<S>, f: F);
+    fn for_each_segment_intersection<F: FnMut(&Aabb<S>, &T)>(
+        &self,
+        start: Vector2<S>,
+        end: Vector2<S>,
+        f: F,
+    );
+}
+
+pub fn remove_handle(handles: &mut Vec<BroadphaseHandle>, handle: BroadphaseHandle) {
+    if let Some(index) = handles.iter().position(|&h| h == handle) {
+        handles.swap_remove(index);
+    }
+}
+
+// storage for broadphase entries, which hands out the handles
+#[derive(Debug, Clone)]
+pub struct Entries<E> {
+    entries: Vec<Option<E>>,
+    free: Vec<usize>,
+}
+
+impl<E> Default for Entries<E> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<E> Entries<E> {
+    pub fn insert(&mut self, entry: E) -> BroadphaseHandle {
+        match self.free.pop() {
+            Some(index) => {
+                self.entries[index] = Some(entry);
+                BroadphaseHandle(index)
+            }
+            None => {
+                self.entries.push(Some(entry));
+                BroadphaseHandle(self.entries.len() - 1)
+            }
+        }
+    }
+    pub fn remove(&mut self, handle: BroadphaseHandle) -> E {
+        let entry = self.entries[handle.0]
+            .take()
+            .expect("No entry for handle");
+        self.free.push(handle.0);
+        entry
+    }
+    pub fn get(&self, handle: BroadphaseHandle) -> &E {
+        self.entries[handle.0]
+            .as_ref()
+            .expect("No entry for handle")
+    }
+    pub fn get_mut(&mut self, handle: BroadphaseHandle) -> &mut E {
+        self.entries[handle.0]
+            .as_mut()
+            .expect("No entry for handle")
+    }
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.free.clear();
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len() - self.free.len()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (BroadphaseHandle, &E)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                entry
+                    .as_ref()
+                    .map(|entry| (BroadphaseHandle(index), entry))
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AnyBroadphase<T, S> {
+    LooseQuadTree(LooseQuadTree<T, S>),
+    SpatialHash(SpatialHash<T, S>),
+    SweepAndPrune(SweepAndPrune<T, S>),
+}
+
+impl<T, S: Scalar> AnyBroadphase<T, S> {
+    pub const NAMES: &'static [&'static str] =
+        &["loose-quad-tree", "spatial-hash", "sweep-and-prune"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "loose-quad-tree" => Some(AnyBroadphase::LooseQuadTree(LooseQuadTree::new())),
+            "spatial-hash" => Some(AnyBroadphase::SpatialHash(SpatialHash::new(
+                real(SPATIAL_HASH_CELL_SIZE),
+            ))),
+            "sweep-and-prune" => Some(AnyBroadphase::SweepAndPrune(SweepAndPrune::new(
+                real(SWEEP_AND_PRUNE_MAX_WIDTH),
+            ))),
+            _ => None,
+        }
+    }
+}
+
+impl<T, S: Scalar> Default for AnyBroadphase<T, S> {
+    fn default() -> Self {
+        AnyBroadphase::LooseQuadTree(LooseQuadTree::new())
+    }
+}
+
+impl<T, S: Scalar> Broadphase<T, S> for AnyBroadphase<T, S> {
+    fn insert(&mut self, aabb: Aabb<S>, t: T) -> BroadphaseHandle {
+        match *self {
+            AnyBroadphase::LooseQuadTree(ref mut b) => b.insert(aabb, t),
+            AnyBroadphase::SpatialHash(ref mut b) => b.insert(aabb, t),
+            AnyBroadphase::SweepAndPrune(ref mut b) => b.insert(aabb, t),
+        }
+    }
+    fn update(&mut self, handle: BroadphaseHandle, aabb: Aabb<S>) {
+        match *self {
+            AnyBroadphase::LooseQuadTree(ref mut b) => b.update(handle, aabb),
+            AnyBroadphase::SpatialHash(ref mut b) => b.update(handle, aabb),
+            AnyBroadphase::SweepAndPrune(ref mut b) => b.update(handle, aabb),
+        }
+    }
+    fn remove(&mut self, handle: BroadphaseHandle) -> T {
+        match *self {
+            AnyBroadphase::LooseQuadTree(ref mut b) => b.remove(handle),
+            AnyBroadphase::SpatialHash(ref mut b) => b.remove(handle),
+            AnyBroadphase::SweepAndPrune(ref mut b) => b.remove(handle),
+        }
+    }
+    fn clear(&mut self) {
+        match *self {
+            AnyBroadphase::LooseQuadTree(ref mut b) => b.clear(),
+            AnyBroadphase::SpatialHash(ref mut b) => b.clear(),
+            AnyBroadphase::SweepAndPrune(ref mut b) => b.clear(),
+        }
+    }
+    fn for_each_intersection<F: FnMut(&Aabb<S>, &T)>(&self, aabb: Aabb<S>, f: F) {
+        match *self {
+            AnyBroadphase::LooseQuadTree(ref b) => b.for_each_intersection(aabb, f),
+            AnyBroadphase::SpatialHash(ref b) => b.for_each_intersection(aabb, f),
+            AnyBroadphase::SweepAndPrune(ref b) => b.for_each_intersection(aabb, f),
+        }
+    }
+    fn for_each_segment_intersection<F: FnMut(&Aabb<S>, &T)>(
+        &self,
+        start: Vector2<S>,
+        end: Vector2<S>,
+        f: F,
+    ) {
+        match *self {
+            AnyBroadphase::LooseQuadTree(ref b) => {
+                b.for_each_segment_intersection(start, end, f)
+            }
+            AnyBroadphase::SpatialHash(ref b) => {
+                b.for_each_segment_intersection(start, end, f)
+            }
+            AnyBroadphase::SweepAndPrune(ref b) => {
+                b.for_each_segment_intersection(start, end, f)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+
+    fn aabb(x: f64, y: f64, width: f64, height: f64) -> Aabb<f64> {
+        Aabb::new(vec2(x, y), vec2(width, height))
+    }
+
+    fn broadphases() -> Vec<(&'static str, AnyBroadphase<u32, f64>)> {
+        AnyBroadphase::<u32, f64>::NAMES
+            .iter()
+            .map(|&name| (name, AnyBroadphase::from_name(name).unwrap()))
+            .collect()
+    }
+
+    fn intersecting(broadphase: &AnyBroadphase<u32, f64>, aabb: Aabb<f64>) -> Vec<u32> {
+        let mut found = Vec::new();
+        broadphase.for_each_intersection(aabb, |_aabb, &t| found.push(t));
+        found.sort();
+        found
+    }
+
+    fn along_segment(
+        broadphase: &AnyBroadphase<u32, f64>,
+        start: Vector2<f64>,
+        end: Vector2<f64>,
+    ) -> Vec<u32> {
+        let mut found = Vec::new();
+        broadphase.for_each_segment_intersection(start, end, |_aabb, &t| found.push(t));
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn insert_update_remove() {
+        let near_origin = aabb(-5., -5., 10., 10.);
+        let far = aabb(108., -92., 1., 1.);
+        for (name, mut broadphase) in broadphases() {
+            let a = broadphase.insert(aabb(0., 0., 10., 10.), 1);
+            broadphase.insert(aabb(100., -100., 10., 10.), 2);
+            assert_eq!(intersecting(&broadphase, near_origin), vec![1], "{}", name);
+            broadphase.update(a, aabb(105., -95., 10., 10.));
+            assert_eq!(intersecting(&broadphase, near_origin), vec![], "{}", name);
+            assert_eq!(intersecting(&broadphase, far), vec![1, 2], "{}", name);
+            assert_eq!(broadphase.remove(a), 1, "{}", name);
+            assert_eq!(intersecting(&broadphase, far), vec![2], "{}", name);
+            broadphase.clear();
+            assert_eq!(intersecting(&broadphase, far), vec![], "{}", name);
+        }
+    }
+
+    // entries of very different sizes, which move, grow, shrink and jump far
+    // away, checked against testing every entry
+    #[test]
+    fn queries_match_brute_force() {
+        let queries = [
+            aabb(-100., -100., 200., 200.),
+            aabb(-2000., -2000., 4000., 4000.),
+            aabb(300., 0., 1., 1.),
+            aabb(-700., 300., 0., 0.),
+        ];
+        let segments = [
+            (vec2(-800., 400.), vec2(800., -400.)),
+            (vec2(0., 0.), vec2(0., 0.)),
+            (vec2(-500., -100.), vec2(-500., 900.)),
+        ];
+        let mut initial = Vec::new();
+        for i in 0..40 {
+            let size = [0., 5., 70., 1000.][i % 4];
+            let position = vec2(i as f64 * 37. - 700., i as f64 * -23. + 300.);
+            initial.push(Aabb::new(position, vec2(size, size / 2.)));
+        }
+        for (name, mut broadphase) in broadphases() {
+            let mut aabbs = initial.clone();
+            let handles = aabbs
+                .iter()
+                .enumerate()
+                .map(|(i, &aabb)| broadphase.insert(aabb, i as u32))
+                .collect::<Vec<_>>();
+            for step in 0..3 {
+                let expected = |matches: &dyn Fn(&Aabb<f64>) -> bool| {
+                    (0..aabbs.len() as u32)
+                        .filter(|&i| matches(&aabbs[i as usize]))
+                        .collect::<Vec<_>>()
+                };
+                for query in queries.iter() {
+                    assert_eq!(
+                        intersecting(&broadphase, *query),
+                        expected(&|aabb| aabb.is_intersecting(query)),
+                        "{}",
+                        name
+                    );
+                }
+                for &(start, end) in segments.iter() {
+                    assert_eq!(
+                        along_segment(&broadphase, start, end),
+                        expected(&|aabb| aabb.is_intersecting_segment(start, end)),
+                        "{}",
+                        name
+                    );
+                }
+                for (i, &handle) in handles.iter().enumerate() {
+                    let aabb = aabbs[i];
+                    let size = aabb.size();
+                    aabbs[i] = match (i + step) % 3 {
+                        0 => aabb.add_vector(vec2(3., -2.)),
+                        1 => Aabb::new(aabb.top_left(), vec2(size.y, size.x * 2.)),
+                        _ => aabb.add_vector(vec2(-1500., 900.)),
+                    };
+                    broadphase.update(handle, aabbs[i]);
+                }
+            }
+        }
+    }
+}
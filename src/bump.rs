@@ -1,28 +1,59 @@
 use cgmath::{InnerSpace, Vector2};
-use collide::{flags, Collision};
+use collide::{flags, Collision, Flags};
 use left_solid_edge::StartOrEnd;
 use movement::ClosestCollisions;
+use scalar::Scalar;
 use std::cmp::Ordering;
+use tolerances::Tolerances;
 
-const EPSILON: f64 = 0.01;
-const BUMP_DISTANCE_PX: f64 = 2.;
-const BUMP_DISTANCE_PX2: f64 = BUMP_DISTANCE_PX * BUMP_DISTANCE_PX;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BumpKind {
+    Step,
+    Corner,
+}
 
-pub struct Bump {
-    distance2: f64,
-    direction: Vector2<f64>,
+pub struct Bump<S> {
+    distance2: S,
+    direction: Vector2<S>,
+    kind: BumpKind,
 }
 
-impl Bump {
-    pub fn vector(&self) -> Vector2<f64> {
+impl<S: Scalar> Bump<S> {
+    pub fn vector(&self) -> Vector2<S> {
         self.direction.normalize_to(self.distance2.sqrt())
     }
+    pub fn kind(&self) -> BumpKind {
+        self.kind
+    }
 }
 
-pub fn max_bump(closest_collisions: ClosestCollisions) -> Option<Bump> {
+pub fn max_bump<S: Scalar>(
+    closest_collisions: ClosestCollisions<S>,
+    max_step_distance: S,
+    max_corner_distance: S,
+    tolerances: &Tolerances<S>,
+) -> Option<Bump<S>> {
     closest_collisions
         .iter()
-        .filter_map(bump)
+        .filter_map(|collision_info| {
+            let step = bump(
+                collision_info,
+                flags::BUMP_START,
+                flags::BUMP_END,
+                max_step_distance,
+                BumpKind::Step,
+                tolerances,
+            );
+            let corner = bump(
+                collision_info,
+                flags::CORNER_START,
+                flags::CORNER_END,
+                max_corner_distance,
+                BumpKind::Corner,
+                tolerances,
+            );
+            step.or(corner)
+        })
         .max_by(|a, b| {
             a.distance2
                 .partial_cmp(&b.distance2)
@@ -30,8 +61,18 @@ pub fn max_bump(closest_collisions: ClosestCollisions) -> Option<Bump> {
         })
 }
 
-fn bump(collision_info: &Collision) -> Option<Bump> {
-    if collision_info.moving_edge_vector.flags & flags::BUMP_START != 0 {
+// only collisions between edges can be bumped (see
+// LeftSolidEdgeCollision::from_tangent)
+fn bump<S: Scalar>(
+    collision_info: &Collision<S>,
+    start_flag: Flags,
+    end_flag: Flags,
+    max_distance: S,
+    kind: BumpKind,
+    tolerances: &Tolerances<S>,
+) -> Option<Bump<S>> {
+    let max_distance2 = max_distance * max_distance;
+    if collision_info.moving_edge_vector.properties.flags & start_flag != 0 {
         if let Some(edge_collision_position) = collision_info
             .left_solid_edge_collision
             .moving_edge_min_collision_position()
@@ -40,28 +81,31 @@ fn bump(collision_info: &Collision) -> Option<Bump> {
                 let multiplier = edge_collision_position.how_far_along_this_edge;
                 let distance2 = collision_info.moving_edge_vector.vector.magnitude2()
                     * multiplier * multiplier;
-                if distance2 <= BUMP_DISTANCE_PX2 {
+                if distance2 <= max_distance2 {
                     return Some(Bump {
-                        distance2: distance2 + EPSILON,
+                        distance2: distance2 + tolerances.bump_clearance,
                         direction: collision_info.moving_edge_vector.vector,
+                        kind,
                     });
                 }
             }
         }
     }
-    if collision_info.moving_edge_vector.flags & flags::BUMP_END != 0 {
+    if collision_info.moving_edge_vector.properties.flags & end_flag != 0 {
         if let Some(edge_collision_position) = collision_info
             .left_solid_edge_collision
             .moving_edge_max_collision_position()
         {
             if edge_collision_position.which_part_of_other_edge == StartOrEnd::End {
-                let multiplier = 1. - edge_collision_position.how_far_along_this_edge;
+                let multiplier =
+                    S::one() - edge_collision_position.how_far_along_this_edge;
                 let distance2 = collision_info.moving_edge_vector.vector.magnitude2()
                     * multiplier * multiplier;
-                if distance2 <= BUMP_DISTANCE_PX2 {
+                if distance2 <= max_distance2 {
                     return Some(Bump {
-                        distance2: distance2 + EPSILON,
+                        distance2: distance2 + tolerances.bump_clearance,
                         direction: -collision_info.moving_edge_vector.vector,
+                        kind,
                     });
                 }
             }
@@ -0,0 +1,52 @@
+use aabb::Aabb;
+use cgmath::Vector2;
+use collide::EdgeProperties;
+use round::{self, Round, RoundedSegment};
+use scalar::Scalar;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capsule<S> {
+    pub start: Vector2<S>,
+    pub end: Vector2<S>,
+    radius: S,
+    edge_properties: EdgeProperties,
+}
+
+impl<S: Scalar> Capsule<S> {
+    pub fn new(start: Vector2<S>, end: Vector2<S>, radius: S) -> Self {
+        Self::new_with_edge_properties(
+            start,
+            end,
+            radius,
+            round::default_edge_properties(),
+        )
+    }
+    pub fn new_with_edge_properties(
+        start: Vector2<S>,
+        end: Vector2<S>,
+        radius: S,
+        edge_properties: EdgeProperties,
+    ) -> Self {
+        Self {
+            start,
+            end,
+            radius,
+            edge_properties,
+        }
+    }
+    pub fn radius(&self) -> S {
+        self.radius
+    }
+    pub fn aabb(&self, top_left: Vector2<S>) -> Aabb<S> {
+        self.rounded_segment().add_vector(top_left).aabb()
+    }
+}
+
+impl<S: Scalar> Round<S> for Capsule<S> {
+    fn rounded_segment(&self) -> RoundedSegment<S> {
+        RoundedSegment::new(self.start, self.end, self.radius)
+    }
+    fn edge_properties(&self) -> EdgeProperties {
+        self.edge_properties
+    }
+}
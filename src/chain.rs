@@ -0,0 +1,171 @@
+use aabb::Aabb;
+use cgmath::Vector2;
+use collide::{Collide, Edge};
+use left_solid_edge::vector2_cross_product;
+use scalar::{real, Scalar};
+
+#[derive(Debug, Clone)]
+pub struct Chain<S> {
+    vertices: Vec<Vector2<S>>,
+    closed: bool,
+    edges: Vec<Edge<S>>,
+    triangles: Vec<[Vector2<S>; 3]>,
+}
+
+fn chain_edges<S: Scalar>(vertices: &[Vector2<S>], closed: bool) -> Vec<Edge<S>> {
+    let num_vertices = vertices.len();
+    let num_edges = if closed { num_vertices } else { num_vertices - 1 };
+    let vertex = |index: usize| vertices[index % num_vertices];
+    (0..num_edges)
+        .map(|i| {
+            let start = vertex(i);
+            let end = vertex(i + 1);
+            let previous_vector = if closed || i > 0 {
+                Some(start - vertex(i + num_vertices - 1))
+            } else {
+                None
+            };
+            let next_vector = if closed || i + 1 < num_edges {
+                Some(vertex(i + 2) - end)
+            } else {
+                None
+            };
+            Edge::new(start, end).with_neighbours(previous_vector, next_vector)
+        })
+        .collect()
+}
+
+fn triangle_contains<S: Scalar>(
+    a: Vector2<S>,
+    b: Vector2<S>,
+    c: Vector2<S>,
+    p: Vector2<S>,
+) -> bool {
+    vector2_cross_product(b - a, p - a) >= real(0.)
+        && vector2_cross_product(c - b, p - b) >= real(0.)
+        && vector2_cross_product(a - c, p - c) >= real(0.)
+}
+
+// ear clipping, assuming the vertices wind clockwise on screen
+fn triangulate<S: Scalar>(vertices: &[Vector2<S>]) -> Vec<[Vector2<S>; 3]> {
+    let mut remaining = (0..vertices.len()).collect::<Vec<_>>();
+    let mut triangles = Vec::with_capacity(vertices.len() - 2);
+    while remaining.len() >= 3 {
+        let num_remaining = remaining.len();
+        let triangle = |i: usize| {
+            [
+                vertices[remaining[(i + num_remaining - 1) % num_remaining]],
+                vertices[remaining[i]],
+                vertices[remaining[(i + 1) % num_remaining]],
+            ]
+        };
+        let is_ear = |i: usize| {
+            let [a, b, c] = triangle(i);
+            vector2_cross_product(b - a, c - b) > real(0.)
+                && remaining.iter().all(|&j| {
+                    let p = vertices[j];
+                    p == a || p == b || p == c || !triangle_contains(a, b, c, p)
+                })
+        };
+        // degenerate polygons may have no ears, in which case clip anything
+        let ear = (0..num_remaining).find(|&i| is_ear(i)).unwrap_or(0);
+        triangles.push(triangle(ear));
+        remaining.remove(ear);
+    }
+    triangles
+}
+
+impl<S: Scalar> Chain<S> {
+    pub fn new_open(vertices: Vec<Vector2<S>>) -> Self {
+        assert!(vertices.len() >= 2, "A chain requires at least 2 vertices");
+        let mut edges = chain_edges(&vertices, false);
+        let reversed = vertices.iter().rev().cloned().collect::<Vec<_>>();
+        edges.extend(chain_edges(&reversed, false));
+        Self {
+            vertices,
+            closed: false,
+            edges,
+            triangles: Vec::new(),
+        }
+    }
+    pub fn new_closed(mut vertices: Vec<Vector2<S>>) -> Self {
+        assert!(vertices.len() >= 3, "Closed chain requires at least 3 vertices");
+        let num_vertices = vertices.len();
+        let twice_signed_area = (0..num_vertices).fold(S::zero(), |acc, i| {
+            acc + vector2_cross_product(vertices[i], vertices[(i + 1) % num_vertices])
+        });
+        // edges are left-solid, so vertices must wind clockwise on screen
+        if twice_signed_area < real(0.) {
+            vertices.reverse();
+        }
+        let edges = chain_edges(&vertices, true);
+        let triangles = triangulate(&vertices);
+        Self {
+            vertices,
+            closed: true,
+            edges,
+            triangles,
+        }
+    }
+    pub fn vertices(&self) -> &[Vector2<S>] {
+        &self.vertices
+    }
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+    pub fn triangles(&self) -> &[[Vector2<S>; 3]] {
+        &self.triangles
+    }
+}
+
+impl<S: Scalar> Collide<S> for Chain<S> {
+    fn aabb(&self, top_left: Vector2<S>) -> Aabb<S> {
+        Aabb::from_points(&self.vertices).add_vector(top_left)
+    }
+    fn for_each_left_solid_edge_facing<F: FnMut(Edge<S>)>(
+        &self,
+        direction: Vector2<S>,
+        mut f: F,
+    ) {
+        for edge in self.edges.iter() {
+            if edge.is_facing(direction) {
+                f(*edge);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+
+    fn area(triangle: &[Vector2<f64>; 3]) -> f64 {
+        let [a, b, c] = *triangle;
+        vector2_cross_product(b - a, c - b) / 2.
+    }
+
+    #[test]
+    fn closed_chains_are_triangulated() {
+        // an L shape, given anticlockwise on screen
+        let vertices = [
+            (0., 0.),
+            (0., 30.),
+            (30., 30.),
+            (30., 20.),
+            (10., 20.),
+            (10., 0.),
+        ].iter()
+            .map(|&(x, y)| vec2(x, y))
+            .collect();
+        let chain = Chain::new_closed(vertices);
+        let triangles = chain.triangles();
+        assert_eq!(triangles.len(), 4);
+        // every triangle winds the same way as the normalised chain
+        assert!(triangles.iter().all(|triangle| area(triangle) > 0.));
+        let total_area = triangles.iter().map(area).sum::<f64>();
+        assert!((total_area - 500.).abs() < 0.000001);
+        let open = Chain::new_open(vec![vec2(0., 0.), vec2(10., 0.)]);
+        assert!(open.triangles().is_empty());
+    }
+}
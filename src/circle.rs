@@ -0,0 +1,41 @@
+use aabb::Aabb;
+use cgmath::{vec2, Vector2};
+use collide::EdgeProperties;
+use round::{self, Round, RoundedSegment};
+use scalar::{real, Scalar};
+
+// Unlike the other shapes, whose position is the top left corner of the
+// shape, a circle is positioned by its centre.
+#[derive(Debug, Clone, Copy)]
+pub struct Circle<S> {
+    radius: S,
+    edge_properties: EdgeProperties,
+}
+
+impl<S: Scalar> Circle<S> {
+    pub fn new(radius: S) -> Self {
+        Self::new_with_edge_properties(radius, round::default_edge_properties())
+    }
+    pub fn new_with_edge_properties(radius: S, edge_properties: EdgeProperties) -> Self {
+        Self {
+            radius,
+            edge_properties,
+        }
+    }
+    pub fn radius(&self) -> S {
+        self.radius
+    }
+    pub fn aabb(&self, centre: Vector2<S>) -> Aabb<S> {
+        self.rounded_segment().add_vector(centre).aabb()
+    }
+}
+
+impl<S: Scalar> Round<S> for Circle<S> {
+    fn rounded_segment(&self) -> RoundedSegment<S> {
+        let centre = vec2(real(0.), real(0.));
+        RoundedSegment::new(centre, centre, self.radius)
+    }
+    fn edge_properties(&self) -> EdgeProperties {
+        self.edge_properties
+    }
+}
@@ -1,19 +1,120 @@
 use aabb::Aabb;
 use best::BestMultiSet;
-use cgmath::Vector2;
-use left_solid_edge::{LeftSolidEdge, LeftSolidEdgeCollision};
+use cgmath::{vec2, InnerSpace, Vector2};
+use left_solid_edge::{LeftSolidEdge, LeftSolidEdgeCollision, StartOrEnd};
+use layer::{CollisionMatrix, Layer, Response};
+use material::MaterialId;
 use movement::EntityId;
+use overlap::closest_point_on_segment;
+use predicates;
+use round::RoundedSegment;
+use scalar::{real, Scalar};
 use std::cmp::Ordering;
+use tolerances::Tolerances;
 
-const EPSILON: f64 = 0.001;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContactPoints<S> {
+    One(Vector2<S>),
+    Two(Vector2<S>, Vector2<S>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Contact<S> {
+    // points away from the stationary surface, opposing the movement
+    pub normal: Vector2<S>,
+    // world space points on the stationary surface at the time of contact
+    pub points: ContactPoints<S>,
+    // fraction of the attempted movement completed before contact
+    pub time: S,
+}
+
+impl<S: Scalar> Contact<S> {
+    fn new(
+        left_solid_edge_collision: &LeftSolidEdgeCollision<S>,
+        movement: Vector2<S>,
+        moving: RoundedSegment<S>,
+        stationary: RoundedSegment<S>,
+        tolerances: &Tolerances<S>,
+    ) -> Self {
+        let time = left_solid_edge_collision.movement_multiplier();
+        let normal = left_solid_edge_collision.normal(movement);
+        let moving = moving.add_vector(movement * time);
+        Self {
+            normal,
+            points: contact_points(&moving, &stationary, normal, tolerances),
+            time,
+        }
+    }
+}
+
+fn contact_points<S: Scalar>(
+    moving: &RoundedSegment<S>,
+    stationary: &RoundedSegment<S>,
+    normal: Vector2<S>,
+    tolerances: &Tolerances<S>,
+) -> ContactPoints<S> {
+    let touching_distance = tolerances.touching_distance;
+    let surface_offset = normal * stationary.radius;
+    let tangent = vec2(-normal.y, normal.x);
+    let moving_vector = moving.end - moving.start;
+    let stationary_vector = stationary.end - stationary.start;
+    let is_flush = |vector: Vector2<S>| {
+        vector.magnitude2() > touching_distance
+            && vector.dot(normal).abs() < touching_distance
+    };
+    if is_flush(moving_vector) && is_flush(stationary_vector) {
+        let interval = |segment: &RoundedSegment<S>| {
+            let a = segment.start.dot(tangent);
+            let b = segment.end.dot(tangent);
+            (a.min(b), a.max(b))
+        };
+        let (moving_min, moving_max) = interval(moving);
+        let (stationary_min, stationary_max) = interval(stationary);
+        let min = moving_min.max(stationary_min);
+        let max = moving_max.min(stationary_max);
+        if max - min > touching_distance {
+            let base = stationary.start + surface_offset
+                - tangent * stationary.start.dot(tangent);
+            return ContactPoints::Two(base + tangent * min, base + tangent * max);
+        }
+    }
+    let on_moving = |point| closest_point_on_segment(point, moving.start, moving.end);
+    let on_stationary =
+        |point| closest_point_on_segment(point, stationary.start, stationary.end);
+    let candidates = [
+        (moving.start, on_stationary(moving.start)),
+        (moving.end, on_stationary(moving.end)),
+        (on_moving(stationary.start), stationary.start),
+        (on_moving(stationary.end), stationary.end),
+    ];
+    let (_, stationary_point) = candidates
+        .iter()
+        .cloned()
+        .min_by(|&(a_moving, a_stationary), &(b_moving, b_stationary)| {
+            (a_moving - a_stationary)
+                .magnitude2()
+                .partial_cmp(&(b_moving - b_stationary).magnitude2())
+                .unwrap_or(Ordering::Equal)
+        })
+        .expect("No contact candidates");
+    ContactPoints::One(stationary_point + surface_offset)
+}
 
 #[derive(Debug)]
-pub struct Collision {
-    pub left_solid_edge_collision: LeftSolidEdgeCollision,
-    pub moving_edge_vector: EdgeVector,
-    pub stationary_edge_vector: EdgeVector,
+pub struct Collision<S> {
+    pub left_solid_edge_collision: LeftSolidEdgeCollision<S>,
+    pub contact: Contact<S>,
+    pub moving_edge_vector: EdgeVector<S>,
+    #[allow(dead_code)]
+    pub stationary_edge_vector: EdgeVector<S>,
     pub moving_entity_id: EntityId,
     pub stationary_entity_id: EntityId,
+    pub moving_layer: Layer,
+    pub stationary_layer: Layer,
+    pub moving_material: MaterialId,
+    pub stationary_material: MaterialId,
+    pub moving_child: Option<usize>,
+    pub stationary_child: Option<usize>,
 }
 
 pub type Flags = u32;
@@ -29,93 +130,280 @@ pub mod flags {
     use super::*;
     pub const BUMP_END: Flags = 1 << 2;
     pub const BUMP_START: Flags = 1 << 3;
+    pub const CORNER_END: Flags = 1 << 4;
+    pub const CORNER_START: Flags = 1 << 5;
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct Edge {
-    pub left_solid_edge: LeftSolidEdge,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeProperties {
     pub channels: Channels,
     pub flags: Flags,
+    pub layer: Option<Layer>,
+    pub material: Option<MaterialId>,
+    pub user_data: u64,
+}
+
+impl Default for EdgeProperties {
+    fn default() -> Self {
+        Self::new(channels::MAIN)
+    }
+}
+
+impl EdgeProperties {
+    pub fn new(channels: Channels) -> Self {
+        Self {
+            channels,
+            flags: 0,
+            layer: None,
+            material: None,
+            user_data: 0,
+        }
+    }
+    pub fn with_flags(self, flags: Flags) -> Self {
+        Self { flags, ..self }
+    }
+    pub fn with_layer(self, layer: Layer) -> Self {
+        Self {
+            layer: Some(layer),
+            ..self
+        }
+    }
+    pub fn with_material(self, material: MaterialId) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+    #[allow(dead_code)]
+    pub fn with_user_data(self, user_data: u64) -> Self {
+        Self { user_data, ..self }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Edge<S> {
+    pub left_solid_edge: LeftSolidEdge<S>,
+    pub properties: EdgeProperties,
+    pub previous_vector: Option<Vector2<S>>,
+    pub next_vector: Option<Vector2<S>>,
 }
 
 #[derive(Debug)]
-pub struct EdgeVector {
-    pub vector: Vector2<f64>,
-    pub channels: Channels,
-    pub flags: Flags,
+pub struct EdgeVector<S> {
+    pub vector: Vector2<S>,
+    pub properties: EdgeProperties,
 }
 
-impl Edge {
-    pub fn new(start: Vector2<f64>, end: Vector2<f64>) -> Self {
+fn outward_normal<S: Scalar>(vector: Vector2<S>) -> Vector2<S> {
+    vec2(vector.y, -vector.x).normalize()
+}
+
+impl<S: Scalar> EdgeVector<S> {
+    fn round(
+        left_solid_edge_collision: &LeftSolidEdgeCollision<S>,
+        properties: EdgeProperties,
+    ) -> Self {
+        EdgeVector {
+            vector: left_solid_edge_collision.edge_vector(),
+            properties,
+        }
+    }
+}
+
+impl<S: Scalar> Edge<S> {
+    pub fn new(start: Vector2<S>, end: Vector2<S>) -> Self {
         Edge {
             left_solid_edge: LeftSolidEdge::new(start, end),
-            channels: channels::MAIN,
-            flags: 0,
+            properties: EdgeProperties::default(),
+            previous_vector: None,
+            next_vector: None,
         }
     }
+    pub fn with_properties(self, properties: EdgeProperties) -> Self {
+        Self { properties, ..self }
+    }
     pub fn with_channels(self, channels: Channels) -> Self {
-        Self { channels, ..self }
+        self.with_properties(EdgeProperties {
+            channels,
+            ..self.properties
+        })
     }
+    #[allow(dead_code)]
     pub fn with_flags(self, flags: Flags) -> Self {
-        Self { flags, ..self }
+        self.with_properties(self.properties.with_flags(flags))
+    }
+    pub fn with_neighbours(
+        self,
+        previous_vector: Option<Vector2<S>>,
+        next_vector: Option<Vector2<S>>,
+    ) -> Self {
+        Self {
+            previous_vector,
+            next_vector,
+            ..self
+        }
     }
-    pub fn start(&self) -> Vector2<f64> {
+    pub fn start(&self) -> Vector2<S> {
         self.left_solid_edge.start
     }
-    pub fn end(&self) -> Vector2<f64> {
+    pub fn end(&self) -> Vector2<S> {
         self.left_solid_edge.end
     }
-    pub fn vector(&self) -> Vector2<f64> {
+    pub fn vector(&self) -> Vector2<S> {
         self.left_solid_edge.vector()
     }
-    pub fn edge_vector(&self) -> EdgeVector {
+    pub fn is_facing(&self, direction: Vector2<S>) -> bool {
+        predicates::edge_side(direction, self.start(), self.end()) != Ordering::Less
+    }
+    fn is_ghost_collision(
+        &self,
+        left_solid_edge_collision: &LeftSolidEdgeCollision<S>,
+        moving_edge_vector: Vector2<S>,
+        tolerances: &Tolerances<S>,
+    ) -> bool {
+        let (before, after) = match left_solid_edge_collision.stationary_vertex_only() {
+            Some(StartOrEnd::Start) => match self.previous_vector {
+                Some(previous_vector) => (previous_vector, self.vector()),
+                None => return false,
+            },
+            Some(StartOrEnd::End) => match self.next_vector {
+                Some(next_vector) => (self.vector(), next_vector),
+                None => return false,
+            },
+            None => return false,
+        };
+        let normal = -outward_normal(moving_edge_vector);
+        let before_normal = outward_normal(before);
+        let after_normal = outward_normal(after);
+        let is_convex = predicates::cross_sign(before, after) == Ordering::Greater;
+        if is_convex {
+            predicates::cross_sign(before_normal, normal) == Ordering::Less
+                || predicates::cross_sign(normal, after_normal) == Ordering::Less
+        } else {
+            normal.dot(before_normal) < S::one() - tolerances.direction
+                && normal.dot(after_normal) < S::one() - tolerances.direction
+        }
+    }
+    pub fn edge_vector(&self) -> EdgeVector<S> {
         EdgeVector {
             vector: self.vector(),
-            channels: self.channels,
-            flags: self.flags,
+            properties: self.properties,
         }
     }
 }
 
-pub struct CollidePosition<'a, C: 'a + Collide> {
+pub struct Collisions<'a, S> {
+    pub collision_matrix: &'a CollisionMatrix,
+    pub tolerances: &'a Tolerances<S>,
+    pub closest: &'a mut BestMultiSet<Collision<S>>,
+    pub reported: &'a mut Vec<Collision<S>>,
+}
+
+impl<'a, S: Scalar> Collisions<'a, S> {
+    fn insert(&mut self, collision: Collision<S>) {
+        match self.collision_matrix
+            .response(collision.moving_layer, collision.stationary_layer)
+        {
+            Response::Block => insert_closest_collision(
+                self.closest,
+                collision,
+                self.tolerances.simultaneous_time,
+            ),
+            Response::Report => self.reported.push(collision),
+            Response::Ignore => (),
+        }
+    }
+}
+
+pub struct CollidePosition<'a, S: 'a + Scalar, C: 'a + Collide<S>> {
     pub collide: &'a C,
-    pub position: Vector2<f64>,
+    pub position: Vector2<S>,
     pub entity_id: EntityId,
+    pub layer: Layer,
+    pub material: MaterialId,
+    pub child: Option<usize>,
 }
 
-impl<'a, C: Collide> CollidePosition<'a, C> {
+impl<'a, S: Scalar, C: Collide<S>> CollidePosition<'a, S, C> {
+    fn edge_layer(&self, edge: &Edge<S>) -> Layer {
+        edge.properties.layer.unwrap_or(self.layer)
+    }
+    fn edge_material(&self, edge: &Edge<S>) -> MaterialId {
+        edge.properties.material.unwrap_or(self.material)
+    }
+
     fn for_each_movement_collision<Stationary, F>(
         &self,
-        stationary: CollidePosition<Stationary>,
-        movement: Vector2<f64>,
+        stationary: CollidePosition<S, Stationary>,
+        movement: Vector2<S>,
+        collision_matrix: &CollisionMatrix,
+        tolerances: &Tolerances<S>,
         mut f: F,
     ) where
-        Stationary: Collide,
-        F: FnMut(Collision),
+        Stationary: Collide<S>,
+        F: FnMut(Collision<S>),
     {
+        let moving_aabb = self.collide.aabb(self.position);
+        let rel_movement_aabb = moving_aabb
+            .union(&moving_aabb.add_vector(movement))
+            .add_vector(-stationary.position);
         self.collide
             .for_each_left_solid_edge_facing(movement, |moving_rel_edge| {
+                let moving_layer = self.edge_layer(&moving_rel_edge);
                 let moving_edge = moving_rel_edge
                     .left_solid_edge
                     .add_vector(self.position);
-                stationary.collide.for_each_left_solid_edge_facing(
+                stationary.collide.for_each_left_solid_edge_facing_in(
+                    rel_movement_aabb,
                     -movement,
                     |stationary_rel_edge| {
-                        if moving_rel_edge.channels & stationary_rel_edge.channels == 0 {
+                        if moving_rel_edge.properties.channels
+                            & stationary_rel_edge.properties.channels == 0
+                        {
+                            return;
+                        }
+                        let stationary_layer =
+                            stationary.edge_layer(&stationary_rel_edge);
+                        if collision_matrix.ignores(moving_layer, stationary_layer) {
                             return;
                         }
                         let stationary_edge = stationary_rel_edge
                             .left_solid_edge
                             .add_vector(stationary.position);
                         if let Some(left_solid_edge_collision) = moving_edge
-                            .collide_with_stationary_edge(&stationary_edge, movement)
-                        {
+                            .collide_with_stationary_edge(
+                                &stationary_edge,
+                                movement,
+                                tolerances,
+                            ) {
+                            if stationary_rel_edge.is_ghost_collision(
+                                &left_solid_edge_collision,
+                                moving_rel_edge.vector(),
+                                tolerances,
+                            ) {
+                                return;
+                            }
+                            let contact = Contact::new(
+                                &left_solid_edge_collision,
+                                movement,
+                                RoundedSegment::from_left_solid_edge(&moving_edge),
+                                RoundedSegment::from_left_solid_edge(&stationary_edge),
+                                tolerances,
+                            );
                             let collision_info = Collision {
                                 left_solid_edge_collision,
+                                contact,
                                 moving_edge_vector: moving_rel_edge.edge_vector(),
                                 stationary_edge_vector: stationary_rel_edge.edge_vector(),
                                 moving_entity_id: self.entity_id,
                                 stationary_entity_id: stationary.entity_id,
+                                moving_layer,
+                                stationary_layer,
+                                moving_material: self.edge_material(&moving_rel_edge),
+                                stationary_material: stationary
+                                    .edge_material(&stationary_rel_edge),
+                                moving_child: self.child,
+                                stationary_child: stationary.child,
                             };
                             f(collision_info);
                         }
@@ -126,33 +414,405 @@ impl<'a, C: Collide> CollidePosition<'a, C> {
 
     pub fn movement_collision_test<Stationary>(
         &self,
-        stationary: CollidePosition<Stationary>,
-        movement: Vector2<f64>,
-        closest_collisions: &mut BestMultiSet<Collision>,
+        stationary: CollidePosition<S, Stationary>,
+        movement: Vector2<S>,
+        collisions: &mut Collisions<S>,
     ) where
-        Stationary: Collide,
+        Stationary: Collide<S>,
     {
-        self.for_each_movement_collision(stationary, movement, |collision| {
-            closest_collisions.insert_lt_by(collision, |a, b| {
-                let delta = a.left_solid_edge_collision.movement_multiplier()
-                    - b.left_solid_edge_collision.movement_multiplier();
-                if delta.abs() < EPSILON {
-                    Ordering::Equal
-                } else if delta > 0. {
-                    Ordering::Greater
-                } else {
-                    Ordering::Less
+        let collision_matrix = collisions.collision_matrix;
+        let tolerances = collisions.tolerances;
+        self.for_each_movement_collision(
+            stationary,
+            movement,
+            collision_matrix,
+            tolerances,
+            |collision| {
+                collisions.insert(collision);
+            },
+        );
+    }
+
+    pub fn movement_collision_test_round(
+        &self,
+        stationary: RoundPosition<S>,
+        movement: Vector2<S>,
+        collisions: &mut Collisions<S>,
+    ) {
+        self.collide
+            .for_each_left_solid_edge_facing(movement, |moving_rel_edge| {
+                let channels = stationary.edge_properties.channels;
+                if moving_rel_edge.properties.channels & channels == 0 {
+                    return;
+                }
+                let moving_layer = self.edge_layer(&moving_rel_edge);
+                if collisions.collision_matrix.ignores(moving_layer, stationary.layer) {
+                    return;
+                }
+                let moving_edge = moving_rel_edge
+                    .left_solid_edge
+                    .add_vector(self.position);
+                if let Some(left_solid_edge_collision) = stationary
+                    .rounded_segment
+                    .collide_with_stationary_edge(
+                        &moving_edge,
+                        -movement,
+                        collisions.tolerances,
+                    ) {
+                    let contact = Contact::new(
+                        &left_solid_edge_collision,
+                        movement,
+                        RoundedSegment::from_left_solid_edge(&moving_edge),
+                        stationary.rounded_segment,
+                        collisions.tolerances,
+                    );
+                    let collision = Collision {
+                        contact,
+                        moving_edge_vector: moving_rel_edge.edge_vector(),
+                        stationary_edge_vector: EdgeVector::round(
+                            &left_solid_edge_collision,
+                            stationary.edge_properties,
+                        ),
+                        left_solid_edge_collision,
+                        moving_entity_id: self.entity_id,
+                        stationary_entity_id: stationary.entity_id,
+                        moving_layer,
+                        stationary_layer: stationary.layer,
+                        moving_material: self.edge_material(&moving_rel_edge),
+                        stationary_material: stationary.material,
+                        moving_child: self.child,
+                        stationary_child: stationary.child,
+                    };
+                    collisions.insert(collision);
                 }
             });
-        });
     }
 }
 
-pub trait Collide {
-    fn aabb(&self, top_left: Vector2<f64>) -> Aabb;
-    fn for_each_left_solid_edge_facing<F: FnMut(Edge)>(
+pub struct RoundPosition<S> {
+    pub rounded_segment: RoundedSegment<S>,
+    pub edge_properties: EdgeProperties,
+    pub entity_id: EntityId,
+    pub layer: Layer,
+    pub material: MaterialId,
+    pub child: Option<usize>,
+}
+
+impl<S: Scalar> RoundPosition<S> {
+    pub fn movement_collision_test<Stationary>(
+        &self,
+        stationary: CollidePosition<S, Stationary>,
+        movement: Vector2<S>,
+        collisions: &mut Collisions<S>,
+    ) where
+        Stationary: Collide<S>,
+    {
+        let moving_aabb = self.rounded_segment.aabb();
+        let rel_movement_aabb = moving_aabb
+            .union(&moving_aabb.add_vector(movement))
+            .add_vector(-stationary.position);
+        stationary.collide.for_each_left_solid_edge_facing_in(
+            rel_movement_aabb,
+            -movement,
+            |stationary_rel_edge| {
+                if stationary_rel_edge.properties.channels & self.edge_properties.channels
+                    == 0
+                {
+                    return;
+                }
+                let stationary_layer = stationary.edge_layer(&stationary_rel_edge);
+                if collisions.collision_matrix.ignores(self.layer, stationary_layer) {
+                    return;
+                }
+                let stationary_edge = stationary_rel_edge
+                    .left_solid_edge
+                    .add_vector(stationary.position);
+                if let Some(left_solid_edge_collision) = self.rounded_segment
+                    .collide_with_stationary_edge(
+                        &stationary_edge,
+                        movement,
+                        collisions.tolerances,
+                    ) {
+                    let contact = Contact::new(
+                        &left_solid_edge_collision,
+                        movement,
+                        self.rounded_segment,
+                        RoundedSegment::from_left_solid_edge(&stationary_edge),
+                        collisions.tolerances,
+                    );
+                    let collision = Collision {
+                        contact,
+                        moving_edge_vector: EdgeVector::round(
+                            &left_solid_edge_collision,
+                            self.edge_properties,
+                        ),
+                        stationary_edge_vector: stationary_rel_edge.edge_vector(),
+                        left_solid_edge_collision,
+                        moving_entity_id: self.entity_id,
+                        stationary_entity_id: stationary.entity_id,
+                        moving_layer: self.layer,
+                        stationary_layer,
+                        moving_material: self.material,
+                        stationary_material: stationary
+                            .edge_material(&stationary_rel_edge),
+                        moving_child: self.child,
+                        stationary_child: stationary.child,
+                    };
+                    collisions.insert(collision);
+                }
+            },
+        );
+    }
+
+    pub fn movement_collision_test_round(
+        &self,
+        stationary: RoundPosition<S>,
+        movement: Vector2<S>,
+        collisions: &mut Collisions<S>,
+    ) {
+        if self.edge_properties.channels & stationary.edge_properties.channels == 0 {
+            return;
+        }
+        if collisions.collision_matrix.ignores(self.layer, stationary.layer) {
+            return;
+        }
+        if let Some(left_solid_edge_collision) = self.rounded_segment
+            .collide_with_stationary_rounded_segment(
+                &stationary.rounded_segment,
+                movement,
+                collisions.tolerances,
+            ) {
+            let contact = Contact::new(
+                &left_solid_edge_collision,
+                movement,
+                self.rounded_segment,
+                stationary.rounded_segment,
+                collisions.tolerances,
+            );
+            let collision = Collision {
+                contact,
+                moving_edge_vector: EdgeVector::round(
+                    &left_solid_edge_collision,
+                    self.edge_properties,
+                ),
+                stationary_edge_vector: EdgeVector::round(
+                    &left_solid_edge_collision,
+                    stationary.edge_properties,
+                ),
+                left_solid_edge_collision,
+                moving_entity_id: self.entity_id,
+                stationary_entity_id: stationary.entity_id,
+                moving_layer: self.layer,
+                stationary_layer: stationary.layer,
+                moving_material: self.material,
+                stationary_material: stationary.material,
+                moving_child: self.child,
+                stationary_child: stationary.child,
+            };
+            collisions.insert(collision);
+        }
+    }
+}
+
+fn insert_closest_collision<S: Scalar>(
+    closest_collisions: &mut BestMultiSet<Collision<S>>,
+    collision: Collision<S>,
+    simultaneous_time: S,
+) {
+    closest_collisions.insert_lt_by(collision, |a, b| {
+        let delta = a.left_solid_edge_collision.movement_multiplier()
+            - b.left_solid_edge_collision.movement_multiplier();
+        if delta.abs() < simultaneous_time {
+            Ordering::Equal
+        } else if delta > real(0.) {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        }
+    });
+}
+
+pub trait Collide<S: Scalar> {
+    fn aabb(&self, top_left: Vector2<S>) -> Aabb<S>;
+    fn for_each_left_solid_edge_facing<F: FnMut(Edge<S>)>(
         &self,
-        direction: Vector2<f64>,
+        direction: Vector2<S>,
         f: F,
     );
+    // Like for_each_left_solid_edge_facing, but edges outside the aabb
+    // (relative to the top left of the shape) may be skipped. Shapes with many
+    // edges override this to avoid visiting all of them.
+    fn for_each_left_solid_edge_facing_in<F: FnMut(Edge<S>)>(
+        &self,
+        _aabb: Aabb<S>,
+        direction: Vector2<S>,
+        f: F,
+    ) {
+        self.for_each_left_solid_edge_facing(direction, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axis_aligned_rect::{AxisAlignedRect, RectEdgeProperties};
+    use convex_polygon::ConvexPolygon;
+    use layer;
+    use material;
+
+    // a single left-solid edge
+    struct Wall(Edge<f64>);
+
+    impl Collide<f64> for Wall {
+        fn aabb(&self, top_left: Vector2<f64>) -> Aabb<f64> {
+            Aabb::from_points(&[self.0.start(), self.0.end()]).add_vector(top_left)
+        }
+        fn for_each_left_solid_edge_facing<F: FnMut(Edge<f64>)>(
+            &self,
+            _direction: Vector2<f64>,
+            mut f: F,
+        ) {
+            f(self.0)
+        }
+    }
+
+    fn collide_position<C: Collide<f64>>(
+        collide: &C,
+        position: Vector2<f64>,
+        entity_id: EntityId,
+    ) -> CollidePosition<'_, f64, C> {
+        CollidePosition {
+            collide,
+            position,
+            entity_id,
+            layer: layer::DEFAULT,
+            material: material::DEFAULT,
+            child: None,
+        }
+    }
+
+    // the closest collisions when moving from the origin
+    fn closest_collisions<M: Collide<f64>, C: Collide<f64>>(
+        moving: &M,
+        stationary: &C,
+        stationary_position: Vector2<f64>,
+        movement: Vector2<f64>,
+    ) -> Vec<Collision<f64>> {
+        let collision_matrix = CollisionMatrix::default();
+        let tolerances = Tolerances::default();
+        let mut closest = BestMultiSet::new();
+        let mut reported = Vec::new();
+        collide_position(moving, vec2(0., 0.), 0).movement_collision_test(
+            collide_position(stationary, stationary_position, 1),
+            movement,
+            &mut Collisions {
+                collision_matrix: &collision_matrix,
+                tolerances: &tolerances,
+                closest: &mut closest,
+                reported: &mut reported,
+            },
+        );
+        closest.drain().collect()
+    }
+
+    #[test]
+    fn edge_flags_and_user_data_reach_collisions() {
+        let character = AxisAlignedRect::new_character(vec2(32., 64.));
+        let wall = Wall(
+            Edge::new(vec2(0., 100.), vec2(0., -100.))
+                .with_properties(EdgeProperties::default().with_user_data(42))
+                .with_flags(flags::CORNER_END),
+        );
+        let movement = vec2(40., 0.);
+        let collisions = closest_collisions(&character, &wall, vec2(50., 0.), movement);
+        assert!(!collisions.is_empty());
+        for collision in collisions {
+            let properties = collision.stationary_edge_vector.properties;
+            assert_eq!(properties.user_data, 42);
+            assert_eq!(properties.flags, flags::CORNER_END);
+            // the character's right edge
+            assert_eq!(collision.moving_edge_vector.properties.flags, flags::BUMP_END);
+        }
+        let properties =
+            RectEdgeProperties::all(EdgeProperties::default().with_user_data(7));
+        let rect = AxisAlignedRect::new_with_edge_properties(vec2(10., 10.), properties);
+        assert_eq!(rect.edge_properties().left.user_data, 7);
+        let collisions = closest_collisions(&character, &rect, vec2(50., 0.), movement);
+        assert!(!collisions.is_empty());
+        for collision in collisions {
+            assert_eq!(collision.stationary_edge_vector.properties.user_data, 7);
+        }
+    }
+
+    fn assert_near(actual: Vector2<f64>, expected: Vector2<f64>) {
+        assert!(
+            (actual - expected).magnitude() < 0.000001,
+            "{:?} is not near {:?}",
+            actual,
+            expected
+        );
+    }
+
+    // the unordered ends of a two point contact
+    fn assert_two_points(points: ContactPoints<f64>, a: Vector2<f64>, b: Vector2<f64>) {
+        match points {
+            ContactPoints::Two(start, end) => {
+                if (start - a).magnitude() < (start - b).magnitude() {
+                    assert_near(start, a);
+                    assert_near(end, b);
+                } else {
+                    assert_near(start, b);
+                    assert_near(end, a);
+                }
+            }
+            ContactPoints::One(point) => panic!("One point contact at {:?}", point),
+        }
+    }
+
+    #[test]
+    fn vertex_on_face_contacts_at_one_point() {
+        let diamond = ConvexPolygon::new(vec![
+            vec2(0., 5.),
+            vec2(5., 0.),
+            vec2(10., 5.),
+            vec2(5., 10.),
+        ])
+        .expect("Invalid polygon");
+        let wall = Wall(Edge::new(vec2(0., 100.), vec2(0., -100.)));
+        let movement = vec2(40., 0.);
+        let collisions = closest_collisions(&diamond, &wall, vec2(30., 0.), movement);
+        assert!(!collisions.is_empty());
+        for collision in collisions {
+            let contact = collision.contact;
+            assert!((contact.time - 0.5).abs() < 0.000001);
+            assert_near(contact.normal, vec2(-1., 0.));
+            assert_eq!(contact.points, ContactPoints::One(vec2(30., 5.)));
+        }
+    }
+
+    #[test]
+    fn face_on_face_contacts_at_the_ends_of_the_overlap() {
+        let square = AxisAlignedRect::new(vec2(10., 10.));
+        // moving right into a face, then falling onto one
+        for &(movement, position, normal, a, b) in [
+            (vec2(40., 0.), vec2(30., 2.), vec2(-1., 0.), vec2(30., 2.), vec2(30., 10.)),
+            (vec2(0., 40.), vec2(-4., 30.), vec2(0., -1.), vec2(0., 30.), vec2(6., 30.)),
+        ]
+        .iter()
+        {
+            let collisions = closest_collisions(&square, &square, position, movement);
+            assert!(!collisions.is_empty());
+            let mut num_flush = 0;
+            for collision in collisions {
+                let contact = collision.contact;
+                assert!((contact.time - 0.5).abs() < 0.000001);
+                assert_near(contact.normal, normal);
+                if let ContactPoints::Two(..) = contact.points {
+                    assert_two_points(contact.points, a, b);
+                    num_flush += 1;
+                }
+            }
+            assert!(num_flush > 0);
+        }
+    }
 }
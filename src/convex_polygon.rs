@@ -0,0 +1,163 @@
+use aabb::Aabb;
+use cgmath::{InnerSpace, Vector2};
+use collide::{Collide, Edge};
+use left_solid_edge::vector2_cross_product;
+use scalar::{real, Scalar};
+use std::f64::consts::PI;
+
+// relative to the size of the polygon, so validation doesn't depend on scale
+const EPSILON: f64 = 0.000001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvexPolygonError {
+    TooFewVertices,
+    Degenerate,
+    NotConvex,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConvexPolygon<S> {
+    vertices: Vec<Vector2<S>>,
+}
+
+impl<S: Scalar> ConvexPolygon<S> {
+    pub fn new(mut vertices: Vec<Vector2<S>>) -> Result<Self, ConvexPolygonError> {
+        if vertices.len() < 3 {
+            return Err(ConvexPolygonError::TooFewVertices);
+        }
+        let num_vertices = vertices.len();
+        let size2 = Aabb::from_points(&vertices).size().magnitude2();
+        let mut num_clockwise = 0;
+        let mut total_turn = S::zero();
+        for i in 0..num_vertices {
+            let a = vertices[i];
+            let b = vertices[(i + 1) % num_vertices];
+            let c = vertices[(i + 2) % num_vertices];
+            let ab = b - a;
+            let bc = c - b;
+            let min_length2 = size2 * real(EPSILON);
+            if ab.magnitude2() < min_length2 || bc.magnitude2() < min_length2 {
+                return Err(ConvexPolygonError::Degenerate);
+            }
+            // the cross product is the sine of the turn scaled by both lengths
+            let cross = vector2_cross_product(ab, bc);
+            if cross.abs() < ab.magnitude() * bc.magnitude() * real(EPSILON) {
+                return Err(ConvexPolygonError::Degenerate);
+            }
+            if cross > real(0.) {
+                num_clockwise += 1;
+            }
+            total_turn += cross.atan2(ab.dot(bc));
+        }
+        if num_clockwise != 0 && num_clockwise != num_vertices {
+            return Err(ConvexPolygonError::NotConvex);
+        }
+        // a self-intersecting polygon such as a star can turn consistently
+        // in one direction but wraps around more than once
+        if (total_turn.abs() - real(2. * PI)).abs() > real(EPSILON) {
+            return Err(ConvexPolygonError::NotConvex);
+        }
+        // edges are left-solid, so vertices must wind clockwise on screen
+        if num_clockwise == 0 {
+            vertices.reverse();
+        }
+        Ok(Self { vertices })
+    }
+    pub fn vertices(&self) -> &[Vector2<S>] {
+        &self.vertices
+    }
+    fn edge(&self, index: usize) -> Edge<S> {
+        let start = self.vertices[index];
+        let end = self.vertices[(index + 1) % self.vertices.len()];
+        Edge::new(start, end)
+    }
+}
+
+impl<S: Scalar> Collide<S> for ConvexPolygon<S> {
+    fn aabb(&self, top_left: Vector2<S>) -> Aabb<S> {
+        Aabb::from_points(&self.vertices).add_vector(top_left)
+    }
+    fn for_each_left_solid_edge_facing<F: FnMut(Edge<S>)>(
+        &self,
+        direction: Vector2<S>,
+        mut f: F,
+    ) {
+        for i in 0..self.vertices.len() {
+            let edge = self.edge(i);
+            if edge.is_facing(direction) {
+                f(edge);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+
+    fn polygon(
+        vertices: &[(f64, f64)],
+    ) -> Result<ConvexPolygon<f64>, ConvexPolygonError> {
+        ConvexPolygon::new(vertices.iter().map(|&(x, y)| vec2(x, y)).collect())
+    }
+
+    #[test]
+    fn validation_is_independent_of_scale() {
+        for &scale in &[0.0001, 1., 10000.] {
+            let triangle = [(0., 0.), (scale, 0.), (0., scale)];
+            assert!(polygon(&triangle).is_ok());
+            let sliver = [(0., 0.), (scale, 0.), (scale * 2., scale * 0.0000001)];
+            assert_eq!(polygon(&sliver).unwrap_err(), ConvexPolygonError::Degenerate);
+        }
+    }
+
+    #[test]
+    fn too_few_vertices() {
+        assert_eq!(polygon(&[]).unwrap_err(), ConvexPolygonError::TooFewVertices);
+        let line = [(0., 0.), (10., 0.)];
+        assert_eq!(polygon(&line).unwrap_err(), ConvexPolygonError::TooFewVertices);
+    }
+
+    #[test]
+    fn degenerate() {
+        let collinear = [(0., 0.), (5., 5.), (10., 10.)];
+        assert_eq!(polygon(&collinear).unwrap_err(), ConvexPolygonError::Degenerate);
+        let repeated = [(0., 0.), (10., 0.), (10., 0.), (0., 10.)];
+        assert_eq!(polygon(&repeated).unwrap_err(), ConvexPolygonError::Degenerate);
+        let straight_angle = [(0., 0.), (5., 0.), (10., 0.), (10., 10.)];
+        let error = polygon(&straight_angle).unwrap_err();
+        assert_eq!(error, ConvexPolygonError::Degenerate);
+    }
+
+    #[test]
+    fn not_convex() {
+        let arrow = [(0., 0.), (10., 5.), (0., 10.), (3., 5.)];
+        assert_eq!(polygon(&arrow).unwrap_err(), ConvexPolygonError::NotConvex);
+        // every other point of a pentagon turns the same way at each corner,
+        // but winds around twice
+        let star = (0..5)
+            .map(|i| {
+                let angle = (i * 2) as f64 * 2. * PI / 5.;
+                (angle.cos() * 10., angle.sin() * 10.)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(polygon(&star).unwrap_err(), ConvexPolygonError::NotConvex);
+    }
+
+    #[test]
+    fn winding_is_normalised() {
+        // clockwise on screen, where y points down, which is the winding that
+        // puts the solid side of each edge inside the polygon
+        let clockwise = [(0., 0.), (10., 0.), (10., 10.), (0., 10.)];
+        let vertices = clockwise.iter().map(|&(x, y)| vec2(x, y)).collect::<Vec<_>>();
+        assert_eq!(polygon(&clockwise).unwrap().vertices(), &vertices[..]);
+        // anticlockwise on screen, so clockwise with y pointing up
+        let anticlockwise = [(0., 10.), (10., 10.), (10., 0.), (0., 0.)];
+        let reversed = anticlockwise.iter().rev().map(|&(x, y)| vec2(x, y));
+        assert_eq!(
+            polygon(&anticlockwise).unwrap().vertices(),
+            &reversed.collect::<Vec<_>>()[..]
+        );
+    }
+}
@@ -1,65 +1,100 @@
 use aabb::Aabb;
-use axis_aligned_rect::AxisAlignedRect;
+use axis_aligned_rect::{AxisAlignedRect, RectEdgeProperties};
+use broadphase::{AnyBroadphase, Broadphase, BroadphaseHandle};
+use capsule::Capsule;
+use chain::Chain;
 use cgmath::{vec2, ElementWise, InnerSpace, Vector2};
+use circle::Circle;
+use collide::{channels, ContactPoints, EdgeProperties};
+use convex_polygon::ConvexPolygon;
 use fnv::{FnvHashMap, FnvHashSet};
+use layer::{self, Layer, LayerMask, Response};
 use line_segment::LineSegment;
-use loose_quad_tree::LooseQuadTree;
-use movement::{Displacement, EntityId, ForEachShapePosition, MovementContext};
+use material::{self, MaterialId};
+use movement::{Displacement, EntityId, ForEachShapePosition, MovementContext,
+               MovementProperties, ResponseMode};
+use oriented_rect::OrientedRect;
+use overlap;
+use query::{self, ShapeCast};
+use raycast::RaycastHit;
+use scalar::{real, Scalar};
 use shape::{Shape, ShapePosition};
 use std::collections::HashMap;
+use tile_map::TileMap;
+use tolerances::Tolerances;
 
-fn clamp(value: f64, min: f64, max: f64) -> f64 {
+const SENSOR_INACTIVE_COLOUR: [f32; 3] = [0.3, 0.3, 0.3];
+const SENSOR_ACTIVE_COLOUR: [f32; 3] = [0.3, 0.8, 0.3];
+// cos(50 degrees)
+const PLAYER_MIN_WALKABLE_COS: f64 = 0.6427876096865394;
+const PLAYER_STEP_HEIGHT: f64 = 12.;
+const PLAYER_CEILING_TOLERANCE: f64 = 6.;
+// combined ground friction at and above which the player has full control
+const PLAYER_FULL_TRACTION_FRICTION: f64 = 0.1;
+const BALL_GRAVITY: f64 = 0.5;
+const QUERY_COLOUR: [f32; 3] = [0.5, 0.5, 0.5];
+const QUERY_MARK_RADIUS: f64 = 3.;
+const QUERY_MARK_NORMAL_LENGTH: f64 = 12.;
+const SIGHT_DISTANCE: f64 = 240.;
+const GROUND_PROBE_DISTANCE: f64 = 400.;
+const GROUND_PROBE_MARK_HALF_WIDTH: f64 = 8.;
+const PICKUP_REACH: f64 = 64.;
+const PICKUP_MARK_RADIUS: f64 = 10.;
+const BALL_PREVIEW_FRAMES: f64 = 20.;
+const NEARBY_DISTANCE: f64 = 100.;
+
+fn clamp<S: Scalar>(value: S, min: S, max: S) -> S {
     value.max(min).min(max)
 }
 
 #[derive(Debug)]
-pub struct InputModel {
-    left: f64,
-    right: f64,
-    up: f64,
-    down: f64,
+pub struct InputModel<S> {
+    left: S,
+    right: S,
+    up: S,
+    down: S,
     jump_current: bool,
     jump_count: Option<u64>,
 }
 
-impl Default for InputModel {
+impl<S: Scalar> Default for InputModel<S> {
     fn default() -> Self {
         Self {
-            left: 0.,
-            right: 0.,
-            up: 0.,
-            down: 0.,
+            left: real(0.),
+            right: real(0.),
+            up: real(0.),
+            down: real(0.),
             jump_current: false,
             jump_count: None,
         }
     }
 }
 
-impl InputModel {
+impl<S: Scalar> InputModel<S> {
     pub fn set_jump(&mut self, jump: bool) {
         self.jump_current = jump;
     }
-    pub fn set_left(&mut self, value: f64) {
-        self.left = clamp(value, 0., 1.);
+    pub fn set_left(&mut self, value: S) {
+        self.left = clamp(value, real(0.), real(1.));
     }
-    pub fn set_right(&mut self, value: f64) {
-        self.right = clamp(value, 0., 1.);
+    pub fn set_right(&mut self, value: S) {
+        self.right = clamp(value, real(0.), real(1.));
     }
-    pub fn set_up(&mut self, value: f64) {
-        self.up = clamp(value, 0., 1.);
+    pub fn set_up(&mut self, value: S) {
+        self.up = clamp(value, real(0.), real(1.));
     }
-    pub fn set_down(&mut self, value: f64) {
-        self.down = clamp(value, 0., 1.);
+    pub fn set_down(&mut self, value: S) {
+        self.down = clamp(value, real(0.), real(1.));
     }
-    fn horizontal(&self) -> f64 {
+    fn horizontal(&self) -> S {
         self.right - self.left
     }
-    fn vertical(&self) -> f64 {
+    fn vertical(&self) -> S {
         self.down - self.up
     }
-    fn movement(&self) -> Vector2<f64> {
+    fn movement(&self) -> Vector2<S> {
         let raw = vec2(self.horizontal(), self.vertical());
-        if raw.magnitude2() > 1. {
+        if raw.magnitude2() > real(1.) {
             raw.normalize()
         } else {
             raw
@@ -77,61 +112,79 @@ impl InputModel {
     }
 }
 
-pub struct RenderUpdate<'a> {
-    pub position: Vector2<f64>,
-    pub shape: &'a Shape,
+pub struct RenderUpdate<'a, S> {
+    pub position: Vector2<S>,
+    pub shape: &'a Shape<S>,
     pub colour: [f32; 3],
 }
 
-fn jump_frame_count_to_velocity(count: u64) -> Option<f64> {
+fn jump_frame_count_to_velocity<S: Scalar>(count: u64) -> Option<S> {
     const MAX_COUNT: u64 = 6;
     const MULTIPLIER: f64 = 0.4;
     if count >= MAX_COUNT {
         None
     } else {
-        Some(((MAX_COUNT - count) as f64) * MULTIPLIER)
+        Some(real((MAX_COUNT - count) as f64 * MULTIPLIER))
     }
 }
 
-fn update_player_velocity(
-    current_velocity: Vector2<f64>,
-    input_model: &InputModel,
-    max_platform_velocity: Option<Vector2<f64>>,
+fn update_player_velocity<S: Scalar>(
+    current_velocity: Vector2<S>,
+    input_model: &InputModel<S>,
+    max_platform_velocity: Option<Vector2<S>>,
+    traction: S,
+    ground_normal: Option<Vector2<S>>,
     jump: &JumpStateMachine,
-) -> Vector2<f64> {
-    const MULTIPLIER: Vector2<f64> = Vector2 { x: 4., y: 0.5 };
-    const GRAVITY: Vector2<f64> = Vector2 { x: 0., y: 0.5 };
+) -> Vector2<S> {
+    const LATERAL_MULTIPLIER: f64 = 4.;
+    const VERTICAL_MULTIPLIER: f64 = 0.5;
+    const GRAVITY: f64 = 0.5;
     const MAX_LATERAL: f64 = 10.;
-    const DECAY: Vector2<f64> = Vector2 { x: 0.0, y: 1. };
+
+    // slippery ground keeps lateral velocity and gives less control
+    let decay = vec2(S::one() - traction, S::one());
+    let multiplier = vec2(
+        real::<S>(LATERAL_MULTIPLIER) * traction,
+        real(VERTICAL_MULTIPLIER),
+    );
 
     let (current_velocity_relative, platform_velocity) =
         if let Some(max_platform_velocity) = max_platform_velocity {
             let current_velocity_relative =
-                (current_velocity - max_platform_velocity).mul_element_wise(DECAY);
+                (current_velocity - max_platform_velocity).mul_element_wise(decay);
             (current_velocity_relative, max_platform_velocity)
         } else {
-            (current_velocity.mul_element_wise(DECAY), vec2(0., 0.))
+            (current_velocity.mul_element_wise(decay), vec2(real(0.), real(0.)))
         };
 
-    let input_movement = input_model.movement().mul_element_wise(MULTIPLIER);
+    let input_movement = input_model.movement().mul_element_wise(multiplier);
 
     let horizontal_velocity_relative = clamp(
         current_velocity_relative.x + input_movement.x,
-        -MAX_LATERAL,
-        MAX_LATERAL,
+        -real::<S>(MAX_LATERAL),
+        real(MAX_LATERAL),
     );
 
-    let vertical_delta = match jump {
-        JumpStateMachine::NotJumping => GRAVITY,
-        JumpStateMachine::JumpingForFrames(n) => match jump_frame_count_to_velocity(*n) {
-            Some(y) => vec2(0., -y),
-            None => GRAVITY,
-        },
+    // On walkable ground gravity presses into the surface (-normal * GRAVITY)
+    // so slopes don't cause sliding. Steep slopes aren't walkable, so there
+    // gravity stays straight down and the player slides off them.
+    let gravity = match ground_normal {
+        Some(normal) => -normal * real(GRAVITY),
+        None => vec2(real(0.), real(GRAVITY)),
     };
-    let vertical_velocity_relative = current_velocity_relative.y + vertical_delta.y;
+    let fall_delta = match jump {
+        JumpStateMachine::NotJumping => gravity,
+        JumpStateMachine::JumpingForFrames(n) => {
+            match jump_frame_count_to_velocity::<S>(*n) {
+                Some(y) => vec2(real(0.), -y),
+                None => gravity,
+            }
+        }
+    };
+    let vertical_velocity_relative = current_velocity_relative.y + fall_delta.y;
 
     let velocity_relative = vec2(
-        horizontal_velocity_relative,
+        horizontal_velocity_relative + fall_delta.x,
         vertical_velocity_relative,
     );
 
@@ -155,30 +208,50 @@ impl EntityIdAllocator {
 }
 
 #[derive(Debug)]
-struct EntityCommon {
-    position: Vector2<f64>,
-    shape: Shape,
+struct EntityCommon<S> {
+    position: Vector2<S>,
+    shape: Shape<S>,
     colour: [f32; 3],
+    layer: Layer,
+    material: MaterialId,
 }
 
-impl EntityCommon {
-    fn new(position: Vector2<f64>, shape: Shape, colour: [f32; 3]) -> Self {
+impl<S: Scalar> EntityCommon<S> {
+    fn new(position: Vector2<S>, shape: Shape<S>, colour: [f32; 3]) -> Self {
         Self {
             position,
             shape,
             colour,
+            layer: layer::DEFAULT,
+            material: material::DEFAULT,
         }
     }
-    fn aabb(&self) -> Aabb {
+    fn with_layer(self, layer: Layer) -> Self {
+        Self { layer, ..self }
+    }
+    fn with_material(self, material: MaterialId) -> Self {
+        Self { material, ..self }
+    }
+    fn aabb(&self) -> Aabb<S> {
         self.shape.aabb(self.position)
     }
 }
 
 #[derive(Default)]
-pub struct GameStateChanges {
-    position: Vec<(EntityId, Vector2<f64>)>,
-    velocity: HashMap<EntityId, Vector2<f64>>,
-    displacements: Vec<(EntityId, Displacement)>,
+pub struct GameStateChanges<S> {
+    position: Vec<(EntityId, Vector2<S>)>,
+    velocity: HashMap<EntityId, Vector2<S>>,
+    displacements: Vec<(EntityId, Displacement<S>)>,
+    reported: Vec<(EntityId, EntityId)>,
+    step_ups: Vec<(EntityId, S)>,
+}
+
+// events for pairs of a sensor and a dynamic physics body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapEvent {
+    Enter,
+    Stay,
+    Exit,
 }
 
 enum JumpStateMachine {
@@ -187,7 +260,7 @@ enum JumpStateMachine {
 }
 
 impl JumpStateMachine {
-    pub fn step(&mut self, can_jump: bool, input: &InputModel) {
+    pub fn step<S: Scalar>(&mut self, can_jump: bool, input: &InputModel<S>) {
         if let Some(jump_count) = input.jump_count {
             if jump_count == 0 {
                 if can_jump {
@@ -207,30 +280,72 @@ impl JumpStateMachine {
     }
 }
 
-pub struct GameState {
+pub struct GameState<S> {
     player_id: Option<EntityId>,
     moving_platform_ids: Vec<EntityId>,
+    ball_ids: Vec<EntityId>,
+    pickup_ids: FnvHashSet<EntityId>,
     entity_id_allocator: EntityIdAllocator,
-    common: FnvHashMap<EntityId, EntityCommon>,
-    velocity: FnvHashMap<EntityId, Vector2<f64>>,
+    common: FnvHashMap<EntityId, EntityCommon<S>>,
+    velocity: FnvHashMap<EntityId, Vector2<S>>,
+    movement_properties: FnvHashMap<EntityId, MovementProperties<S>>,
     dynamic_physics: FnvHashSet<EntityId>,
     static_physics: FnvHashSet<EntityId>,
-    quad_tree: LooseQuadTree<EntityId>,
+    sensors: FnvHashSet<EntityId>,
+    overlaps: FnvHashSet<(EntityId, EntityId)>,
+    overlap_events: FnvHashMap<(EntityId, EntityId), OverlapEvent>,
+    step_ups: FnvHashMap<EntityId, S>,
+    broadphase: AnyBroadphase<EntityId, S>,
+    broadphase_handles: FnvHashMap<EntityId, BroadphaseHandle>,
     jump: FnvHashMap<EntityId, JumpStateMachine>,
+    // the horizontal direction the player last moved in
+    player_facing: S,
+    // shapes showing the results of the demo's world queries
+    query_shapes: Vec<(Vector2<S>, Shape<S>)>,
+    // what the ball's shape cast will hit
+    ball_target: Option<EntityId>,
     frame_count: u64,
 }
 
-struct AllShapePositions<'a>(&'a GameState);
-struct DynamicPhysicsShapePositions<'a>(&'a GameState);
+struct AllShapePositions<'a, S>(&'a GameState<S>);
+struct DynamicPhysicsShapePositions<'a, S>(&'a GameState<S>);
 
-impl<'a> ForEachShapePosition for AllShapePositions<'a> {
-    fn for_each<F: FnMut(ShapePosition)>(&self, aabb: Aabb, mut f: F) {
+impl<'a, S: Scalar> ForEachShapePosition<S> for AllShapePositions<'a, S> {
+    fn for_each<F: FnMut(ShapePosition<S>)>(&self, aabb: Aabb<S>, mut f: F) {
         self.0
-            .quad_tree
+            .broadphase
             .for_each_intersection(aabb, |_aabb, &entity_id| {
+                if self.0.sensors.contains(&entity_id) {
+                    return;
+                }
+                let common = self.0.common.get(&entity_id).unwrap();
+                let shape_position = ShapePosition {
+                    entity_id,
+                    layer: common.layer,
+                    material: common.material,
+                    shape: &common.shape,
+                    position: common.position,
+                };
+                f(shape_position);
+            });
+    }
+    fn for_each_along_segment<F: FnMut(ShapePosition<S>)>(
+        &self,
+        start: Vector2<S>,
+        end: Vector2<S>,
+        mut f: F,
+    ) {
+        self.0
+            .broadphase
+            .for_each_segment_intersection(start, end, |_aabb, &entity_id| {
+                if self.0.sensors.contains(&entity_id) {
+                    return;
+                }
                 let common = self.0.common.get(&entity_id).unwrap();
                 let shape_position = ShapePosition {
                     entity_id,
+                    layer: common.layer,
+                    material: common.material,
                     shape: &common.shape,
                     position: common.position,
                 };
@@ -239,15 +354,17 @@ impl<'a> ForEachShapePosition for AllShapePositions<'a> {
     }
 }
 
-impl<'a> ForEachShapePosition for DynamicPhysicsShapePositions<'a> {
-    fn for_each<F: FnMut(ShapePosition)>(&self, aabb: Aabb, mut f: F) {
+impl<'a, S: Scalar> ForEachShapePosition<S> for DynamicPhysicsShapePositions<'a, S> {
+    fn for_each<F: FnMut(ShapePosition<S>)>(&self, aabb: Aabb<S>, mut f: F) {
         self.0
-            .quad_tree
+            .broadphase
             .for_each_intersection(aabb, |_aabb, &entity_id| {
                 if self.0.dynamic_physics.contains(&entity_id) {
                     let common = self.0.common.get(&entity_id).unwrap();
                     let shape_position = ShapePosition {
                         entity_id,
+                        layer: common.layer,
+                        material: common.material,
                         shape: &common.shape,
                         position: common.position,
                     };
@@ -257,216 +374,519 @@ impl<'a> ForEachShapePosition for DynamicPhysicsShapePositions<'a> {
     }
 }
 
-impl GameState {
-    pub fn new(size_hint: Vector2<f64>) -> Self {
+impl<S: Scalar> GameState<S> {
+    pub fn new() -> Self {
         Self {
             player_id: None,
             moving_platform_ids: Vec::new(),
+            ball_ids: Vec::new(),
+            pickup_ids: Default::default(),
             entity_id_allocator: Default::default(),
             common: Default::default(),
             velocity: Default::default(),
+            movement_properties: Default::default(),
             dynamic_physics: Default::default(),
             static_physics: Default::default(),
-            quad_tree: LooseQuadTree::new(size_hint),
+            sensors: Default::default(),
+            overlaps: Default::default(),
+            overlap_events: Default::default(),
+            step_ups: Default::default(),
+            broadphase: Default::default(),
+            broadphase_handles: Default::default(),
             jump: Default::default(),
+            player_facing: real(1.),
+            query_shapes: Vec::new(),
+            ball_target: None,
             frame_count: 0,
         }
     }
+    // moves any existing entities into the new broadphase
+    pub fn set_broadphase(&mut self, broadphase: AnyBroadphase<EntityId, S>) {
+        self.broadphase = broadphase;
+        self.broadphase.clear();
+        self.broadphase_handles.clear();
+        for (id, common) in self.common.iter() {
+            let handle = self.broadphase.insert(common.aabb(), *id);
+            self.broadphase_handles.insert(*id, handle);
+        }
+    }
     fn clear(&mut self) {
         self.player_id = None;
+        self.ball_ids.clear();
+        self.pickup_ids.clear();
         self.entity_id_allocator.reset();
         self.common.clear();
         self.velocity.clear();
+        self.movement_properties.clear();
         self.dynamic_physics.clear();
         self.static_physics.clear();
-        self.quad_tree.clear();
+        self.sensors.clear();
+        self.overlaps.clear();
+        self.overlap_events.clear();
+        self.step_ups.clear();
+        self.broadphase.clear();
+        self.broadphase_handles.clear();
         self.jump.clear();
+        self.player_facing = real(1.);
+        self.query_shapes.clear();
+        self.ball_target = None;
         self.frame_count = 0;
     }
-    fn add_static_solid(&mut self, common: EntityCommon) -> EntityId {
-        let id = self.entity_id_allocator.allocate();
-        self.quad_tree.insert(common.aabb(), id);
-        self.common.insert(id, common);
+    fn add_static_solid(&mut self, common: EntityCommon<S>) -> EntityId {
+        self.add_common(common)
+    }
+    fn add_sensor(&mut self, common: EntityCommon<S>) -> EntityId {
+        let id = self.add_static_solid(common);
+        self.sensors.insert(id);
         id
     }
-    fn add_common(&mut self, common: EntityCommon) -> EntityId {
+    fn add_common(&mut self, common: EntityCommon<S>) -> EntityId {
         let id = self.entity_id_allocator.allocate();
+        let handle = self.broadphase.insert(common.aabb(), id);
+        self.broadphase_handles.insert(id, handle);
         self.common.insert(id, common);
         id
     }
-    pub fn init_demo(&mut self) {
+    fn remove_common(&mut self, id: EntityId) {
+        self.common.remove(&id);
+        if let Some(handle) = self.broadphase_handles.remove(&id) {
+            self.broadphase.remove(handle);
+        }
+    }
+    fn set_position(&mut self, id: EntityId, position: Vector2<S>) {
+        if let Some(common) = self.common.get_mut(&id) {
+            common.position = position;
+            if let Some(&handle) = self.broadphase_handles.get(&id) {
+                self.broadphase.update(handle, common.aabb());
+            }
+        }
+    }
+    pub fn init_demo(&mut self, movement_context: &mut MovementContext<S>) {
         self.clear();
-        let player_id = self.add_common(EntityCommon::new(
-            vec2(550., 500. - 64.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new_character(vec2(32., 64.))),
-            [1., 0., 0.],
-        ));
+        let (player_layer, pickup_layer, player_pass_layer) = {
+            let collision_matrix = movement_context.collision_matrix_mut();
+            collision_matrix.clear();
+            let player_layer = collision_matrix.add_layer("player");
+            let pickup_layer = collision_matrix.add_layer("pickup");
+            let player_pass_layer = collision_matrix.add_layer("player-pass");
+            collision_matrix.set_response(player_layer, pickup_layer, Response::Report);
+            collision_matrix.set_response(pickup_layer, pickup_layer, Response::Ignore);
+            collision_matrix.set_response(
+                player_layer,
+                player_pass_layer,
+                Response::Ignore,
+            );
+            (player_layer, pickup_layer, player_pass_layer)
+        };
+        let (character_material, ice_material, bouncy_material) = {
+            let materials = movement_context.materials_mut();
+            materials.clear();
+            // surfaces grip the player unless they are made of something slippery
+            materials.add_material("default", real(0.2), real(0.));
+            (
+                materials.add_material("character", real(0.), real(0.)),
+                materials.add_material("ice", real(0.01), real(0.)),
+                materials.add_material("bouncy", real(1.), real(0.8)),
+            )
+        };
+
+        let player_size = vec2(real(32.), real(64.));
+        let player_id = self.add_common(
+            EntityCommon::new(
+                vec2(real(550.), real(500. - 64.)),
+                Shape::AxisAlignedRect(AxisAlignedRect::new_character(player_size)),
+                [1., 0., 0.],
+            ).with_layer(player_layer)
+                .with_material(character_material),
+        );
         self.player_id = Some(player_id);
-        self.velocity.insert(player_id, vec2(0., 0.));
+        self.velocity.insert(player_id, vec2(real(0.), real(0.)));
+        self.movement_properties.insert(
+            player_id,
+            MovementProperties::default()
+                .with_step_height(real(PLAYER_STEP_HEIGHT))
+                .with_ceiling_tolerance(real(PLAYER_CEILING_TOLERANCE)),
+        );
         self.dynamic_physics.insert(player_id);
         self.jump
             .insert(player_id, JumpStateMachine::NotJumping);
         let moving_platform_id = self.add_static_solid(EntityCommon::new(
-            vec2(200., 350.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(128., 32.))),
+            vec2(real(200.), real(350.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(128.), real(32.)))),
             [0., 1., 1.],
         ));
         self.moving_platform_ids.push(moving_platform_id);
-        self.velocity.insert(moving_platform_id, vec2(0., 0.));
+        self.velocity.insert(moving_platform_id, vec2(real(0.), real(0.)));
         self.static_physics.insert(moving_platform_id);
 
         let moving_platform_id = self.add_static_solid(EntityCommon::new(
-            vec2(700., 450.),
+            vec2(real(700.), real(450.)),
             Shape::LineSegment(LineSegment::new_both_solid(
-                vec2(0., 32.),
-                vec2(128., 0.),
+                vec2(real(0.), real(32.)),
+                vec2(real(128.), real(0.)),
             )),
             [0., 1., 1.],
         ));
         self.moving_platform_ids.push(moving_platform_id);
-        self.velocity.insert(moving_platform_id, vec2(0., 0.));
+        self.velocity.insert(moving_platform_id, vec2(real(0.), real(0.)));
         self.static_physics.insert(moving_platform_id);
 
         self.add_static_solid(EntityCommon::new(
-            vec2(700., 200.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(32., 64.))),
+            vec2(real(700.), real(200.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(32.), real(64.)))),
             [1., 1., 0.],
         ));
 
+        self.add_static_solid(
+            EntityCommon::new(
+                vec2(real(50.), real(200.)),
+                Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(400.), real(20.)))),
+                [0.6, 0.8, 1.],
+            ).with_material(ice_material),
+        );
         self.add_static_solid(EntityCommon::new(
-            vec2(50., 200.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(400., 20.))),
-            [1., 1., 0.],
-        ));
-        self.add_static_solid(EntityCommon::new(
-            vec2(150., 250.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new_floor_only(vec2(500., 20.))),
+            vec2(real(150.), real(250.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new_floor_only(vec2(
+                real(500.),
+                real(20.),
+            ))),
             [1., 1., 1.],
         ));
+        self.add_static_solid(
+            EntityCommon::new(
+                vec2(real(50.), real(450.)),
+                Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(100.), real(20.)))),
+                [1., 0., 1.],
+            ).with_material(bouncy_material),
+        );
+        // The player can jump up through this ledge and land on it, and
+        // nothing else can pass through it. Only its top edge overrides the
+        // entity's layer.
+        self.add_static_solid(
+            EntityCommon::new(
+                vec2(real(170.), real(460.)),
+                Shape::AxisAlignedRect(AxisAlignedRect::new_with_edge_properties(
+                    vec2(real(70.), real(10.)),
+                    RectEdgeProperties {
+                        top: EdgeProperties::default()
+                            .with_layer(layer::DEFAULT)
+                            .with_material(ice_material),
+                        ..RectEdgeProperties::main()
+                    },
+                )),
+                [0.5, 0.5, 1.],
+            ).with_layer(player_pass_layer),
+        );
         self.add_static_solid(EntityCommon::new(
-            vec2(50., 450.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(100., 20.))),
-            [1., 1., 0.],
-        ));
-        self.add_static_solid(EntityCommon::new(
-            vec2(50., 500.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(700., 20.))),
+            vec2(real(50.), real(500.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(700.), real(20.)))),
             [1., 1., 0.],
         ));
         self.add_static_solid(EntityCommon::new(
-            vec2(450., 499.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            vec2(real(450.), real(499.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(20.), real(20.)))),
             [1., 1., 0.],
         ));
 
         self.add_static_solid(EntityCommon::new(
-            vec2(600., 498.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            vec2(real(600.), real(498.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(20.), real(20.)))),
             [1., 1., 0.],
         ));
         self.add_static_solid(EntityCommon::new(
-            vec2(620., 496.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            vec2(real(620.), real(496.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(20.), real(20.)))),
             [1., 1., 0.],
         ));
         self.add_static_solid(EntityCommon::new(
-            vec2(640., 492.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            vec2(real(640.), real(492.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(20.), real(20.)))),
             [1., 1., 0.],
         ));
 
         self.add_static_solid(EntityCommon::new(
-            vec2(760., 500.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            vec2(real(760.), real(500.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(20.), real(20.)))),
             [1., 1., 0.],
         ));
         self.add_static_solid(EntityCommon::new(
-            vec2(813., 500.),
-            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(20., 20.))),
+            vec2(real(813.), real(500.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(20.), real(20.)))),
             [1., 1., 0.],
         ));
 
         self.add_static_solid(EntityCommon::new(
-            vec2(20., 20.),
+            vec2(real(20.), real(20.)),
             Shape::LineSegment(LineSegment::new_both_solid(
-                vec2(0., 0.),
-                vec2(50., 100.),
+                vec2(real(0.), real(0.)),
+                vec2(real(50.), real(100.)),
             )),
             [0., 1., 0.],
         ));
         self.add_static_solid(EntityCommon::new(
-            vec2(200., 20.),
+            vec2(real(200.), real(20.)),
             Shape::LineSegment(LineSegment::new_both_solid(
-                vec2(0., 0.),
-                vec2(300., 200.),
+                vec2(real(0.), real(0.)),
+                vec2(real(300.), real(200.)),
             )),
             [0., 1., 0.],
         ));
         self.add_static_solid(EntityCommon::new(
-            vec2(200., 20.),
+            vec2(real(200.), real(20.)),
             Shape::LineSegment(LineSegment::new_both_solid(
-                vec2(0., 120.),
-                vec2(300., 200.),
+                vec2(real(0.), real(120.)),
+                vec2(real(300.), real(200.)),
+            )),
+            [0., 1., 0.],
+        ));
+        self.add_static_solid(EntityCommon::new(
+            vec2(real(560.), real(420.)),
+            Shape::LineSegment(LineSegment::new_left_solid(
+                vec2(real(0.), real(0.)),
+                vec2(real(80.), real(0.)),
+            )),
+            [0., 1., 0.],
+        ));
+        self.add_static_solid(EntityCommon::new(
+            vec2(real(560.), real(360.)),
+            Shape::LineSegment(LineSegment::new_right_solid(
+                vec2(real(80.), real(0.)),
+                vec2(real(0.), real(0.)),
             )),
             [0., 1., 0.],
         ));
         self.add_static_solid(EntityCommon::new(
-            vec2(900., 200.),
+            vec2(real(900.), real(200.)),
             Shape::LineSegment(LineSegment::new_both_solid(
-                vec2(0., 0.),
-                vec2(-300., 200.),
+                vec2(real(0.), real(0.)),
+                vec2(real(-300.), real(200.)),
             )),
             [0., 1., 0.],
         ));
 
+        self.add_static_solid(EntityCommon::new(
+            vec2(real(850.), real(500.)),
+            Shape::ConvexPolygon(
+                ConvexPolygon::new(vec![
+                    vec2(real(0.), real(0.)),
+                    vec2(real(40.), real(-30.)),
+                    vec2(real(80.), real(-30.)),
+                    vec2(real(100.), real(0.)),
+                    vec2(real(100.), real(20.)),
+                    vec2(real(0.), real(20.)),
+                ]).expect("Invalid polygon"),
+            ),
+            [1., 0., 1.],
+        ));
+
+        self.add_static_solid(EntityCommon::new(
+            vec2(real(130.), real(430.)),
+            Shape::Circle(Circle::new(real(20.))),
+            [0., 0.5, 1.],
+        ));
+        self.add_static_solid(EntityCommon::new(
+            vec2(real(380.), real(490.)),
+            Shape::Capsule(Capsule::new(
+                vec2(real(0.), real(0.)),
+                vec2(real(40.), real(0.)),
+                real(10.),
+            )),
+            [0., 0.5, 1.],
+        ));
+
+        self.add_static_solid(EntityCommon::new(
+            vec2(real(420.), real(440.)),
+            Shape::OrientedRect(OrientedRect::new(vec2(real(30.), real(30.)), real(0.4))),
+            [1., 0., 1.],
+        ));
+        self.add_static_solid(EntityCommon::new(
+            vec2(real(40.), real(310.)),
+            Shape::TileMap(TileMap::from_rows(
+                vec2(real(12.), real(12.)),
+                &[
+                    "........====",
+                    "............",
+                    "......##....",
+                    "....####....",
+                    "..######....",
+                    "############",
+                ],
+            )),
+            [1., 1., 0.],
+        ));
+        self.add_static_solid(EntityCommon::new(
+            vec2(real(460.), real(330.)),
+            Shape::Chain(Chain::new_open(vec![
+                vec2(real(0.), real(60.)),
+                vec2(real(40.), real(50.)),
+                vec2(real(80.), real(44.)),
+                vec2(real(120.), real(44.)),
+                vec2(real(160.), real(50.)),
+                vec2(real(200.), real(60.)),
+            ])),
+            [0., 1., 0.],
+        ));
+        self.add_static_solid(EntityCommon::new(
+            vec2(real(600.), real(60.)),
+            Shape::Chain(Chain::new_closed(vec![
+                vec2(real(0.), real(0.)),
+                vec2(real(20.), real(0.)),
+                vec2(real(20.), real(30.)),
+                vec2(real(60.), real(30.)),
+                vec2(real(60.), real(0.)),
+                vec2(real(80.), real(0.)),
+                vec2(real(80.), real(50.)),
+                vec2(real(0.), real(50.)),
+            ])),
+            [0., 1., 0.],
+        ));
+        self.add_static_solid(EntityCommon::new(
+            vec2(real(860.), real(300.)),
+            Shape::Compound(vec![
+                (
+                    vec2(real(0.), real(0.)),
+                    Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(
+                        real(12.),
+                        real(60.),
+                    ))),
+                ),
+                (
+                    vec2(real(12.), real(48.)),
+                    Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(
+                        real(68.),
+                        real(12.),
+                    ))),
+                ),
+            ]),
+            [1., 0.5, 0.],
+        ));
+
+        self.add_sensor(EntityCommon::new(
+            vec2(real(480.), real(400.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(60.), real(100.)))),
+            SENSOR_INACTIVE_COLOUR,
+        ));
+
+        for &x in [real(200.), real(225.), real(250.)].iter() {
+            let pickup_id = self.add_static_solid(
+                EntityCommon::new(
+                    vec2(x, real(490.)),
+                    Shape::Circle(Circle::new(real(6.))),
+                    [1., 0.8, 0.],
+                ).with_layer(pickup_layer),
+            );
+            self.pickup_ids.insert(pickup_id);
+        }
+
         let moving_platform_id = self.add_static_solid(EntityCommon::new(
-            vec2(300., 472.),
+            vec2(real(300.), real(472.)),
             Shape::LineSegment(LineSegment::new_both_solid(
-                vec2(0., 0.),
-                vec2(32., 32.),
+                vec2(real(0.), real(0.)),
+                vec2(real(32.), real(32.)),
             )),
             [0., 1., 0.],
         ));
         self.moving_platform_ids.push(moving_platform_id);
-        self.velocity.insert(moving_platform_id, vec2(0., 0.));
+        self.velocity.insert(moving_platform_id, vec2(real(0.), real(0.)));
         self.static_physics.insert(moving_platform_id);
+
+        let ball_id = self.add_common(EntityCommon::new(
+            vec2(real(300.), real(100.)),
+            Shape::Circle(Circle::new(real(8.))),
+            [1., 0.5, 0.],
+        ));
+        self.ball_ids.push(ball_id);
+        self.velocity.insert(ball_id, vec2(real(3.), real(0.)));
+        self.movement_properties.insert(
+            ball_id,
+            MovementProperties::default()
+                .with_response_mode(ResponseMode::Reflect { restitution: real(0.9) }),
+        );
+        self.dynamic_physics.insert(ball_id);
+
+        // sticks wherever it first lands
+        let sticky_ball_id = self.add_common(EntityCommon::new(
+            vec2(real(340.), real(100.)),
+            Shape::Circle(Circle::new(real(8.))),
+            [0.5, 0.25, 0.],
+        ));
+        self.ball_ids.push(sticky_ball_id);
+        self.velocity.insert(sticky_ball_id, vec2(real(3.), real(0.)));
+        self.movement_properties.insert(
+            sticky_ball_id,
+            MovementProperties::default().with_response_mode(ResponseMode::Stop),
+        );
+        self.dynamic_physics.insert(sticky_ball_id);
+    }
+    // sin is taken in S rather than f64, so fixed point runs stay deterministic
+    fn platform_phase(&self, frequency: f64) -> S {
+        real((self.frame_count as f64) * frequency)
     }
     pub fn update(
         &mut self,
-        input_model: &InputModel,
-        changes: &mut GameStateChanges,
-        movement_context: &mut MovementContext,
+        input_model: &InputModel<S>,
+        changes: &mut GameStateChanges<S>,
+        movement_context: &mut MovementContext<S>,
     ) {
-        self.quad_tree.clear();
-        for (id, common) in self.common.iter() {
-            self.quad_tree.insert(common.aabb(), *id);
-        }
-
         self.velocity.insert(
             self.moving_platform_ids[0],
-            vec2(((self.frame_count as f64) * 0.05).sin() * 2., 0.),
+            vec2(self.platform_phase(0.05).sin() * real(2.), real(0.)),
         );
         self.velocity.insert(
             self.moving_platform_ids[1],
-            vec2(0., ((self.frame_count as f64) * 0.1).sin() * 4.),
+            vec2(real(0.), self.platform_phase(0.1).sin() * real(4.)),
         );
         self.velocity.insert(
             self.moving_platform_ids[2],
-            vec2(((self.frame_count as f64) * 0.1).sin() * 5., 0.),
+            vec2(self.platform_phase(0.1).sin() * real(5.), real(0.)),
         );
 
+        for id in self.dynamic_physics.iter() {
+            if let Some(common) = self.common.get(id) {
+                let shape_position = ShapePosition {
+                    entity_id: *id,
+                    layer: common.layer,
+                    material: common.material,
+                    position: common.position,
+                    shape: &common.shape,
+                };
+                let all_shape_positions = AllShapePositions(self);
+                if let Some(correction) =
+                    movement_context.depenetration(shape_position, &all_shape_positions)
+                {
+                    changes.position.push((*id, common.position + correction));
+                }
+            }
+        }
+
+        for (id, position) in changes.position.drain(..) {
+            self.set_position(id, position);
+        }
+
+        for id in self.ball_ids.iter() {
+            if let Some(velocity) = self.velocity.get_mut(id) {
+                *velocity += vec2(real(0.), real(BALL_GRAVITY));
+            }
+        }
+
         let player_id = self.player_id.expect("No player id");
         {
             let collisions_below_player = {
                 let player_common = self.common.get(&player_id).unwrap();
                 let player_shape_position = ShapePosition {
                     entity_id: player_id,
+                    layer: player_common.layer,
+                    material: player_common.material,
                     position: player_common.position,
                     shape: &player_common.shape,
                 };
 
                 movement_context
-                    .collisions_below(player_shape_position, &AllShapePositions(self))
+                    .collisions_below(
+                        player_shape_position,
+                        real(PLAYER_MIN_WALKABLE_COS),
+                        &AllShapePositions(self),
+                    )
             };
 
             let jump = self.jump
@@ -475,16 +895,32 @@ impl GameState {
 
             jump.step(collisions_below_player.can_jump(), input_model);
 
+            let horizontal = input_model.horizontal();
+            if horizontal > real(0.) {
+                self.player_facing = real(1.);
+            } else if horizontal < real(0.) {
+                self.player_facing = -S::one();
+            }
+
             let max_platform_velocity = {
                 let velocity = &mut self.velocity;
                 collisions_below_player.max_velocity(|id| velocity.get(&id).cloned())
             };
+            let traction = match collisions_below_player.ground_friction() {
+                Some(friction) => {
+                    (friction / real(PLAYER_FULL_TRACTION_FRICTION)).min(S::one())
+                }
+                None => real(1.),
+            };
+            let ground_normal = collisions_below_player.ground_normal();
 
             if let Some(velocity) = self.velocity.get_mut(&player_id) {
                 *velocity = update_player_velocity(
                     *velocity,
                     input_model,
                     max_platform_velocity,
+                    traction,
+                    ground_normal,
                     jump,
                 );
             }
@@ -495,33 +931,51 @@ impl GameState {
                 if let Some(common) = self.common.get(id) {
                     let shape_position = ShapePosition {
                         entity_id: *id,
+                        layer: common.layer,
+                        material: common.material,
                         position: common.position,
                         shape: &common.shape,
                     };
-                    let movement = movement_context.position_after_allowed_movement(
-                        shape_position,
-                        *velocity,
-                        &AllShapePositions(self),
-                    );
+                    let properties = self.movement_properties
+                        .get(id)
+                        .cloned()
+                        .unwrap_or_default();
+                    let movement = movement_context
+                        .position_after_allowed_movement_with_properties(
+                            shape_position,
+                            *velocity,
+                            properties,
+                            &AllShapePositions(self),
+                        );
+                    if let Some(step_up) = movement.step_up {
+                        changes.step_ups.push((*id, step_up));
+                    }
                     changes.velocity.insert(*id, movement.velocity);
                     changes.position.push((*id, movement.position));
+                    changes.reported.extend(
+                        movement_context.reported_collisions().iter().map(|collision| {
+                            (collision.moving_entity_id, collision.stationary_entity_id)
+                        }),
+                    );
                 }
             }
         }
 
         for (id, position) in changes.position.drain(..) {
-            if let Some(common) = self.common.get_mut(&id) {
-                common.position = position;
-            }
+            self.set_position(id, position);
         }
 
         for (id, velocity) in changes.velocity.drain() {
             self.velocity.insert(id, velocity);
         }
 
-        self.quad_tree.clear();
-        for (id, common) in self.common.iter() {
-            self.quad_tree.insert(common.aabb(), *id);
+        self.step_ups.clear();
+        self.step_ups.extend(changes.step_ups.drain(..));
+
+        for (moving_id, stationary_id) in changes.reported.drain(..) {
+            if moving_id == player_id && self.pickup_ids.remove(&stationary_id) {
+                self.remove_common(stationary_id);
+            }
         }
 
         for id in self.static_physics.iter() {
@@ -529,6 +983,8 @@ impl GameState {
                 if let Some(common) = self.common.get(id) {
                     let shape_position = ShapePosition {
                         entity_id: *id,
+                        layer: common.layer,
+                        material: common.material,
                         position: common.position,
                         shape: &common.shape,
                     };
@@ -546,8 +1002,8 @@ impl GameState {
         }
 
         for (id, displacement) in changes.displacements.drain(..) {
-            if let Some(common) = self.common.get_mut(&id) {
-                common.position += displacement.movement;
+            if let Some(position) = self.common.get(&id).map(|common| common.position) {
+                self.set_position(id, position + displacement.movement);
             }
             if let Some(velocity) = self.velocity.get_mut(&id) {
                 *velocity = displacement.combine_velocity(*velocity);
@@ -555,18 +1011,397 @@ impl GameState {
         }
 
         for (id, position) in changes.position.drain(..) {
-            if let Some(common) = self.common.get_mut(&id) {
-                common.position = position;
+            self.set_position(id, position);
+        }
+
+        self.update_overlaps(movement_context.tolerances());
+
+        for (&(sensor_id, _), event) in self.overlap_events.iter() {
+            let colour = match event {
+                OverlapEvent::Enter => SENSOR_ACTIVE_COLOUR,
+                OverlapEvent::Exit => SENSOR_INACTIVE_COLOUR,
+                OverlapEvent::Stay => continue,
+            };
+            if let Some(common) = self.common.get_mut(&sensor_id) {
+                common.colour = colour;
             }
         }
 
+        self.update_query_shapes(movement_context);
+
         self.frame_count += 1;
     }
-    pub fn render_updates(&self) -> impl Iterator<Item = RenderUpdate> {
-        self.common.values().map(|common| RenderUpdate {
-            position: common.position,
-            shape: &common.shape,
-            colour: common.colour,
-        })
+    // The player looks ahead along a sight line which passes through floor
+    // only surfaces and stops at the first solid one, with a mark on each
+    // surface it reaches. A ground probe marks the surface below the player,
+    // pickups within the player's reach are circled, and a ghost shows where
+    // the ball will be blocked if it keeps its velocity.
+    fn update_query_shapes(&mut self, movement_context: &MovementContext<S>) {
+        self.query_shapes.clear();
+        self.ball_target = None;
+        let player_id = self.player_id.expect("No player id");
+        let (player_centre, player_layer) = match self.common.get(&player_id) {
+            Some(common) => (common.aabb().centre(), common.layer),
+            None => return,
+        };
+        let not_player = layer::ALL & !layer::mask(player_layer);
+
+        let sight_direction = vec2(self.player_facing, real(0.5));
+        let mut hits = Vec::new();
+        self.raycast_all(
+            player_centre,
+            sight_direction,
+            real(SIGHT_DISTANCE),
+            not_player,
+            movement_context,
+            &mut hits,
+        );
+        let mut sight_end =
+            player_centre + sight_direction.normalize_to(real(SIGHT_DISTANCE));
+        for hit in hits.iter() {
+            self.add_query_mark(hit.point, hit.normal);
+            if hit.edge_properties.channels & channels::MAIN != 0 {
+                sight_end = hit.point;
+                break;
+            }
+        }
+        self.add_query_line(player_centre, sight_end);
+
+        if let Some(hit) = self.raycast(
+            player_centre,
+            vec2(real(0.), real(1.)),
+            real(GROUND_PROBE_DISTANCE),
+            not_player,
+            movement_context,
+        ) {
+            let half_width = vec2(real(GROUND_PROBE_MARK_HALF_WIDTH), real(0.));
+            self.add_query_line(hit.point - half_width, hit.point + half_width);
+        }
+
+        if let Some(pickup_layer) = movement_context.collision_matrix().layer("pickup") {
+            let mut pickup_ids = Vec::new();
+            self.query_shape_overlap(
+                &Shape::Circle(Circle::new(real(PICKUP_REACH))),
+                player_centre,
+                layer::mask(pickup_layer),
+                movement_context,
+                &mut pickup_ids,
+            );
+            for id in pickup_ids.iter() {
+                let position = match self.common.get(id) {
+                    Some(common) => common.position,
+                    None => continue,
+                };
+                let mark = Shape::Circle(Circle::new(real(PICKUP_MARK_RADIUS)));
+                self.query_shapes.push((position, mark));
+            }
+        }
+
+        let ball_id = match self.ball_ids.first() {
+            Some(&ball_id) => ball_id,
+            None => return,
+        };
+        let ball_cast = match (self.common.get(&ball_id), self.velocity.get(&ball_id)) {
+            (Some(common), Some(&velocity)) => {
+                let movement = velocity * real(BALL_PREVIEW_FRAMES);
+                self.shape_cast(
+                    &common.shape,
+                    common.position,
+                    common.layer,
+                    movement,
+                    |id| id != ball_id,
+                    movement_context,
+                ).map(|shape_cast| {
+                    let position = common.position + movement * shape_cast.fraction;
+                    (position, common.shape.clone(), shape_cast)
+                })
+            }
+            _ => None,
+        };
+        if let Some((position, shape, shape_cast)) = ball_cast {
+            self.query_shapes.push((position, shape));
+            for collision in shape_cast.collisions.iter() {
+                match collision.contact.points {
+                    ContactPoints::One(point) => {
+                        self.add_query_mark(point, shape_cast.normal)
+                    }
+                    ContactPoints::Two(a, b) => self.add_query_line(a, b),
+                }
+            }
+            self.ball_target = Some(shape_cast.entity_id);
+        }
+    }
+    fn add_query_line(&mut self, start: Vector2<S>, end: Vector2<S>) {
+        self.query_shapes.push((
+            start,
+            Shape::LineSegment(LineSegment::new_both_solid(
+                vec2(real(0.), real(0.)),
+                end - start,
+            )),
+        ));
+    }
+    // a small circle on a surface, with a tick along the surface's normal
+    fn add_query_mark(&mut self, point: Vector2<S>, normal: Vector2<S>) {
+        self.query_shapes
+            .push((point, Shape::Circle(Circle::new(real(QUERY_MARK_RADIUS)))));
+        self.add_query_line(point, point + normal * real(QUERY_MARK_NORMAL_LENGTH));
+    }
+    // describes the player's surroundings, found with point and aabb queries
+    pub fn status(&self, movement_context: &MovementContext<S>) -> String {
+        let player_id = self.player_id.expect("No player id");
+        let player_aabb = match self.common.get(&player_id) {
+            Some(common) => common.aabb(),
+            None => return String::new(),
+        };
+        let mut entity_ids = Vec::new();
+        let below_feet = player_aabb.bottom_right_coord()
+            - vec2(player_aabb.size().x / real(2.), -S::one());
+        self.query_point(below_feet, layer::ALL, movement_context, &mut entity_ids);
+        let standing_on = entity_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let nearby_half_size = player_aabb.size() / real(2.)
+            + vec2(real(NEARBY_DISTANCE), real(NEARBY_DISTANCE));
+        self.query_aabb(
+            Aabb::from_centre_and_half_size(player_aabb.centre(), nearby_half_size),
+            layer::ALL,
+            movement_context,
+            &mut entity_ids,
+        );
+        let num_nearby = entity_ids.iter().filter(|&&id| id != player_id).count();
+        let ball_target = self.ball_target
+            .map_or("nothing".to_string(), |id| id.to_string());
+        let step_up = self.step_ups.get(&player_id).cloned().unwrap_or(real(0.));
+        format!(
+            "standing on: [{}], entities nearby: {}, ball heading for: {}, \
+             stepped up: {:.1}, depenetrations: {}",
+            standing_on,
+            num_nearby,
+            ball_target,
+            step_up,
+            movement_context.num_depenetrations()
+        )
+    }
+    // Sensors are only tested against dynamic physics bodies (the player and
+    // the ball), so static and moving platforms never enter or exit a sensor.
+    fn update_overlaps(&mut self, tolerances: &Tolerances<S>) {
+        self.overlap_events.clear();
+        for sensor_id in self.sensors.iter() {
+            let sensor_common = match self.common.get(sensor_id) {
+                Some(common) => common,
+                None => continue,
+            };
+            let sensor_shape_position = ShapePosition {
+                entity_id: *sensor_id,
+                layer: sensor_common.layer,
+                material: sensor_common.material,
+                position: sensor_common.position,
+                shape: &sensor_common.shape,
+            };
+            for id in self.dynamic_physics.iter() {
+                if let Some(common) = self.common.get(id) {
+                    let shape_position = ShapePosition {
+                        entity_id: *id,
+                        layer: common.layer,
+                        material: common.material,
+                        position: common.position,
+                        shape: &common.shape,
+                    };
+                    if overlap::shapes_overlap(
+                        &sensor_shape_position,
+                        &shape_position,
+                        tolerances,
+                    ) {
+                        let pair = (*sensor_id, *id);
+                        let event = if self.overlaps.contains(&pair) {
+                            OverlapEvent::Stay
+                        } else {
+                            OverlapEvent::Enter
+                        };
+                        self.overlap_events.insert(pair, event);
+                    }
+                }
+            }
+        }
+        for pair in self.overlaps.drain() {
+            self.overlap_events
+                .entry(pair)
+                .or_insert(OverlapEvent::Exit);
+        }
+        for (pair, event) in self.overlap_events.iter() {
+            if *event != OverlapEvent::Exit {
+                self.overlaps.insert(*pair);
+            }
+        }
+    }
+    // the shape doesn't need to belong to an entity, so the filter should
+    // exclude any entity which the shape represents
+    pub fn shape_cast<P>(
+        &self,
+        shape: &Shape<S>,
+        position: Vector2<S>,
+        layer: Layer,
+        movement: Vector2<S>,
+        filter: P,
+        movement_context: &MovementContext<S>,
+    ) -> Option<ShapeCast<S>>
+    where
+        P: Fn(EntityId) -> bool,
+    {
+        query::shape_cast(
+            shape,
+            position,
+            layer,
+            movement,
+            filter,
+            movement_context.collision_matrix(),
+            movement_context.tolerances(),
+            &AllShapePositions(self),
+        )
+    }
+    pub fn raycast(
+        &self,
+        origin: Vector2<S>,
+        direction: Vector2<S>,
+        max_distance: S,
+        layer_mask: LayerMask,
+        movement_context: &MovementContext<S>,
+    ) -> Option<RaycastHit<S>> {
+        query::raycast(
+            origin,
+            direction,
+            max_distance,
+            layer_mask,
+            movement_context.tolerances(),
+            &AllShapePositions(self),
+        )
+    }
+    pub fn raycast_all(
+        &self,
+        origin: Vector2<S>,
+        direction: Vector2<S>,
+        max_distance: S,
+        layer_mask: LayerMask,
+        movement_context: &MovementContext<S>,
+        hits: &mut Vec<RaycastHit<S>>,
+    ) {
+        query::raycast_all(
+            origin,
+            direction,
+            max_distance,
+            layer_mask,
+            movement_context.tolerances(),
+            &AllShapePositions(self),
+            hits,
+        );
+    }
+    pub fn query_point(
+        &self,
+        point: Vector2<S>,
+        layer_mask: LayerMask,
+        movement_context: &MovementContext<S>,
+        entity_ids: &mut Vec<EntityId>,
+    ) {
+        query::query_point(
+            point,
+            layer_mask,
+            movement_context.tolerances(),
+            &AllShapePositions(self),
+            entity_ids,
+        );
+    }
+    pub fn query_aabb(
+        &self,
+        aabb: Aabb<S>,
+        layer_mask: LayerMask,
+        movement_context: &MovementContext<S>,
+        entity_ids: &mut Vec<EntityId>,
+    ) {
+        query::query_aabb(
+            aabb,
+            layer_mask,
+            movement_context.tolerances(),
+            &AllShapePositions(self),
+            entity_ids,
+        );
+    }
+    pub fn query_shape_overlap(
+        &self,
+        shape: &Shape<S>,
+        position: Vector2<S>,
+        layer_mask: LayerMask,
+        movement_context: &MovementContext<S>,
+        entity_ids: &mut Vec<EntityId>,
+    ) {
+        query::query_shape_overlap(
+            shape,
+            position,
+            layer_mask,
+            movement_context.tolerances(),
+            &AllShapePositions(self),
+            entity_ids,
+        );
+    }
+    // events are keyed by (sensor, other) pairs
+    #[allow(dead_code)]
+    pub fn overlap_events(&self) -> &FnvHashMap<(EntityId, EntityId), OverlapEvent> {
+        &self.overlap_events
+    }
+    pub fn render_updates(&self) -> impl Iterator<Item = RenderUpdate<'_, S>> {
+        let query_updates = self.query_shapes
+            .iter()
+            .map(|&(position, ref shape)| RenderUpdate {
+                position,
+                shape,
+                colour: QUERY_COLOUR,
+            });
+        self.common
+            .values()
+            .map(|common| RenderUpdate {
+                position: common.position,
+                shape: &common.shape,
+                colour: common.colour,
+            })
+            .chain(query_updates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_entering_and_leaving_sensors_report_each_event_once() {
+        let mut game_state = GameState::<f64>::new();
+        let sensor_id = game_state.add_sensor(EntityCommon::new(
+            vec2(real(100.), real(0.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(20.), real(20.)))),
+            SENSOR_INACTIVE_COLOUR,
+        ));
+        let body_id = game_state.add_common(EntityCommon::new(
+            vec2(real(0.), real(0.)),
+            Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(10.), real(10.)))),
+            [1., 0., 0.],
+        ));
+        game_state.dynamic_physics.insert(body_id);
+        let tolerances = Tolerances::default();
+        let mut events = Vec::new();
+        for x in 0..30 {
+            game_state.set_position(body_id, vec2(real(x as f64 * 5.), real(5.)));
+            game_state.update_overlaps(&tolerances);
+            assert!(game_state.overlap_events().len() <= 1);
+            if let Some(&event) = game_state.overlap_events().get(&(sensor_id, body_id)) {
+                events.push(event);
+            }
+        }
+        // one enter, a stay for every frame spent inside, then one exit
+        let (first, rest) = events.split_first().unwrap();
+        let (last, middle) = rest.split_last().unwrap();
+        assert_eq!(*first, OverlapEvent::Enter);
+        assert!(!middle.is_empty());
+        assert!(middle.iter().all(|&event| event == OverlapEvent::Stay));
+        assert_eq!(*last, OverlapEvent::Exit);
     }
 }
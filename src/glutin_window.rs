@@ -13,6 +13,7 @@ pub struct GlutinWindow {
         gfx_device_gl::Resources,
         (gfx::format::R8_G8_B8_A8, gfx::format::Srgb),
     >,
+    #[allow(dead_code)]
     pub depth_stencil_view: gfx::handle::DepthStencilView<
         gfx_device_gl::Resources,
         (gfx::format::D24_S8, gfx::format::Unorm),
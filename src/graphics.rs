@@ -50,13 +50,13 @@ mod buffer_alloc {
     use super::buffer_types;
     use super::consts;
     use gfx;
+    type InstanceAndUploadBuffers<R, T> =
+        (gfx::handle::Buffer<R, T>, gfx::handle::Buffer<R, T>);
+
     pub fn create_instance_and_upload_buffers<R, F, T>(
         size: usize,
         factory: &mut F,
-    ) -> Result<
-        (gfx::handle::Buffer<R, T>, gfx::handle::Buffer<R, T>),
-        gfx::buffer::CreationError,
-    >
+    ) -> Result<InstanceAndUploadBuffers<R, T>, gfx::buffer::CreationError>
     where
         R: gfx::Resources,
         F: gfx::Factory<R> + gfx::traits::FactoryExt<R>,
@@ -178,7 +178,7 @@ mod instance_renderer {
         pub fn instance_writer<F>(
             &mut self,
             factory: &mut F,
-        ) -> InstanceWriter<R, D::Instance>
+        ) -> InstanceWriter<'_, R, D::Instance>
         where
             F: gfx::Factory<R> + gfx::traits::FactoryExt<R>,
         {
@@ -316,8 +316,9 @@ pub mod line_segment {
     pub type Renderer<R> = instance_renderer::Renderer<R, pipe::Data<R>>;
 }
 
-use cgmath::Vector2;
+use cgmath::{vec2, Vector2};
 use gfx;
+use std::f32::consts::{FRAC_PI_2, PI};
 
 pub struct InstanceWriter<'a, R: gfx::Resources, T: 'a + Copy> {
     num_instances: &'a mut usize,
@@ -345,9 +346,9 @@ impl<'a, R: gfx::Resources, T: Copy> Drop for InstanceWriter<'a, R, T> {
 }
 
 impl<'a, R: gfx::Resources, T: Copy> InstanceWriter<'a, R, T> {
-    pub fn iter_mut(&mut self) -> InstanceWriterIterMut<T> {
+    pub fn iter_mut(&mut self) -> InstanceWriterIterMut<'_, T> {
         InstanceWriterIterMut {
-            num_instances: &mut self.num_instances,
+            num_instances: self.num_instances,
             iter_mut: self.writer.iter_mut(),
         }
     }
@@ -359,7 +360,7 @@ pub struct Frame<'a, R: gfx::Resources> {
 }
 
 impl<'a, R: gfx::Resources> Frame<'a, R> {
-    pub fn updater(&mut self) -> FrameUpdater {
+    pub fn updater(&mut self) -> FrameUpdater<'_> {
         FrameUpdater {
             quad: self.quad.iter_mut(),
             line_segment: self.line_segment.iter_mut(),
@@ -397,6 +398,51 @@ impl<'a> FrameUpdater<'a> {
             line_segment.colour = colour;
         }
     }
+    pub fn polyline<I>(&mut self, vertices: I, colour: [f32; 3])
+    where
+        I: IntoIterator<Item = Vector2<f32>>,
+    {
+        let mut vertices = vertices.into_iter();
+        if let Some(mut previous) = vertices.next() {
+            for vertex in vertices {
+                self.line_segment(previous, vertex, colour);
+                previous = vertex;
+            }
+        }
+    }
+    pub fn polygon<I>(&mut self, vertices: I, colour: [f32; 3])
+    where
+        I: IntoIterator<Item = Vector2<f32>>,
+    {
+        let mut vertices = vertices.into_iter().peekable();
+        if let Some(&first) = vertices.peek() {
+            self.polyline(vertices.chain(Some(first)), colour);
+        }
+    }
+    pub fn capsule(
+        &mut self,
+        start: Vector2<f32>,
+        end: Vector2<f32>,
+        radius: f32,
+        colour: [f32; 3],
+    ) {
+        const HALF_CIRCLE_SEGMENTS: usize = 12;
+        let axis = end - start;
+        let angle = axis.y.atan2(axis.x);
+        let arc = |centre: Vector2<f32>, from: f32| {
+            (0..HALF_CIRCLE_SEGMENTS + 1).map(move |i| {
+                let a = from + PI * i as f32 / HALF_CIRCLE_SEGMENTS as f32;
+                centre + vec2(a.cos(), a.sin()) * radius
+            })
+        };
+        self.polygon(
+            arc(end, angle - FRAC_PI_2).chain(arc(start, angle + FRAC_PI_2)),
+            colour,
+        );
+    }
+    pub fn circle(&mut self, centre: Vector2<f32>, radius: f32, colour: [f32; 3]) {
+        self.capsule(centre, centre, radius, colour);
+    }
 }
 
 pub struct Renderer<R: gfx::Resources> {
@@ -429,7 +475,7 @@ impl<R: gfx::Resources> Renderer<R> {
             ),
         }
     }
-    pub fn prepare_frame<F>(&mut self, factory: &mut F) -> Frame<R>
+    pub fn prepare_frame<F>(&mut self, factory: &mut F) -> Frame<'_, R>
     where
         F: gfx::Factory<R> + gfx::traits::FactoryExt<R>,
     {
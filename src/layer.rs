@@ -0,0 +1,140 @@
+pub type Layer = u8;
+pub type LayerMask = u32;
+
+pub const MAX_LAYERS: usize = 32;
+pub const DEFAULT: Layer = 0;
+pub const ALL: LayerMask = !0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    Block,
+    Report,
+    Ignore,
+}
+
+#[derive(Debug, Clone)]
+pub struct CollisionMatrix {
+    names: Vec<String>,
+    block: [LayerMask; MAX_LAYERS],
+    report: [LayerMask; MAX_LAYERS],
+}
+
+impl Default for CollisionMatrix {
+    fn default() -> Self {
+        Self {
+            names: vec!["default".to_string()],
+            block: [!0; MAX_LAYERS],
+            report: [0; MAX_LAYERS],
+        }
+    }
+}
+
+pub fn mask(layer: Layer) -> LayerMask {
+    assert!((layer as usize) < MAX_LAYERS, "Invalid collision layer");
+    1 << layer
+}
+
+impl CollisionMatrix {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+    // layers block every other layer until configured otherwise
+    pub fn add_layer(&mut self, name: &str) -> Layer {
+        if let Some(layer) = self.layer(name) {
+            return layer;
+        }
+        assert!(self.names.len() < MAX_LAYERS, "Too many collision layers");
+        self.names.push(name.to_string());
+        (self.names.len() - 1) as Layer
+    }
+    pub fn layer(&self, name: &str) -> Option<Layer> {
+        self.names
+            .iter()
+            .position(|layer_name| layer_name == name)
+            .map(|index| index as Layer)
+    }
+    #[allow(dead_code)]
+    pub fn name(&self, layer: Layer) -> Option<&str> {
+        self.names.get(layer as usize).map(|name| name.as_str())
+    }
+    #[allow(dead_code)]
+    pub fn num_layers(&self) -> usize {
+        self.names.len()
+    }
+    pub fn set_response(&mut self, a: Layer, b: Layer, response: Response) {
+        let (a_bit, b_bit) = (mask(a), mask(b));
+        for &(layer, other_bit) in [(a, b_bit), (b, a_bit)].iter() {
+            let index = layer as usize;
+            self.block[index] &= !other_bit;
+            self.report[index] &= !other_bit;
+            match response {
+                Response::Block => self.block[index] |= other_bit,
+                Response::Report => self.report[index] |= other_bit,
+                Response::Ignore => (),
+            }
+        }
+    }
+    pub fn response(&self, a: Layer, b: Layer) -> Response {
+        let b_bit = mask(b);
+        if self.block[a as usize] & b_bit != 0 {
+            Response::Block
+        } else if self.report[a as usize] & b_bit != 0 {
+            Response::Report
+        } else {
+            Response::Ignore
+        }
+    }
+    pub fn ignores(&self, a: Layer, b: Layer) -> bool {
+        self.response(a, b) == Response::Ignore
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_matrix_has_one_blocking_layer() {
+        let matrix = CollisionMatrix::default();
+        assert_eq!(matrix.num_layers(), 1);
+        assert_eq!(matrix.name(DEFAULT), Some("default"));
+        assert_eq!(matrix.layer("default"), Some(DEFAULT));
+        assert_eq!(matrix.name(1), None);
+        assert_eq!(matrix.response(DEFAULT, DEFAULT), Response::Block);
+    }
+
+    #[test]
+    fn layers_are_named() {
+        let mut matrix = CollisionMatrix::default();
+        let player = matrix.add_layer("player");
+        let enemy = matrix.add_layer("enemy");
+        assert_eq!(matrix.add_layer("player"), player);
+        assert_eq!(matrix.num_layers(), 3);
+        assert_eq!(matrix.name(enemy), Some("enemy"));
+        assert_eq!(matrix.layer("enemy"), Some(enemy));
+        assert_eq!(matrix.layer("pickup"), None);
+        // new layers block everything
+        assert_eq!(matrix.response(player, enemy), Response::Block);
+        assert_eq!(matrix.response(enemy, DEFAULT), Response::Block);
+        matrix.clear();
+        assert_eq!(matrix.num_layers(), 1);
+    }
+
+    #[test]
+    fn responses_are_symmetric() {
+        let mut matrix = CollisionMatrix::default();
+        let player = matrix.add_layer("player");
+        let pickup = matrix.add_layer("pickup");
+        for &response in [Response::Ignore, Response::Report, Response::Block].iter() {
+            matrix.set_response(player, pickup, response);
+            assert_eq!(matrix.response(player, pickup), response);
+            assert_eq!(matrix.response(pickup, player), response);
+            // other pairs are unchanged
+            assert_eq!(matrix.response(player, player), Response::Block);
+            assert_eq!(matrix.response(pickup, DEFAULT), Response::Block);
+        }
+        matrix.set_response(pickup, pickup, Response::Ignore);
+        assert!(matrix.ignores(pickup, pickup));
+        assert!(!matrix.ignores(player, pickup));
+    }
+}
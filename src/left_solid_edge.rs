@@ -1,10 +1,13 @@
 use cgmath::{InnerSpace, Vector2, vec2};
+use predicates;
+use scalar::{real, Scalar};
 use std::cmp::Ordering;
+use tolerances::Tolerances;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub struct LeftSolidEdge {
-    pub start: Vector2<f64>,
-    pub end: Vector2<f64>,
+pub struct LeftSolidEdge<S> {
+    pub start: Vector2<S>,
+    pub end: Vector2<S>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -14,25 +17,25 @@ pub enum StartOrEnd {
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct EdgeCollisionPosition {
+pub struct EdgeCollisionPosition<S> {
     pub which_part_of_other_edge: StartOrEnd,
-    pub how_far_along_this_edge: f64,
+    pub how_far_along_this_edge: S,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-enum EdgeCollisions {
+enum EdgeCollisions<S> {
     Zero,
-    One(EdgeCollisionPosition),
+    One(EdgeCollisionPosition<S>),
     Two {
-        min: EdgeCollisionPosition,
-        max: EdgeCollisionPosition,
+        min: EdgeCollisionPosition<S>,
+        max: EdgeCollisionPosition<S>,
     },
 }
 
-impl EdgeCollisions {
+impl<S: Scalar> EdgeCollisions<S> {
     fn new(
-        other_edge_start: &Option<VertexCollision>,
-        other_edge_end: &Option<VertexCollision>,
+        other_edge_start: &Option<VertexCollision<S>>,
+        other_edge_end: &Option<VertexCollision<S>>,
     ) -> Self {
         match (other_edge_start, other_edge_end) {
             (None, None) => EdgeCollisions::Zero,
@@ -57,14 +60,14 @@ impl EdgeCollisions {
         }
     }
 
-    fn min_edge_collision_position(&self) -> Option<EdgeCollisionPosition> {
+    fn min_edge_collision_position(&self) -> Option<EdgeCollisionPosition<S>> {
         match self {
             EdgeCollisions::Zero => None,
             EdgeCollisions::One(m) => Some(*m),
             EdgeCollisions::Two { min, .. } => Some(*min),
         }
     }
-    fn max_edge_collision_position(&self) -> Option<EdgeCollisionPosition> {
+    fn max_edge_collision_position(&self) -> Option<EdgeCollisionPosition<S>> {
         match self {
             EdgeCollisions::Zero => None,
             EdgeCollisions::One(m) => Some(*m),
@@ -74,46 +77,95 @@ impl EdgeCollisions {
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct LeftSolidEdgeCollision {
-    moving_edge_collisions: EdgeCollisions,
-    stationary_edge_collisions: EdgeCollisions,
-    movement_multiplier: f64,
-    edge_vector: Vector2<f64>,
+pub struct LeftSolidEdgeCollision<S> {
+    moving_edge_collisions: EdgeCollisions<S>,
+    stationary_edge_collisions: EdgeCollisions<S>,
+    movement_multiplier: S,
+    edge_vector: Vector2<S>,
 }
 
-impl LeftSolidEdgeCollision {
-    pub fn movement_multiplier(&self) -> f64 {
+impl<S: Scalar> LeftSolidEdgeCollision<S> {
+    // Collisions involving round shapes touch at a single point rather than
+    // along edges, so they record no edge collision positions and are never
+    // bumped. They don't need to be: the contact normal at a ledge's corner
+    // is already tilted, so sliding carries a round shape over the corner.
+    pub fn from_tangent(movement_multiplier: S, tangent: Vector2<S>) -> Self {
+        Self {
+            moving_edge_collisions: EdgeCollisions::Zero,
+            stationary_edge_collisions: EdgeCollisions::Zero,
+            movement_multiplier,
+            edge_vector: tangent,
+        }
+    }
+    pub fn movement_multiplier(&self) -> S {
         self.movement_multiplier
     }
-    pub fn movement_to_collision(&self, movement_attempt: Vector2<f64>) -> Vector2<f64> {
+    pub fn edge_vector(&self) -> Vector2<S> {
+        self.edge_vector
+    }
+    // perpendicular to the collision edge, opposing the movement
+    pub fn normal(&self, movement_attempt: Vector2<S>) -> Vector2<S> {
+        let normal = vec2(-self.edge_vector.y, self.edge_vector.x).normalize();
+        if normal.dot(movement_attempt) > real(0.) {
+            -normal
+        } else {
+            normal
+        }
+    }
+    pub fn movement_to_collision(&self, movement_attempt: Vector2<S>) -> Vector2<S> {
         movement_attempt * self.movement_multiplier
     }
     pub fn movement_following_collision(
         &self,
-        movement_attempt: Vector2<f64>,
-    ) -> Vector2<f64> {
-        movement_attempt * (1. - self.movement_multiplier)
+        movement_attempt: Vector2<S>,
+    ) -> Vector2<S> {
+        movement_attempt * (S::one() - self.movement_multiplier)
     }
-    pub fn slide(&self, movement_attempt: Vector2<f64>) -> Vector2<f64> {
+    pub fn slide(&self, movement_attempt: Vector2<S>) -> Vector2<S> {
         self.movement_following_collision(movement_attempt)
             .project_on(self.edge_vector)
     }
-    pub fn displacement(&self, movement_attempt: Vector2<f64>) -> Vector2<f64> {
+    // the sliding movement is reduced by friction times the movement into the edge
+    pub fn slide_with_friction(
+        &self,
+        movement_attempt: Vector2<S>,
+        friction: S,
+    ) -> Vector2<S> {
+        let following = self.movement_following_collision(movement_attempt);
+        let slide = self.slide(movement_attempt);
+        let slide_length = slide.magnitude();
+        if slide_length <= real(0.) {
+            return slide;
+        }
+        let into_edge_length = (following - slide).magnitude();
+        let reduced_length = (slide_length - friction * into_edge_length).max(real(0.));
+        slide * (reduced_length / slide_length)
+    }
+    pub fn displacement(&self, movement_attempt: Vector2<S>) -> Vector2<S> {
         let perpendicular_to_edge_vector = vec2(self.edge_vector.y, -self.edge_vector.x);
         self.movement_following_collision(movement_attempt)
             .project_on(perpendicular_to_edge_vector)
     }
     pub fn displacement_full_movement(
         &self,
-        movement_attempt: Vector2<f64>,
-    ) -> Vector2<f64> {
+        movement_attempt: Vector2<S>,
+    ) -> Vector2<S> {
         let perpendicular_to_edge_vector = vec2(self.edge_vector.y, -self.edge_vector.x);
         movement_attempt.project_on(perpendicular_to_edge_vector)
     }
-    pub fn moving_edge_min_collision_position(&self) -> Option<EdgeCollisionPosition> {
+    pub fn stationary_vertex_only(&self) -> Option<StartOrEnd> {
+        if self.stationary_edge_collisions != EdgeCollisions::Zero {
+            return None;
+        }
+        match self.moving_edge_collisions {
+            EdgeCollisions::One(position) => Some(position.which_part_of_other_edge),
+            _ => None,
+        }
+    }
+    pub fn moving_edge_min_collision_position(&self) -> Option<EdgeCollisionPosition<S>> {
         self.moving_edge_collisions.min_edge_collision_position()
     }
-    pub fn moving_edge_max_collision_position(&self) -> Option<EdgeCollisionPosition> {
+    pub fn moving_edge_max_collision_position(&self) -> Option<EdgeCollisionPosition<S>> {
         self.moving_edge_collisions.max_edge_collision_position()
     }
 }
@@ -124,82 +176,126 @@ const STATIONARY_START: usize = 2;
 const STATIONARY_END: usize = 3;
 
 #[derive(Debug, Clone, Copy)]
-struct VertexCollision {
-    movement_multiplier: f64,
-    edge_vector_multiplier: f64,
-    edge_vector: Vector2<f64>,
+struct VertexCollision<S> {
+    movement_multiplier: S,
+    edge_vector_multiplier: S,
+    edge_vector: Vector2<S>,
 }
 
-fn vector2_cross_product(v: Vector2<f64>, w: Vector2<f64>) -> f64 {
+pub fn vector2_cross_product<S: Scalar>(v: Vector2<S>, w: Vector2<S>) -> S {
     v.x * w.y - v.y * w.x
 }
 
+// A vertex lying exactly on an end of an edge only collides if the range
+// includes that end, so a vertex shared by two neighbouring edges is only
+// counted once.
 #[derive(Debug, Clone, Copy)]
-struct Multipliers {
-    min: f64,
-    max: f64,
+struct EdgeRange {
+    includes_start: bool,
+    includes_end: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
-struct EdgeVectorAndCross {
-    vector: Vector2<f64>,
-    cross: f64,
+struct EdgeVectorAndCross<S> {
+    vector: Vector2<S>,
+    cross: S,
 }
 
-impl EdgeVectorAndCross {
-    fn new(edge: &LeftSolidEdge, movement: Vector2<f64>) -> Self {
+impl<S: Scalar> EdgeVectorAndCross<S> {
+    // the movement must cross the edge from its open side
+    fn approaching(
+        edge: &LeftSolidEdge<S>,
+        movement: Vector2<S>,
+        tolerances: &Tolerances<S>,
+    ) -> Option<Self> {
+        if predicates::edge_side(movement, edge.start, edge.end) != Ordering::Less {
+            return None;
+        }
         let vector = edge.vector();
         let cross = vector2_cross_product(movement, vector);
-        Self { vector, cross }
+        // The side test is exact, but the rounded cross product divides the
+        // multipliers in collide_moving_vertex, so movements almost parallel
+        // to the edge are rejected. The bound is relative to the lengths of
+        // the movement and the edge.
+        if cross >= -tolerances.parallel * movement.magnitude() * vector.magnitude() {
+            return None;
+        }
+        Some(Self { vector, cross })
     }
 }
 
-pub const EPSILON: f64 = 0.000001;
-
-const START_MULTIPLIERS: Multipliers = Multipliers {
-    min: EPSILON,
-    max: 1. + EPSILON,
+const START_RANGE: EdgeRange = EdgeRange {
+    includes_start: false,
+    includes_end: true,
 };
 
-const END_MULTIPLIERS: Multipliers = Multipliers {
-    min: -EPSILON,
-    max: 1. - EPSILON,
+const END_RANGE: EdgeRange = EdgeRange {
+    includes_start: true,
+    includes_end: false,
 };
 
-impl LeftSolidEdge {
-    pub fn new(start: Vector2<f64>, end: Vector2<f64>) -> Self {
+impl<S: Scalar> LeftSolidEdge<S> {
+    pub fn new(start: Vector2<S>, end: Vector2<S>) -> Self {
         Self { start, end }
     }
 
-    pub fn add_vector(&self, vector: Vector2<f64>) -> Self {
+    pub fn add_vector(&self, vector: Vector2<S>) -> Self {
         Self {
             start: self.start + vector,
             end: self.end + vector,
         }
     }
 
-    pub fn vector(&self) -> Vector2<f64> {
+    pub fn vector(&self) -> Vector2<S> {
         self.end - self.start
     }
 
+    // whether the line swept by the vertex passes through the edge
+    fn is_crossed_by(
+        &self,
+        vertex: Vector2<S>,
+        vertex_movement: Vector2<S>,
+        range: EdgeRange,
+    ) -> bool {
+        let start_side = predicates::edge_side(vertex_movement, vertex, self.start);
+        let end_side = predicates::edge_side(vertex_movement, vertex, self.end);
+        let after_start = match start_side {
+            Ordering::Greater => true,
+            Ordering::Equal => range.includes_start,
+            Ordering::Less => false,
+        };
+        let before_end = match end_side {
+            Ordering::Less => true,
+            Ordering::Equal => range.includes_end,
+            Ordering::Greater => false,
+        };
+        after_start && before_end
+    }
+
     fn collide_moving_vertex(
         &self,
-        vertex: Vector2<f64>,
-        vertex_movement: Vector2<f64>,
-        edge_multipliers: Multipliers,
-        evc: EdgeVectorAndCross,
-    ) -> Option<VertexCollision> {
-        let vertex_to_start = self.start - vertex;
-        let edge_vector_multiplier =
-            vector2_cross_product(vertex_to_start, vertex_movement) / evc.cross;
-        if edge_vector_multiplier < edge_multipliers.min
-            || edge_vector_multiplier > edge_multipliers.max
-        {
+        vertex: Vector2<S>,
+        vertex_movement: Vector2<S>,
+        range: EdgeRange,
+        evc: EdgeVectorAndCross<S>,
+        tolerances: &Tolerances<S>,
+    ) -> Option<VertexCollision<S>> {
+        if !self.is_crossed_by(vertex, vertex_movement, range) {
             return None;
         }
+        let vertex_to_start = self.start - vertex;
+        let edge_vector_multiplier =
+            (vector2_cross_product(vertex_to_start, vertex_movement) / evc.cross)
+                .max(real(0.))
+                .min(real(1.));
         let movement_multiplier =
             vector2_cross_product(vertex_to_start, evc.vector) / evc.cross;
-        if movement_multiplier < -EPSILON || movement_multiplier > 1. + EPSILON {
+        // Whether the vertex's line crosses the edge is exact, but the
+        // multiplier is rounded. A vertex which rounding in an earlier
+        // movement left just past the edge must still be stopped by it.
+        if movement_multiplier < -tolerances.contact_time
+            || movement_multiplier > S::one() + tolerances.contact_time
+        {
             return None;
         }
         Some(VertexCollision {
@@ -212,47 +308,51 @@ impl LeftSolidEdge {
     pub fn collide_with_stationary_edge(
         &self,
         other: &Self,
-        movement: Vector2<f64>,
-    ) -> Option<LeftSolidEdgeCollision> {
-        let stationary = EdgeVectorAndCross::new(other, movement);
-        if stationary.cross > -EPSILON {
-            return None;
-        }
+        movement: Vector2<S>,
+        tolerances: &Tolerances<S>,
+    ) -> Option<LeftSolidEdgeCollision<S>> {
+        let stationary = EdgeVectorAndCross::approaching(other, movement, tolerances)?;
         let reverse_movement = -movement;
-        let moving = EdgeVectorAndCross::new(self, -movement);
-        if moving.cross > -EPSILON {
-            return None;
-        }
+        let moving = EdgeVectorAndCross::approaching(self, reverse_movement, tolerances)?;
         let vertex_collisions = [
             self.collide_moving_vertex(
                 other.start,
                 reverse_movement,
-                START_MULTIPLIERS,
+                START_RANGE,
                 moving,
+                tolerances,
             ),
             self.collide_moving_vertex(
                 other.end,
                 reverse_movement,
-                END_MULTIPLIERS,
+                END_RANGE,
                 moving,
+                tolerances,
             ),
             other.collide_moving_vertex(
                 self.start,
                 movement,
-                START_MULTIPLIERS,
+                START_RANGE,
+                stationary,
+                tolerances,
+            ),
+            other.collide_moving_vertex(
+                self.end,
+                movement,
+                END_RANGE,
                 stationary,
+                tolerances,
             ),
-            other.collide_moving_vertex(self.end, movement, END_MULTIPLIERS, stationary),
         ];
         let (min_movement, edge_vector) = vertex_collisions
             .iter()
             .filter_map(|c| c.map(|c| (c.movement_multiplier, c.edge_vector)))
-            .min_by(|&(ref a, _), &(ref b, _)| {
+            .min_by(|(a, _), (b, _)| {
                 a.partial_cmp(b).unwrap_or(Ordering::Equal)
             })?;
 
-        let movement_filter = |c: VertexCollision| {
-            if c.movement_multiplier > min_movement + EPSILON {
+        let movement_filter = |c: VertexCollision<S>| {
+            if c.movement_multiplier > min_movement + tolerances.contact_time {
                 None
             } else {
                 Some(c)
@@ -277,3 +377,23 @@ impl LeftSolidEdge {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn friction_reduces_sliding_by_the_movement_into_the_edge() {
+        // a floor, hit halfway through a movement 3 units into it
+        let collision = LeftSolidEdgeCollision::from_tangent(0.5, vec2(1., 0.));
+        let movement = vec2(8., 6.);
+        assert_eq!(collision.slide(movement), vec2(4., 0.));
+        assert_eq!(collision.slide_with_friction(movement, 0.), vec2(4., 0.));
+        assert_eq!(collision.slide_with_friction(movement, 0.5), vec2(2.5, 0.));
+        assert_eq!(collision.slide_with_friction(movement, 1.), vec2(1., 0.));
+        // friction never reverses the slide
+        assert_eq!(collision.slide_with_friction(movement, 2.), vec2(0., 0.));
+        let movement = vec2(-8., 6.);
+        assert_eq!(collision.slide_with_friction(movement, 0.5), vec2(-2.5, 0.));
+    }
+}
@@ -1,59 +1,95 @@
 use aabb::Aabb;
 use cgmath::{vec2, InnerSpace, Vector2};
 use collide::{Collide, Edge};
+use scalar::{real, Scalar};
 
 const WIDTH: f64 = 0.1;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Solidity {
+    Both,
+    Left,
+    Right,
+}
+
 #[derive(Debug, Clone, Copy)]
-pub struct LineSegment {
-    pub start: Vector2<f64>,
-    pub end: Vector2<f64>,
+pub struct LineSegment<S> {
+    pub start: Vector2<S>,
+    pub end: Vector2<S>,
+    solidity: Solidity,
 }
 
-impl LineSegment {
-    pub fn new_both_solid(start: Vector2<f64>, end: Vector2<f64>) -> Self {
-        Self { start, end }
+impl<S: Scalar> LineSegment<S> {
+    pub fn new_both_solid(start: Vector2<S>, end: Vector2<S>) -> Self {
+        Self {
+            start,
+            end,
+            solidity: Solidity::Both,
+        }
+    }
+    pub fn new_left_solid(start: Vector2<S>, end: Vector2<S>) -> Self {
+        Self {
+            start,
+            end,
+            solidity: Solidity::Left,
+        }
+    }
+    pub fn new_right_solid(start: Vector2<S>, end: Vector2<S>) -> Self {
+        Self {
+            start,
+            end,
+            solidity: Solidity::Right,
+        }
     }
-    pub fn add_vector(&self, vector: Vector2<f64>) -> Self {
+    pub fn add_vector(&self, vector: Vector2<S>) -> Self {
         Self {
             start: self.start + vector,
             end: self.end + vector,
+            ..*self
         }
     }
-    pub fn vector(&self) -> Vector2<f64> {
+    pub fn vector(&self) -> Vector2<S> {
         self.end - self.start
     }
-    fn left_solid_edge(&self) -> Edge {
+    pub fn is_both_solid(&self) -> bool {
+        self.solidity == Solidity::Both
+    }
+    fn left_solid_edge(&self) -> Edge<S> {
         Edge::new(self.start, self.end)
     }
-    fn left_solid_edge_flipped(&self) -> Edge {
+    fn left_solid_edge_flipped(&self) -> Edge<S> {
         Edge::new(self.end, self.start)
     }
 }
 
-impl Collide for LineSegment {
-    fn aabb(&self, top_left: Vector2<f64>) -> Aabb {
+impl<S: Scalar> Collide<S> for LineSegment<S> {
+    fn aabb(&self, top_left: Vector2<S>) -> Aabb<S> {
         let start = self.start + top_left;
         let end = self.end + top_left;
         // XXX the +/- 1 is because of how edges are computed.
         // Currentyl they push the corners of the thin rectangle
         // representing the line outside the normal bounding box.
-        let x_min = start.x.min(end.x) - 1.;
-        let x_max = start.x.max(end.x) + 1.;
-        let y_min = start.y.min(end.y) - 1.;
-        let y_max = start.y.max(end.y) + 1.;
+        let x_min = start.x.min(end.x) - real(1.);
+        let x_max = start.x.max(end.x) + real(1.);
+        let y_min = start.y.min(end.y) - real(1.);
+        let y_max = start.y.max(end.y) + real(1.);
         let top_left = vec2(x_min, y_min);
         let bottom_right = vec2(x_max, y_max);
         Aabb::new(top_left, bottom_right - top_left)
     }
 
-    fn for_each_left_solid_edge_facing<F: FnMut(Edge)>(
+    fn for_each_left_solid_edge_facing<F: FnMut(Edge<S>)>(
         &self,
-        _direction: Vector2<f64>,
+        _direction: Vector2<S>,
         mut f: F,
     ) {
+        match self.solidity {
+            Solidity::Left => return f(self.left_solid_edge()),
+            Solidity::Right => return f(self.left_solid_edge_flipped()),
+            Solidity::Both => (),
+        }
         let vector = self.vector();
-        let left = vec2(-vector.y, vector.x).normalize_to(WIDTH);
+        let left = vec2(-vector.y, vector.x).normalize_to(real(WIDTH));
         let a = self.add_vector(left).left_solid_edge_flipped();
         let b = self.add_vector(-left).left_solid_edge();
         f(a);
@@ -62,3 +98,27 @@ impl Collide for LineSegment {
         f(Edge::new(b.end(), a.start()));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(line_segment: &LineSegment<f64>) -> Vec<Edge<f64>> {
+        let mut edges = Vec::new();
+        line_segment
+            .for_each_left_solid_edge_facing(vec2(0., 1.), |edge| edges.push(edge));
+        edges
+    }
+
+    #[test]
+    fn one_sided_segments_emit_a_single_edge() {
+        let (start, end) = (vec2(0., 0.), vec2(80., 0.));
+        assert_eq!(edges(&LineSegment::new_both_solid(start, end)).len(), 4);
+        let left = edges(&LineSegment::new_left_solid(start, end));
+        assert_eq!(left.len(), 1);
+        assert_eq!((left[0].start(), left[0].end()), (start, end));
+        let right = edges(&LineSegment::new_right_solid(start, end));
+        assert_eq!(right.len(), 1);
+        assert_eq!((right[0].start(), right[0].end()), (end, start));
+    }
+}
@@ -1,182 +1,411 @@
 use aabb::*;
+use broadphase::{Broadphase, BroadphaseHandle, Entries};
 use cgmath::{vec2, Vector2};
+use scalar::{real, Scalar};
 use std::num::NonZeroUsize;
 
+// stops degenerate (e.g. zero sized) entries descending forever
+const MAX_DEPTH: usize = 24;
+// stops unbounded (e.g. infinite) entries growing the tree forever.
+// Entries which still don't fit are kept in the root node.
+const MAX_GROWTH: usize = 64;
+const MIN_ROOT_SIZE: f64 = 1.;
+
 #[derive(Debug, Clone)]
-pub struct LooseQuadTree<T> {
-    seq: u64,
-    nodes: Vec<Node<T>>,
-    size: Vector2<f64>,
-    next_free: usize,
+pub struct LooseQuadTree<T, S> {
+    nodes: Vec<Node>,
+    entries: Entries<Entry<T, S>>,
+    // grows to contain each entry, and is None while the tree is empty
+    root: Option<Aabb<S>>,
 }
 
-#[derive(Debug, Clone)]
-struct Node<T> {
-    items: Vec<(Aabb, T)>,
+#[derive(Debug, Clone, Default)]
+struct Node {
+    items: Vec<BroadphaseHandle>,
     child_offset: Option<NonZeroUsize>,
-    seq: u64,
 }
 
-impl<T> Default for Node<T> {
-    fn default() -> Self {
-        Self {
-            items: Vec::new(),
-            child_offset: None,
-            seq: 0,
-        }
-    }
-}
-impl<T> Node<T> {
-    fn reuse(&mut self, seq: u64) {
-        self.items.clear();
-        self.child_offset = None;
-        self.seq = seq;
-    }
+#[derive(Debug, Clone)]
+struct Entry<T, S> {
+    aabb: Aabb<S>,
+    t: T,
+    node: usize,
+    // index of the entry in its node's items
+    slot: usize,
+    // the entry stays in its node while its aabb is within these bounds.
+    // Entries in the root node have none, and are relocated on every update.
+    loose_bounds: Option<Aabb<S>>,
 }
 
-impl<T> LooseQuadTree<T> {
+impl<T, S: Scalar> LooseQuadTree<T, S> {
     const TOP_LEFT: usize = 0;
     const TOP_RIGHT: usize = 1;
     const BOTTOM_LEFT: usize = 2;
     const BOTTOM_RIGHT: usize = 3;
     const NUM_CHILDREN: usize = 4;
 
-    pub fn new(size: Vector2<f64>) -> Self {
+    pub fn new() -> Self {
         Self {
-            seq: 1,
             nodes: vec![Default::default()],
-            size,
-            next_free: 1,
+            entries: Default::default(),
+            root: None,
         }
     }
 
-    pub fn clear(&mut self) {
-        self.seq += 1;
-        self.nodes[0].reuse(self.seq);
-    }
-
-    pub fn insert(&mut self, aabb: Aabb, t: T) {
-        let mut centre = aabb.centre();
-        let mut index = 0;
-        let mut max_size = self.size / 2.;
-        let nodes = &mut self.nodes;
-        let next_free = &mut self.next_free;
-        loop {
-            let child_offset = {
-                while nodes.len() <= index {
-                    nodes.push(Default::default());
-                }
-                let mut node = &mut nodes[index];
-                if node.seq != self.seq {
-                    node.reuse(self.seq);
-                }
+    // Doubles the root towards the aabb until it contains it. Each old root
+    // becomes a child of the new root, so only entries which were in the old
+    // root node need to be reattached.
+    fn grow_to_contain(&mut self, aabb: &Aabb<S>) {
+        let mut root = match self.root {
+            Some(root) => root,
+            None => {
                 let size = aabb.size();
-                if size.x > max_size.x || size.y > max_size.y {
-                    node.items.push((aabb, t));
-                    break;
+                let size = size.x.max(size.y).max(real(MIN_ROOT_SIZE));
+                self.root = Some(Aabb::new(aabb.top_left(), vec2(size, size)));
+                return;
+            }
+        };
+        if root.is_containing(aabb) {
+            return;
+        }
+        let old_root_items: Vec<BroadphaseHandle> =
+            self.nodes[0].items.drain(..).collect();
+        let centre = aabb.centre();
+        for _ in 0..MAX_GROWTH {
+            if root.is_containing(aabb) {
+                break;
+            }
+            let root_centre = root.centre();
+            let top_left = root.top_left();
+            let size = root.size();
+            let (child, top_left) = if centre.x < root_centre.x {
+                if centre.y < root_centre.y {
+                    (Self::BOTTOM_RIGHT, top_left - size)
+                } else {
+                    (Self::TOP_RIGHT, vec2(top_left.x - size.x, top_left.y))
+                }
+            } else {
+                if centre.y < root_centre.y {
+                    (Self::BOTTOM_LEFT, vec2(top_left.x, top_left.y - size.y))
+                } else {
+                    (Self::TOP_LEFT, top_left)
                 }
-                node.child_offset
-                    .get_or_insert_with(|| {
-                        let free = *next_free;
-                        *next_free += Self::NUM_CHILDREN;
-                        NonZeroUsize::new(free).expect("unexpected state")
-                    })
-                    .get() as usize
             };
-            if centre.x < max_size.x {
-                if centre.y < max_size.y {
-                    index = child_offset + Self::TOP_LEFT;
+            let child_offset = self.nodes.len();
+            for _ in 0..Self::NUM_CHILDREN {
+                self.nodes.push(Default::default());
+            }
+            self.nodes.swap(0, child_offset + child);
+            self.nodes[0].child_offset =
+                Some(NonZeroUsize::new(child_offset).expect("unexpected state"));
+            root = Aabb::new(top_left, size * real(2.));
+        }
+        self.root = Some(root);
+        for handle in old_root_items {
+            let entry_aabb = self.entries.get(handle).aabb;
+            self.attach(handle, &entry_aabb);
+        }
+    }
+
+    // the deepest node whose loose bounds contain the aabb, and those bounds
+    fn find_node(&mut self, aabb: &Aabb<S>) -> (usize, Option<Aabb<S>>) {
+        let centre = aabb.centre();
+        let mut index = 0;
+        let mut node_aabb = self.root.expect("unexpected state");
+        let mut loose_bounds = None;
+        for _ in 0..MAX_DEPTH {
+            let node_centre = node_aabb.centre();
+            let AabbSplitFour {
+                top_left,
+                top_right,
+                bottom_left,
+                bottom_right,
+            } = node_aabb.split_four();
+            let (child, child_aabb) = if centre.x < node_centre.x {
+                if centre.y < node_centre.y {
+                    (Self::TOP_LEFT, top_left)
                 } else {
-                    index = child_offset + Self::BOTTOM_LEFT;
-                    centre.y = centre.y - max_size.y;
+                    (Self::BOTTOM_LEFT, bottom_left)
                 }
             } else {
-                if centre.y < max_size.y {
-                    index = child_offset + Self::TOP_RIGHT;
-                    centre.x = centre.x - max_size.x;
+                if centre.y < node_centre.y {
+                    (Self::TOP_RIGHT, top_right)
                 } else {
-                    index = child_offset + Self::BOTTOM_RIGHT;
-                    centre = centre - max_size;
+                    (Self::BOTTOM_RIGHT, bottom_right)
                 }
+            };
+            let child_loose_bounds = child_aabb.double_about_centre();
+            if !child_loose_bounds.is_containing(aabb) {
+                break;
             }
-            max_size = max_size / 2.
+            let child_offset = match self.nodes[index].child_offset {
+                Some(child_offset) => child_offset.get(),
+                None => {
+                    let free = self.nodes.len();
+                    for _ in 0..Self::NUM_CHILDREN {
+                        self.nodes.push(Default::default());
+                    }
+                    self.nodes[index].child_offset =
+                        Some(NonZeroUsize::new(free).expect("unexpected state"));
+                    free
+                }
+            };
+            index = child_offset + child;
+            node_aabb = child_aabb;
+            loose_bounds = Some(child_loose_bounds);
         }
+        (index, loose_bounds)
     }
 
-    fn for_each_intersection_rec<F: FnMut(&Aabb, &T)>(
-        nodes: &[Node<T>],
+    fn attach(&mut self, handle: BroadphaseHandle, aabb: &Aabb<S>) {
+        let (node, loose_bounds) = self.find_node(aabb);
+        let items = &mut self.nodes[node].items;
+        let entry = self.entries.get_mut(handle);
+        entry.node = node;
+        entry.slot = items.len();
+        entry.loose_bounds = loose_bounds;
+        items.push(handle);
+    }
+
+    fn detach(&mut self, handle: BroadphaseHandle) {
+        let (node, slot) = {
+            let entry = self.entries.get(handle);
+            (entry.node, entry.slot)
+        };
+        let items = &mut self.nodes[node].items;
+        items.swap_remove(slot);
+        if let Some(&moved) = items.get(slot) {
+            self.entries.get_mut(moved).slot = slot;
+        }
+    }
+
+    fn for_each_rec<P, F>(
+        nodes: &[Node],
+        entries: &Entries<Entry<T, S>>,
         current_index: usize,
-        current_node_aabb: Aabb,
-        aabb_to_test: &Aabb,
+        current_node_aabb: Aabb<S>,
+        predicate: &P,
         f: &mut F,
-    ) {
+    ) where
+        P: Fn(&Aabb<S>) -> bool,
+        F: FnMut(&Aabb<S>, &T),
+    {
         if let Some(node) = nodes.get(current_index) {
-            for &(ref aabb, ref t) in node.items.iter() {
-                if aabb.is_intersecting(aabb_to_test) {
-                    f(aabb, t);
+            for &handle in node.items.iter() {
+                let entry = entries.get(handle);
+                if predicate(&entry.aabb) {
+                    f(&entry.aabb, &entry.t);
                 }
             }
             if let Some(child_offset) = node.child_offset {
-                let child_offset = child_offset.get() as usize;
+                let child_offset = child_offset.get();
                 let AabbSplitFour {
                     top_left,
                     top_right,
                     bottom_left,
                     bottom_right,
                 } = current_node_aabb.split_four();
-                if top_left
-                    .double_about_centre()
-                    .is_intersecting(&aabb_to_test)
-                {
-                    Self::for_each_intersection_rec(
+                if predicate(&top_left.double_about_centre()) {
+                    Self::for_each_rec(
                         nodes,
+                        entries,
                         child_offset + Self::TOP_LEFT,
                         top_left,
-                        aabb_to_test,
+                        predicate,
                         f,
                     );
                 }
-                if top_right
-                    .double_about_centre()
-                    .is_intersecting(&aabb_to_test)
-                {
-                    Self::for_each_intersection_rec(
+                if predicate(&top_right.double_about_centre()) {
+                    Self::for_each_rec(
                         nodes,
+                        entries,
                         child_offset + Self::TOP_RIGHT,
                         top_right,
-                        aabb_to_test,
+                        predicate,
                         f,
                     );
                 }
-                if bottom_left
-                    .double_about_centre()
-                    .is_intersecting(&aabb_to_test)
-                {
-                    Self::for_each_intersection_rec(
+                if predicate(&bottom_left.double_about_centre()) {
+                    Self::for_each_rec(
                         nodes,
+                        entries,
                         child_offset + Self::BOTTOM_LEFT,
                         bottom_left,
-                        aabb_to_test,
+                        predicate,
                         f,
                     );
                 }
-                if bottom_right
-                    .double_about_centre()
-                    .is_intersecting(&aabb_to_test)
-                {
-                    Self::for_each_intersection_rec(
+                if predicate(&bottom_right.double_about_centre()) {
+                    Self::for_each_rec(
                         nodes,
+                        entries,
                         child_offset + Self::BOTTOM_RIGHT,
                         bottom_right,
-                        aabb_to_test,
+                        predicate,
                         f,
                     );
                 }
             }
         }
     }
-    pub fn for_each_intersection<F: FnMut(&Aabb, &T)>(&self, aabb: Aabb, mut f: F) {
-        let root_aabb = Aabb::new(vec2(0., 0.), self.size);
-        Self::for_each_intersection_rec(&self.nodes, 0, root_aabb, &aabb, &mut f);
+}
+
+impl<T, S: Scalar> Broadphase<T, S> for LooseQuadTree<T, S> {
+    fn insert(&mut self, aabb: Aabb<S>, t: T) -> BroadphaseHandle {
+        let handle = self.entries.insert(Entry {
+            aabb,
+            t,
+            node: 0,
+            slot: 0,
+            loose_bounds: None,
+        });
+        self.grow_to_contain(&aabb);
+        self.attach(handle, &aabb);
+        handle
+    }
+    fn update(&mut self, handle: BroadphaseHandle, aabb: Aabb<S>) {
+        let within_loose_bounds = {
+            let entry = self.entries.get_mut(handle);
+            entry.aabb = aabb;
+            match entry.loose_bounds {
+                Some(loose_bounds) => loose_bounds.is_containing(&aabb),
+                // it may now fit in a child, or be outside the root
+                None => false,
+            }
+        };
+        if !within_loose_bounds {
+            self.grow_to_contain(&aabb);
+            self.detach(handle);
+            self.attach(handle, &aabb);
+        }
+    }
+    fn remove(&mut self, handle: BroadphaseHandle) -> T {
+        self.detach(handle);
+        self.entries.remove(handle).t
+    }
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.nodes.push(Default::default());
+        self.entries.clear();
+        self.root = None;
+    }
+    fn for_each_intersection<F: FnMut(&Aabb<S>, &T)>(&self, aabb: Aabb<S>, mut f: F) {
+        if let Some(root) = self.root {
+            Self::for_each_rec(
+                &self.nodes,
+                &self.entries,
+                0,
+                root,
+                &|other: &Aabb<S>| other.is_intersecting(&aabb),
+                &mut f,
+            );
+        }
+    }
+    fn for_each_segment_intersection<F: FnMut(&Aabb<S>, &T)>(
+        &self,
+        start: Vector2<S>,
+        end: Vector2<S>,
+        mut f: F,
+    ) {
+        if let Some(root) = self.root {
+            Self::for_each_rec(
+                &self.nodes,
+                &self.entries,
+                0,
+                root,
+                &|other: &Aabb<S>| other.is_intersecting_segment(start, end),
+                &mut f,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aabb(x: f64, y: f64, width: f64, height: f64) -> Aabb<f64> {
+        Aabb::new(vec2(x, y), vec2(width, height))
+    }
+
+    fn intersecting(tree: &LooseQuadTree<u32, f64>, aabb: Aabb<f64>) -> Vec<u32> {
+        let mut found = Vec::new();
+        tree.for_each_intersection(aabb, |_aabb, &t| found.push(t));
+        found.sort();
+        found
+    }
+
+    const NUM_CHILDREN: usize = LooseQuadTree::<u32, f64>::NUM_CHILDREN;
+
+    #[test]
+    fn insert_update_remove() {
+        let mut tree = LooseQuadTree::new();
+        let a = tree.insert(aabb(0., 0., 10., 10.), 1);
+        tree.insert(aabb(100., 100., 10., 10.), 2);
+        assert_eq!(intersecting(&tree, aabb(-5., -5., 10., 10.)), vec![1]);
+        assert_eq!(intersecting(&tree, aabb(0., 0., 200., 200.)), vec![1, 2]);
+        tree.update(a, aabb(95., 95., 10., 10.));
+        assert_eq!(intersecting(&tree, aabb(-5., -5., 10., 10.)), Vec::<u32>::new());
+        assert_eq!(intersecting(&tree, aabb(90., 90., 10., 10.)), vec![1, 2]);
+        assert_eq!(tree.remove(a), 1);
+        assert_eq!(intersecting(&tree, aabb(0., 0., 200., 200.)), vec![2]);
+    }
+
+    #[test]
+    fn root_entries_are_relocated_on_update() {
+        let mut tree = LooseQuadTree::new();
+        // the first entry sets the root, so it only fits in the root node
+        let a = tree.insert(aabb(0., 0., 100., 100.), 1);
+        assert_eq!(tree.entries.get(a).node, 0);
+        tree.update(a, aabb(10., 10., 1., 1.));
+        assert_ne!(tree.entries.get(a).node, 0);
+        assert!(tree.entries.get(a).loose_bounds.is_some());
+        // moving outside the root grows it
+        tree.update(a, aabb(500., 500., 1., 1.));
+        assert!(tree.root.unwrap().is_containing(&aabb(500., 500., 1., 1.)));
+        assert_eq!(intersecting(&tree, aabb(500., 500., 1., 1.)), vec![1]);
+        assert_eq!(intersecting(&tree, aabb(0., 0., 100., 100.)), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn root_grows_toward_negative_coordinates() {
+        let mut tree = LooseQuadTree::new();
+        tree.insert(aabb(0., 0., 1., 1.), 1);
+        tree.insert(aabb(-1000., -500., 1., 1.), 2);
+        tree.insert(aabb(300., -2000., 1., 1.), 3);
+        let root = tree.root.unwrap();
+        assert!(root.top_left().x <= -1000.);
+        assert!(root.top_left().y <= -2000.);
+        assert!(root.is_containing(&aabb(0., 0., 1., 1.)));
+        assert_eq!(intersecting(&tree, aabb(-1000., -500., 1., 1.)), vec![2]);
+        assert_eq!(intersecting(&tree, aabb(300., -2000., 1., 1.)), vec![3]);
+        let everything = aabb(-5000., -5000., 10000., 10000.);
+        assert_eq!(intersecting(&tree, everything), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn growth_is_limited_for_huge_entries() {
+        let mut tree = LooseQuadTree::new();
+        tree.insert(aabb(0., 0., 1., 1.), 1);
+        let huge = tree.insert(aabb(-1e30, -1e30, 2e30, 2e30), 2);
+        // each doubling of the root adds one level of children
+        assert_eq!(tree.nodes.len(), 1 + MAX_GROWTH * NUM_CHILDREN);
+        assert!(!tree.root.unwrap().is_containing(&aabb(-1e30, -1e30, 2e30, 2e30)));
+        // entries which don't fit are kept in the root, so are still found
+        assert_eq!(tree.entries.get(huge).node, 0);
+        assert_eq!(intersecting(&tree, aabb(1e29, 1e29, 1., 1.)), vec![2]);
+        assert_eq!(intersecting(&tree, aabb(0., 0., 1., 1.)), vec![1, 2]);
+    }
+
+    #[test]
+    fn depth_is_limited_for_zero_sized_entries() {
+        let mut tree = LooseQuadTree::new();
+        tree.insert(aabb(0., 0., 64., 64.), 1);
+        for i in 0..10 {
+            tree.insert(aabb(16., 16., 0., 0.), 2 + i);
+        }
+        // only the first descent creates nodes, one level at a time
+        assert_eq!(tree.nodes.len(), 1 + MAX_DEPTH * NUM_CHILDREN);
+        assert_eq!(intersecting(&tree, aabb(16., 16., 0., 0.)).len(), 11);
     }
 }
@@ -1,5 +1,4 @@
-#![feature(nonzero)]
-
+extern crate approx;
 extern crate best;
 extern crate cgmath;
 extern crate fnv;
@@ -8,79 +7,199 @@ extern crate gfx;
 extern crate gfx_device_gl;
 extern crate gfx_window_glutin;
 extern crate glutin;
+extern crate num_traits;
 
 mod aabb;
 mod axis_aligned_rect;
+mod benchmark;
+mod broadphase;
 mod bump;
+mod capsule;
+mod chain;
+mod circle;
 mod collide;
+mod convex_polygon;
 mod game;
 mod glutin_window;
 mod graphics;
+mod layer;
 mod left_solid_edge;
 mod line_segment;
 mod loose_quad_tree;
+mod material;
 mod movement;
+mod oriented_rect;
+mod overlap;
+mod predicates;
+mod query;
+mod raycast;
+mod round;
+mod scalar;
 mod shape;
+mod spatial_hash;
+mod sweep_and_prune;
+mod tile_map;
+mod tolerances;
 
-use cgmath::vec2;
+use broadphase::AnyBroadphase;
+use cgmath::Vector2;
 use game::{GameState, GameStateChanges, InputModel};
 use gfx::Device;
-use glutin::GlContext;
+use glutin::{GlContext, VirtualKeyCode};
 use glutin_window::GlutinWindow;
-use graphics::Renderer;
-use movement::MovementContext;
+use graphics::{FrameUpdater, Renderer};
+use movement::{EntityId, MovementContext};
+use scalar::{Fixed, Scalar};
 use shape::Shape;
+use std::env;
 
 enum ExternalEvent {
     Quit,
     Reset,
 }
 
-fn process_input(
+fn process_input<S: Scalar>(
     events_loop: &mut glutin::EventsLoop,
-    input_model: &mut InputModel,
+    input_model: &mut InputModel<S>,
 ) -> Option<ExternalEvent> {
     let mut external_event = None;
 
-    events_loop.poll_events(|event| match event {
-        glutin::Event::WindowEvent { event, .. } => match event {
-            glutin::WindowEvent::CloseRequested => {
-                external_event = Some(ExternalEvent::Quit);
-            }
-            glutin::WindowEvent::KeyboardInput { input, .. } => {
-                if let Some(virtual_keycode) = input.virtual_keycode {
-                    match input.state {
-                        glutin::ElementState::Pressed => match virtual_keycode {
-                            glutin::VirtualKeyCode::Return => {
+    events_loop.poll_events(|event| {
+        if let glutin::Event::WindowEvent { event, .. } = event {
+            match event {
+                glutin::WindowEvent::CloseRequested => {
+                    external_event = Some(ExternalEvent::Quit);
+                }
+                glutin::WindowEvent::KeyboardInput { input, .. } => {
+                    if let Some(virtual_keycode) = input.virtual_keycode {
+                        let pressed = input.state == glutin::ElementState::Pressed;
+                        let value = if pressed { S::one() } else { S::zero() };
+                        match virtual_keycode {
+                            VirtualKeyCode::Return if pressed => {
                                 external_event = Some(ExternalEvent::Reset)
                             }
-                            glutin::VirtualKeyCode::Left => input_model.set_left(1.),
-                            glutin::VirtualKeyCode::Right => input_model.set_right(1.),
-                            glutin::VirtualKeyCode::Up => input_model.set_up(1.),
-                            glutin::VirtualKeyCode::Down => input_model.set_down(1.),
-                            glutin::VirtualKeyCode::Space => input_model.set_jump(true),
-                            _ => (),
-                        },
-                        glutin::ElementState::Released => match virtual_keycode {
-                            glutin::VirtualKeyCode::Left => input_model.set_left(0.),
-                            glutin::VirtualKeyCode::Right => input_model.set_right(0.),
-                            glutin::VirtualKeyCode::Up => input_model.set_up(0.),
-                            glutin::VirtualKeyCode::Down => input_model.set_down(0.),
-                            glutin::VirtualKeyCode::Space => input_model.set_jump(false),
+                            VirtualKeyCode::Left => input_model.set_left(value),
+                            VirtualKeyCode::Right => input_model.set_right(value),
+                            VirtualKeyCode::Up => input_model.set_up(value),
+                            VirtualKeyCode::Down => input_model.set_down(value),
+                            VirtualKeyCode::Space => input_model.set_jump(pressed),
                             _ => (),
-                        },
+                        }
                     }
                 }
+                _ => (),
             }
-            _ => (),
-        },
-        _ => (),
+        }
     });
 
     external_event
 }
 
+fn render_shape<S: Scalar>(
+    updater: &mut FrameUpdater,
+    shape: &Shape<S>,
+    position: Vector2<S>,
+    colour: [f32; 3],
+) {
+    match *shape {
+        Shape::AxisAlignedRect(ref rect) => updater.axis_aligned_rect(
+            position.cast().unwrap(),
+            rect.dimensions().cast().unwrap(),
+            colour,
+        ),
+        Shape::LineSegment(ref line_segment) => updater.line_segment(
+            (line_segment.start + position).cast().unwrap(),
+            (line_segment.end + position).cast().unwrap(),
+            colour,
+        ),
+        Shape::ConvexPolygon(ref convex_polygon) => updater.polygon(
+            convex_polygon
+                .vertices()
+                .iter()
+                .map(|&v| (v + position).cast().unwrap()),
+            colour,
+        ),
+        Shape::OrientedRect(ref oriented_rect) => updater.polygon(
+            oriented_rect
+                .corners()
+                .iter()
+                .map(|&v| (v + position).cast().unwrap()),
+            colour,
+        ),
+        Shape::TileMap(ref tile_map) => for edge in tile_map.edges() {
+            updater.line_segment(
+                (edge.start() + position).cast().unwrap(),
+                (edge.end() + position).cast().unwrap(),
+                colour,
+            );
+        },
+        Shape::Chain(ref chain) => {
+            let vertices = chain
+                .vertices()
+                .iter()
+                .map(|&v| (v + position).cast().unwrap());
+            if chain.is_closed() {
+                updater.polygon(vertices, colour);
+            } else {
+                updater.polyline(vertices, colour);
+            }
+        }
+        Shape::Circle(ref circle) => updater.circle(
+            position.cast().unwrap(),
+            circle.radius().as_f64() as f32,
+            colour,
+        ),
+        Shape::Capsule(ref capsule) => updater.capsule(
+            (capsule.start + position).cast().unwrap(),
+            (capsule.end + position).cast().unwrap(),
+            capsule.radius().as_f64() as f32,
+            colour,
+        ),
+        Shape::Compound(ref children) => for &(offset, ref child) in children.iter() {
+            render_shape(updater, child, position + offset, colour);
+        },
+    }
+}
+
 fn main() {
+    let mut broadphase_name = None;
+    let mut fixed_point = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--benchmark" => {
+                benchmark::run();
+                return;
+            }
+            "--broadphase" => {
+                let name = args.next().expect("Missing broadphase name");
+                let names = AnyBroadphase::<EntityId, f64>::NAMES;
+                if !names.contains(&name.as_str()) {
+                    panic!(
+                        "Unknown broadphase {} (expected one of {})",
+                        name,
+                        names.join(", ")
+                    );
+                }
+                broadphase_name = Some(name);
+            }
+            "--fixed-point" => fixed_point = true,
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+    if fixed_point {
+        run::<Fixed>(broadphase_name);
+    } else {
+        run::<f64>(broadphase_name);
+    }
+}
+
+fn run<S: Scalar>(broadphase_name: Option<String>) {
+    let broadphase = match broadphase_name {
+        Some(name) => AnyBroadphase::from_name(&name).expect("Unknown broadphase"),
+        None => AnyBroadphase::default(),
+    };
+
     let width = 960;
     let height = 640;
     let GlutinWindow {
@@ -96,39 +215,34 @@ fn main() {
     let mut renderer =
         Renderer::new(render_target_view.clone(), &mut factory, &mut encoder);
 
-    let mut game_state = GameState::new(vec2(width as f64, height as f64));
+    let mut game_state = GameState::<S>::new();
+    game_state.set_broadphase(broadphase);
     let mut game_changes = GameStateChanges::default();
-    game_state.init_demo();
-
     let mut input_model = InputModel::default();
     let mut movement_context = MovementContext::default();
+    game_state.init_demo(&mut movement_context);
+    let mut title = String::new();
 
     loop {
         encoder.clear(&render_target_view, [0.0, 0.0, 0.0, 1.0]);
         match process_input(&mut events_loop, &mut input_model) {
             Some(ExternalEvent::Quit) => break,
-            Some(ExternalEvent::Reset) => game_state.init_demo(),
+            Some(ExternalEvent::Reset) => game_state.init_demo(&mut movement_context),
             None => (),
         }
         input_model.after_process();
 
         game_state.update(&input_model, &mut game_changes, &mut movement_context);
+        let status = game_state.status(&movement_context);
+        if status != title {
+            window.set_title(&status);
+            title = status;
+        }
         {
             let mut frame = renderer.prepare_frame(&mut factory);
             let mut updater = frame.updater();
             for update in game_state.render_updates() {
-                match update.shape {
-                    &Shape::AxisAlignedRect(ref rect) => updater.axis_aligned_rect(
-                        update.position.cast().unwrap(),
-                        rect.dimensions().cast().unwrap(),
-                        update.colour,
-                    ),
-                    &Shape::LineSegment(ref line_segment) => updater.line_segment(
-                        (line_segment.start + update.position).cast().unwrap(),
-                        (line_segment.end + update.position).cast().unwrap(),
-                        update.colour,
-                    ),
-                }
+                render_shape(&mut updater, update.shape, update.position, update.colour);
             }
         }
         renderer.encode(&mut encoder);
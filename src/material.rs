@@ -0,0 +1,113 @@
+use scalar::{real, Scalar};
+
+pub type MaterialId = u8;
+
+pub const DEFAULT: MaterialId = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material<S> {
+    pub name: String,
+    // fraction of the sliding movement lost per unit of movement into a surface
+    pub friction: S,
+    // fraction of the movement into a surface returned as velocity away from it
+    pub restitution: S,
+}
+
+#[derive(Debug, Clone)]
+pub struct MaterialRegistry<S> {
+    materials: Vec<Material<S>>,
+}
+
+impl<S: Scalar> Default for MaterialRegistry<S> {
+    fn default() -> Self {
+        Self {
+            materials: vec![
+                Material {
+                    name: "default".to_string(),
+                    friction: real(0.),
+                    restitution: real(0.),
+                },
+            ],
+        }
+    }
+}
+
+impl<S: Scalar> MaterialRegistry<S> {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+    // adding a material with an existing name replaces its properties
+    pub fn add_material(
+        &mut self,
+        name: &str,
+        friction: S,
+        restitution: S,
+    ) -> MaterialId {
+        let material = Material {
+            name: name.to_string(),
+            friction,
+            restitution,
+        };
+        if let Some(id) = self.id(name) {
+            self.materials[id as usize] = material;
+            return id;
+        }
+        assert!(
+            self.materials.len() <= MaterialId::MAX as usize,
+            "Too many materials"
+        );
+        self.materials.push(material);
+        (self.materials.len() - 1) as MaterialId
+    }
+    pub fn id(&self, name: &str) -> Option<MaterialId> {
+        self.materials
+            .iter()
+            .position(|material| material.name == name)
+            .map(|index| index as MaterialId)
+    }
+    pub fn material(&self, id: MaterialId) -> &Material<S> {
+        self.materials
+            .get(id as usize)
+            .expect("Invalid material id")
+    }
+    // the mean, so a frictionless material doesn't cancel the other's friction
+    pub fn friction(&self, a: MaterialId, b: MaterialId) -> S {
+        (self.material(a).friction + self.material(b).friction) / real(2.)
+    }
+    pub fn restitution(&self, a: MaterialId, b: MaterialId) -> S {
+        self.material(a)
+            .restitution
+            .max(self.material(b).restitution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn materials_combine_mean_friction_and_max_restitution() {
+        let mut registry = MaterialRegistry::<f64>::default();
+        let ice = registry.add_material("ice", real(0.1), real(0.));
+        let rubber = registry.add_material("rubber", real(0.9), real(0.8));
+        assert_eq!(registry.friction(ice, rubber), 0.5);
+        assert_eq!(registry.friction(rubber, ice), 0.5);
+        assert_eq!(registry.friction(ice, DEFAULT), 0.05);
+        assert_eq!(registry.restitution(ice, rubber), 0.8);
+        assert_eq!(registry.restitution(rubber, ice), 0.8);
+        assert_eq!(registry.restitution(ice, DEFAULT), 0.);
+    }
+
+    #[test]
+    fn materials_are_looked_up_and_replaced_by_name() {
+        let mut registry = MaterialRegistry::<f64>::default();
+        assert_eq!(registry.id("default"), Some(DEFAULT));
+        let ice = registry.add_material("ice", real(0.1), real(0.));
+        assert_eq!(registry.id("ice"), Some(ice));
+        assert_eq!(registry.add_material("ice", real(0.2), real(0.5)), ice);
+        assert_eq!(registry.material(ice).friction, 0.2);
+        assert_eq!(registry.material(ice).restitution, 0.5);
+        registry.clear();
+        assert_eq!(registry.id("ice"), None);
+    }
+}
@@ -1,92 +1,251 @@
 use aabb::Aabb;
 use best::BestMultiSet;
-use bump::max_bump;
+use bump::{max_bump, BumpKind};
 use cgmath::{vec2, InnerSpace, Vector2};
-use collide::Collision;
+use collide::{Collision, Collisions};
+use layer::{CollisionMatrix, Response};
+use material::{Material, MaterialRegistry};
+use overlap;
+use scalar::{real, Scalar};
 use shape::ShapePosition;
 use std::cmp::Ordering;
+use tolerances::Tolerances;
 
-const BELOW_TEST_MOVEMENT: Vector2<f64> = Vector2 { x: 0., y: 1. };
+const BELOW_TEST_DISTANCE: f64 = 1.;
+
+const MAX_DEPENETRATION_ITERATIONS: usize = 8;
 
 #[derive(Default)]
-pub struct MovementContext {
-    closest_collisions: BestMultiSet<Collision>,
+pub struct MovementContext<S: Scalar> {
+    closest_collisions: BestMultiSet<Collision<S>>,
+    reported_collisions: Vec<Collision<S>>,
+    collision_matrix: CollisionMatrix,
+    materials: MaterialRegistry<S>,
+    tolerances: Tolerances<S>,
+    num_depenetrations: u64,
 }
 
-pub type ClosestCollisions<'a> = &'a BestMultiSet<Collision>;
+pub type ClosestCollisions<'a, S> = &'a BestMultiSet<Collision<S>>;
 
-pub struct CollisionsBelow<'a>(ClosestCollisions<'a>);
+pub struct CollisionsBelow<'a, S> {
+    collisions: ClosestCollisions<'a, S>,
+    materials: &'a MaterialRegistry<S>,
+    min_walkable_cos: S,
+    tolerances: &'a Tolerances<S>,
+}
 
-impl<'a> CollisionsBelow<'a> {
+impl<'a, S: Scalar> CollisionsBelow<'a, S> {
+    fn walkable(&self) -> impl Iterator<Item = &Collision<S>> {
+        let min_walkable_cos = self.min_walkable_cos - self.tolerances.slope_cos;
+        self.collisions
+            .iter()
+            .filter(move |collision| slope_cos(collision) >= min_walkable_cos)
+    }
+    fn flattest_walkable(&self) -> Option<&Collision<S>> {
+        self.walkable().max_by(|a, b| {
+            slope_cos(a)
+                .partial_cmp(&slope_cos(b))
+                .unwrap_or(Ordering::Equal)
+        })
+    }
+    // angle in radians between the flattest surface below and the horizontal
+    #[allow(dead_code)]
+    pub fn ground_angle(&self) -> Option<S> {
+        self.collisions
+            .iter()
+            .map(slope_cos)
+            .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|cos| cos.min(S::one()).acos())
+    }
+    pub fn is_grounded(&self) -> bool {
+        self.walkable().next().is_some()
+    }
     pub fn can_jump(&self) -> bool {
-        !self.0.is_empty()
+        self.is_grounded()
+    }
+    pub fn ground_normal(&self) -> Option<Vector2<S>> {
+        self.flattest_walkable()
+            .map(|collision| collision.contact.normal)
+    }
+    #[allow(dead_code)]
+    pub fn ground_material(&self) -> Option<&Material<S>> {
+        self.flattest_walkable()
+            .map(|collision| self.materials.material(collision.stationary_material))
+    }
+    // combined friction of the moving shape and the ground
+    pub fn ground_friction(&self) -> Option<S> {
+        self.flattest_walkable().map(|collision| {
+            self.materials
+                .friction(collision.moving_material, collision.stationary_material)
+        })
     }
     pub fn max_velocity(
         &self,
-        get_velocity: impl Fn(EntityId) -> Option<Vector2<f64>>,
-    ) -> Option<Vector2<f64>> {
-        if self.0.is_empty() {
+        get_velocity: impl Fn(EntityId) -> Option<Vector2<S>>,
+    ) -> Option<Vector2<S>> {
+        if !self.is_grounded() {
             return None;
         }
         Some(
-            self.0
-                .iter()
+            self.walkable()
                 .filter_map(|collision| get_velocity(collision.stationary_entity_id))
                 .max_by(|a, b| {
                     a.magnitude2()
                         .partial_cmp(&b.magnitude2())
                         .unwrap_or(Ordering::Equal)
                 })
-                .unwrap_or(vec2(0., 0.)),
+                .unwrap_or(vec2(real(0.), real(0.))),
         )
     }
 }
 
+// the cosine of the slope's angle, compared against a precomputed threshold
+// rather than taking its acos, which isn't bit-identical across platforms
+fn slope_cos<S: Scalar>(collision: &Collision<S>) -> S {
+    -collision.contact.normal.y
+}
+
 pub type EntityId = u32;
 
-pub trait ForEachShapePosition {
-    fn for_each<F: FnMut(ShapePosition)>(&self, aabb: Aabb, f: F);
+pub trait ForEachShapePosition<S: Scalar> {
+    fn for_each<F: FnMut(ShapePosition<S>)>(&self, aabb: Aabb<S>, f: F);
+    fn for_each_along_segment<F: FnMut(ShapePosition<S>)>(
+        &self,
+        start: Vector2<S>,
+        end: Vector2<S>,
+        f: F,
+    ) {
+        self.for_each(Aabb::from_points(&[start, end]), f);
+    }
 }
 
-pub struct Movement {
-    pub position: Vector2<f64>,
-    pub velocity: Vector2<f64>,
+// how a moving body responds when it is blocked
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResponseMode<S> {
+    #[default]
+    Slide,
+    Stop,
+    Reflect { restitution: S },
 }
 
-pub struct Displacement {
-    pub movement: Vector2<f64>,
-    pub velocity: Vector2<f64>,
+pub const DEFAULT_STEP_HEIGHT: f64 = 2.;
+pub const DEFAULT_CEILING_TOLERANCE: f64 = 0.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovementProperties<S> {
+    pub response_mode: ResponseMode<S>,
+    // highest ledge that edges with bump flags will step up onto
+    pub step_height: S,
+    // furthest that edges with corner flags will be nudged around a corner
+    pub ceiling_tolerance: S,
 }
 
-impl Displacement {
-    pub fn combine_velocity(&self, current_velocity: Vector2<f64>) -> Vector2<f64> {
+impl<S: Scalar> Default for MovementProperties<S> {
+    fn default() -> Self {
+        Self {
+            response_mode: ResponseMode::default(),
+            step_height: real(DEFAULT_STEP_HEIGHT),
+            ceiling_tolerance: real(DEFAULT_CEILING_TOLERANCE),
+        }
+    }
+}
+
+impl<S: Scalar> MovementProperties<S> {
+    pub fn with_response_mode(self, response_mode: ResponseMode<S>) -> Self {
+        Self {
+            response_mode,
+            ..self
+        }
+    }
+    pub fn with_step_height(self, step_height: S) -> Self {
+        Self {
+            step_height,
+            ..self
+        }
+    }
+    pub fn with_ceiling_tolerance(self, ceiling_tolerance: S) -> Self {
+        Self {
+            ceiling_tolerance,
+            ..self
+        }
+    }
+}
+
+pub struct Movement<S> {
+    pub position: Vector2<S>,
+    // bodies which stop or reflect report their velocity after the last contact
+    pub velocity: Vector2<S>,
+    // total distance stepped up onto ledges during the movement
+    pub step_up: Option<S>,
+}
+
+pub struct Displacement<S> {
+    pub movement: Vector2<S>,
+    pub velocity: Vector2<S>,
+}
+
+impl<S: Scalar> Displacement<S> {
+    pub fn combine_velocity(&self, current_velocity: Vector2<S>) -> Vector2<S> {
         let lateral_direction = vec2(self.velocity.y, -self.velocity.x);
         let lateral_component = current_velocity.project_on(lateral_direction);
         self.velocity + lateral_component
     }
 }
 
-impl MovementContext {
+impl<S: Scalar> MovementContext<S> {
+    pub fn collision_matrix(&self) -> &CollisionMatrix {
+        &self.collision_matrix
+    }
+    pub fn collision_matrix_mut(&mut self) -> &mut CollisionMatrix {
+        &mut self.collision_matrix
+    }
+    pub fn materials_mut(&mut self) -> &mut MaterialRegistry<S> {
+        &mut self.materials
+    }
+    pub fn tolerances(&self) -> &Tolerances<S> {
+        &self.tolerances
+    }
+    #[allow(dead_code)]
+    pub fn tolerances_mut(&mut self) -> &mut Tolerances<S> {
+        &mut self.tolerances
+    }
+    // non-blocking collisions found by the most recent movement
+    pub fn reported_collisions(&self) -> &[Collision<S>] {
+        &self.reported_collisions
+    }
+    // number of times a shape has been pushed out of solid geometry
+    pub fn num_depenetrations(&self) -> u64 {
+        self.num_depenetrations
+    }
+    fn collisions(&mut self) -> Collisions<'_, S> {
+        Collisions {
+            collision_matrix: &self.collision_matrix,
+            tolerances: &self.tolerances,
+            closest: &mut self.closest_collisions,
+            reported: &mut self.reported_collisions,
+        }
+    }
     fn for_each_collision<F, G>(
         &mut self,
-        shape_position: ShapePosition,
-        movement: Vector2<f64>,
+        shape_position: ShapePosition<S>,
+        movement: Vector2<S>,
         for_each_shape_position: &F,
         mut f: G,
     ) where
-        F: ForEachShapePosition,
-        G: FnMut(EntityId, Collision),
+        F: ForEachShapePosition<S>,
+        G: FnMut(EntityId, Collision<S>),
     {
         self.closest_collisions.clear();
+        self.reported_collisions.clear();
         for_each_shape_position.for_each(
             shape_position.movement_aabb(movement),
-            |other_shape_position: ShapePosition| {
+            |other_shape_position: ShapePosition<S>| {
                 let other_entity_id = other_shape_position.entity_id;
                 if other_entity_id != shape_position.entity_id {
                     shape_position.movement_collision_test(
                         other_shape_position,
                         movement,
-                        &mut self.closest_collisions,
+                        &mut self.collisions(),
                     );
                     if let Some(collision) = self.closest_collisions.drain().next() {
                         f(other_entity_id, collision);
@@ -95,56 +254,167 @@ impl MovementContext {
             },
         );
     }
-    fn closest_collisions<F>(
+    fn update_closest_collisions<F>(
         &mut self,
-        shape_position: ShapePosition,
-        movement: Vector2<f64>,
+        shape_position: ShapePosition<S>,
+        movement: Vector2<S>,
         for_each_shape_position: &F,
-    ) -> ClosestCollisions
-    where
-        F: ForEachShapePosition,
+    ) where
+        F: ForEachShapePosition<S>,
     {
         self.closest_collisions.clear();
+        let num_reported = self.reported_collisions.len();
         for_each_shape_position.for_each(
             shape_position.movement_aabb(movement),
-            |other_shape_position: ShapePosition| {
+            |other_shape_position: ShapePosition<S>| {
                 if other_shape_position.entity_id != shape_position.entity_id {
                     shape_position.movement_collision_test(
                         other_shape_position,
                         movement,
-                        &mut self.closest_collisions,
+                        &mut self.collisions(),
                     );
                 }
             },
         );
-
+        self.prune_reported_collisions(num_reported);
+    }
+    // drop reports beyond the blocking collision, and repeats of earlier reports
+    fn prune_reported_collisions(&mut self, num_reported: usize) {
+        let max_movement_multiplier = self.closest_collisions
+            .first()
+            .map(|closest| closest.left_solid_edge_collision.movement_multiplier())
+            .unwrap_or(real(1.));
+        let new_collisions = self.reported_collisions.split_off(num_reported);
+        for collision in new_collisions {
+            if collision.left_solid_edge_collision.movement_multiplier()
+                > max_movement_multiplier + self.tolerances.simultaneous_time
+            {
+                continue;
+            }
+            let is_repeat = self.reported_collisions.iter().any(|reported| {
+                reported.stationary_entity_id == collision.stationary_entity_id
+                    && reported.stationary_child == collision.stationary_child
+                    && reported.moving_child == collision.moving_child
+            });
+            if !is_repeat {
+                self.reported_collisions.push(collision);
+            }
+        }
+    }
+    fn closest_collisions<F>(
+        &mut self,
+        shape_position: ShapePosition<S>,
+        movement: Vector2<S>,
+        for_each_shape_position: &F,
+    ) -> ClosestCollisions<'_, S>
+    where
+        F: ForEachShapePosition<S>,
+    {
+        self.update_closest_collisions(shape_position, movement, for_each_shape_position);
         &self.closest_collisions
     }
 
     pub fn collisions_below<F>(
         &mut self,
-        shape_position: ShapePosition,
+        shape_position: ShapePosition<S>,
+        min_walkable_cos: S,
         for_each_shape_position: &F,
-    ) -> CollisionsBelow
+    ) -> CollisionsBelow<'_, S>
     where
-        F: ForEachShapePosition,
+        F: ForEachShapePosition<S>,
     {
-        CollisionsBelow(self.closest_collisions(
+        self.update_closest_collisions(
             shape_position,
-            BELOW_TEST_MOVEMENT,
+            vec2(real(0.), real(BELOW_TEST_DISTANCE)),
             for_each_shape_position,
-        ))
+        );
+        self.reported_collisions.clear();
+        CollisionsBelow {
+            collisions: &self.closest_collisions,
+            materials: &self.materials,
+            min_walkable_cos,
+            tolerances: &self.tolerances,
+        }
+    }
+    // translation which pushes the shape out of any blocking shapes it overlaps
+    pub fn depenetration<F>(
+        &mut self,
+        shape_position: ShapePosition<S>,
+        for_each_shape_position: &F,
+    ) -> Option<Vector2<S>>
+    where
+        F: ForEachShapePosition<S>,
+    {
+        let mut correction = vec2(real(0.), real(0.));
+        for _ in 0..MAX_DEPENETRATION_ITERATIONS {
+            let current = ShapePosition {
+                position: shape_position.position + correction,
+                ..shape_position
+            };
+            let mut deepest: Option<Vector2<S>> = None;
+            let collision_matrix = &self.collision_matrix;
+            let tolerances = &self.tolerances;
+            for_each_shape_position.for_each(
+                current.shape.aabb(current.position),
+                |other: ShapePosition<S>| {
+                    if other.entity_id == current.entity_id
+                        || collision_matrix.response(current.layer, other.layer)
+                            != Response::Block
+                    {
+                        return;
+                    }
+                    if let Some(penetration) =
+                        overlap::shape_penetration(&current, &other, tolerances)
+                    {
+                        if deepest.is_none_or(|deepest| {
+                            penetration.magnitude2() > deepest.magnitude2()
+                        }) {
+                            deepest = Some(penetration);
+                        }
+                    }
+                },
+            );
+            match deepest {
+                Some(penetration) => correction += penetration,
+                None => break,
+            }
+        }
+        if correction == vec2(real(0.), real(0.)) {
+            return None;
+        }
+        self.num_depenetrations += 1;
+        Some(correction)
     }
+    #[allow(dead_code)]
     pub fn position_after_allowed_movement<F>(
         &mut self,
-        shape_position: ShapePosition,
-        movement: Vector2<f64>,
+        shape_position: ShapePosition<S>,
+        movement: Vector2<S>,
         for_each_shape_position: &F,
-    ) -> Movement
+    ) -> Movement<S>
     where
-        F: ForEachShapePosition,
+        F: ForEachShapePosition<S>,
     {
-        let mut state = MovementStateMachine::new(movement, shape_position.position);
+        self.position_after_allowed_movement_with_properties(
+            shape_position,
+            movement,
+            MovementProperties::default(),
+            for_each_shape_position,
+        )
+    }
+    pub fn position_after_allowed_movement_with_properties<F>(
+        &mut self,
+        shape_position: ShapePosition<S>,
+        movement: Vector2<S>,
+        properties: MovementProperties<S>,
+        for_each_shape_position: &F,
+    ) -> Movement<S>
+    where
+        F: ForEachShapePosition<S>,
+    {
+        self.reported_collisions.clear();
+        let mut state =
+            MovementStateMachine::new(movement, shape_position.position, properties);
         let env = MovementEnv {
             for_each_shape_position,
             original: shape_position,
@@ -157,12 +427,12 @@ impl MovementContext {
     }
     pub fn displacement_after_movement<F>(
         &mut self,
-        shape_position: ShapePosition,
-        movement: Vector2<f64>,
+        shape_position: ShapePosition<S>,
+        movement: Vector2<S>,
         for_each_shape_position: &F,
-        displacements: &mut Vec<(EntityId, Displacement)>,
+        displacements: &mut Vec<(EntityId, Displacement<S>)>,
     ) where
-        F: ForEachShapePosition,
+        F: ForEachShapePosition<S>,
     {
         self.for_each_collision(
             shape_position,
@@ -185,24 +455,36 @@ impl MovementContext {
     }
 }
 
-struct MovementEnv<'a, F: 'a + ForEachShapePosition> {
+struct MovementEnv<'a, S: 'a + Scalar, F: 'a + ForEachShapePosition<S>> {
     for_each_shape_position: &'a F,
-    original: ShapePosition<'a>,
+    original: ShapePosition<'a, S>,
 }
 
-impl<'a, F: ForEachShapePosition> MovementEnv<'a, F> {
-    fn shape_position(&self, position: Vector2<f64>) -> ShapePosition {
+impl<'a, S: Scalar, F: ForEachShapePosition<S>> MovementEnv<'a, S, F> {
+    fn shape_position(&self, position: Vector2<S>) -> ShapePosition<'_, S> {
         ShapePosition {
             position,
             ..self.original
         }
     }
+    fn update_closest_collisions(
+        &self,
+        position: Vector2<S>,
+        movement: Vector2<S>,
+        ctx: &mut MovementContext<S>,
+    ) {
+        ctx.update_closest_collisions(
+            self.shape_position(position),
+            movement,
+            self.for_each_shape_position,
+        );
+    }
     fn closest_collisions<'b>(
         &self,
-        position: Vector2<f64>,
-        movement: Vector2<f64>,
-        ctx: &'b mut MovementContext,
-    ) -> ClosestCollisions<'b> {
+        position: Vector2<S>,
+        movement: Vector2<S>,
+        ctx: &'b mut MovementContext<S>,
+    ) -> ClosestCollisions<'b, S> {
         ctx.closest_collisions(
             self.shape_position(position),
             movement,
@@ -211,59 +493,99 @@ impl<'a, F: ForEachShapePosition> MovementEnv<'a, F> {
     }
 }
 
-struct MovementStateMachine {
-    movement: Vector2<f64>,
-    position: Vector2<f64>,
-    bump: Option<Vector2<f64>>,
-    velocity_correction: Vector2<f64>,
+// mirrors the part of the vector moving into the surface, scaled by restitution
+fn reflect<S: Scalar>(
+    vector: Vector2<S>,
+    normal: Vector2<S>,
+    restitution: S,
+) -> Vector2<S> {
+    let into_surface = (-vector.dot(normal)).max(real(0.));
+    vector + normal * into_surface * (S::one() + restitution)
+}
+
+struct PendingBump<S> {
+    vector: Vector2<S>,
+    kind: BumpKind,
+    // movement to use instead if there's no room to bump
+    slide: Vector2<S>,
+}
+
+struct MovementStateMachine<S> {
+    movement: Vector2<S>,
+    position: Vector2<S>,
+    bump: Option<PendingBump<S>>,
+    velocity_correction: Vector2<S>,
+    properties: MovementProperties<S>,
+    response_velocity: Vector2<S>,
+    step_up: S,
     remaining: u8,
 }
 
-impl MovementStateMachine {
-    fn new(movement: Vector2<f64>, position: Vector2<f64>) -> Self {
+impl<S: Scalar> MovementStateMachine<S> {
+    fn new(
+        movement: Vector2<S>,
+        position: Vector2<S>,
+        properties: MovementProperties<S>,
+    ) -> Self {
         const MAX_ITERATIONS: u8 = 16;
         Self {
             movement,
             position,
             bump: None,
-            velocity_correction: vec2(0., 0.),
+            velocity_correction: vec2(real(0.), real(0.)),
+            properties,
+            response_velocity: movement,
+            step_up: real(0.),
             remaining: MAX_ITERATIONS,
         }
     }
-    fn to_movement(&self, original_position: Vector2<f64>) -> Movement {
+    fn to_movement(&self, original_position: Vector2<S>) -> Movement<S> {
+        let velocity = match self.properties.response_mode {
+            ResponseMode::Slide => {
+                self.position - original_position + self.velocity_correction
+            }
+            ResponseMode::Stop | ResponseMode::Reflect { .. } => self.response_velocity,
+        };
         Movement {
             position: self.position,
-            velocity: self.position - original_position + self.velocity_correction,
+            velocity,
+            step_up: if self.step_up > real(0.) {
+                Some(self.step_up)
+            } else {
+                None
+            },
         }
     }
     fn step<F>(
         &mut self,
-        env: &MovementEnv<F>,
-        ctx: &mut MovementContext,
-    ) -> Option<Movement>
+        env: &MovementEnv<S, F>,
+        ctx: &mut MovementContext<S>,
+    ) -> Option<Movement<S>>
     where
-        F: ForEachShapePosition,
+        F: ForEachShapePosition<S>,
     {
         if self.remaining == 0 {
             return Some(self.to_movement(env.original.position));
         }
-        match self.bump {
+        match self.bump.take() {
             Some(bump) => {
-                let closest = env.closest_collisions(self.position, bump, ctx);
+                let closest = env.closest_collisions(self.position, bump.vector, ctx);
                 match closest.first() {
                     Some(_closest) => {
-                        return Some(self.to_movement(env.original.position))
+                        self.movement = bump.slide;
                     }
                     None => {
-                        self.position += bump;
-                        self.velocity_correction -= bump;
-                        self.bump = None;
+                        self.position += bump.vector;
+                        self.velocity_correction -= bump.vector;
+                        if bump.kind == BumpKind::Step {
+                            self.step_up += bump.vector.magnitude();
+                        }
                     }
                 }
             }
             None => {
-                let closest_collisions =
-                    env.closest_collisions(self.position, self.movement, ctx);
+                env.update_closest_collisions(self.position, self.movement, ctx);
+                let closest_collisions = &ctx.closest_collisions;
                 match closest_collisions.first() {
                     None => {
                         self.position += self.movement;
@@ -273,14 +595,52 @@ impl MovementStateMachine {
                         self.position += closest
                             .left_solid_edge_collision
                             .movement_to_collision(self.movement);
-                        match max_bump(closest_collisions) {
-                            None => {
-                                self.movement = closest
-                                    .left_solid_edge_collision
-                                    .slide(self.movement);
+                        match self.properties.response_mode {
+                            ResponseMode::Slide => (),
+                            ResponseMode::Stop => {
+                                self.response_velocity = vec2(real(0.), real(0.));
+                                return Some(self.to_movement(env.original.position));
                             }
+                            ResponseMode::Reflect { restitution } => {
+                                let restitution =
+                                    restitution.max(ctx.materials.restitution(
+                                        closest.moving_material,
+                                        closest.stationary_material,
+                                    ));
+                                let normal = closest.contact.normal;
+                                self.movement = reflect(
+                                    closest
+                                        .left_solid_edge_collision
+                                        .movement_following_collision(self.movement),
+                                    normal,
+                                    restitution,
+                                );
+                                self.response_velocity =
+                                    reflect(self.response_velocity, normal, restitution);
+                                self.remaining -= 1;
+                                return None;
+                            }
+                        }
+                        let friction = ctx.materials.friction(
+                            closest.moving_material,
+                            closest.stationary_material,
+                        );
+                        let slide = closest
+                            .left_solid_edge_collision
+                            .slide_with_friction(self.movement, friction);
+                        match max_bump(
+                            closest_collisions,
+                            self.properties.step_height,
+                            self.properties.ceiling_tolerance,
+                            &ctx.tolerances,
+                        ) {
+                            None => self.movement = slide,
                             Some(max_bump) => {
-                                self.bump = Some(max_bump.vector());
+                                self.bump = Some(PendingBump {
+                                    vector: max_bump.vector(),
+                                    kind: max_bump.kind(),
+                                    slide,
+                                });
                                 self.movement = closest
                                     .left_solid_edge_collision
                                     .movement_following_collision(self.movement);
@@ -294,3 +654,349 @@ impl MovementStateMachine {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axis_aligned_rect::AxisAlignedRect;
+    use chain::Chain;
+    use circle::Circle;
+    use collide::EdgeProperties;
+    use layer;
+    use line_segment::LineSegment;
+    use material;
+    use shape::Shape;
+
+    struct World(Vec<(Vector2<f64>, Shape<f64>)>);
+
+    impl ForEachShapePosition<f64> for World {
+        fn for_each<F: FnMut(ShapePosition<f64>)>(&self, aabb: Aabb<f64>, mut f: F) {
+            for (i, &(position, ref shape)) in self.0.iter().enumerate() {
+                if shape.aabb(position).is_intersecting(&aabb) {
+                    f(ShapePosition {
+                        entity_id: i as EntityId,
+                        layer: layer::DEFAULT,
+                        material: material::DEFAULT,
+                        position,
+                        shape,
+                    });
+                }
+            }
+        }
+    }
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> (Vector2<f64>, Shape<f64>) {
+        let size = vec2(real(width), real(height));
+        (vec2(real(x), real(y)), Shape::AxisAlignedRect(AxisAlignedRect::new(size)))
+    }
+
+    fn character() -> Shape<f64> {
+        Shape::AxisAlignedRect(AxisAlignedRect::new_character(vec2(real(32.), real(64.))))
+    }
+
+    fn at_origin(shape: &Shape<f64>) -> ShapePosition<'_, f64> {
+        ShapePosition {
+            entity_id: 100,
+            layer: layer::DEFAULT,
+            material: material::DEFAULT,
+            position: vec2(real(0.), real(0.)),
+            shape,
+        }
+    }
+
+    // moves a 32x64 character from the origin
+    fn move_character(
+        world: &World,
+        movement: Vector2<f64>,
+        properties: MovementProperties<f64>,
+    ) -> Movement<f64> {
+        let shape = character();
+        MovementContext::default().position_after_allowed_movement_with_properties(
+            at_origin(&shape),
+            movement,
+            properties,
+            world,
+        )
+    }
+
+    fn assert_near(actual: Vector2<f64>, expected: Vector2<f64>) {
+        assert!(
+            (actual - expected).magnitude() < real(0.01),
+            "{:?} is not near {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn steps_up_onto_a_low_ledge() {
+        let world = World(vec![rect(40., 56., 40., 20.)]);
+        let movement = vec2(real(20.), real(0.));
+        let properties = MovementProperties::default().with_step_height(real(12.));
+        let movement = move_character(&world, movement, properties);
+        assert_near(movement.position, vec2(real(20.), real(-8.)));
+        let step_up = movement.step_up.expect("No step up");
+        assert!((step_up - 8.).abs() < 0.01);
+    }
+
+    #[test]
+    fn ledges_above_the_step_height_block() {
+        let world = World(vec![rect(40., 56., 40., 20.)]);
+        let movement = vec2(real(20.), real(0.));
+        let properties = MovementProperties::default().with_step_height(real(4.));
+        let movement = move_character(&world, movement, properties);
+        assert_near(movement.position, vec2(real(8.), real(0.)));
+        assert_eq!(movement.step_up, None);
+    }
+
+    #[test]
+    fn steps_without_headroom_block() {
+        let world = World(vec![rect(40., 56., 40., 20.), rect(0., -12., 80., 10.)]);
+        let movement = vec2(real(20.), real(0.));
+        let properties = MovementProperties::default().with_step_height(real(12.));
+        let movement = move_character(&world, movement, properties);
+        assert_near(movement.position, vec2(real(8.), real(0.)));
+        assert_eq!(movement.step_up, None);
+    }
+
+    #[test]
+    fn ceiling_corners_within_tolerance_are_slipped_past() {
+        let world = World(vec![rect(-100., -20., 104., 10.)]);
+        let movement = vec2(real(0.), real(-20.));
+        let properties = MovementProperties::default().with_ceiling_tolerance(real(6.));
+        let movement = move_character(&world, movement, properties);
+        assert_near(movement.position, vec2(real(4.), real(-20.)));
+        assert_eq!(movement.step_up, None);
+    }
+
+    #[test]
+    fn ceiling_corners_beyond_tolerance_block() {
+        let world = World(vec![rect(-100., -20., 104., 10.)]);
+        let movement = vec2(real(0.), real(-20.));
+        let properties = MovementProperties::default().with_ceiling_tolerance(real(2.));
+        let movement = move_character(&world, movement, properties);
+        assert_near(movement.position, vec2(real(0.), real(-10.)));
+    }
+
+    #[test]
+    fn tolerances_are_configurable() {
+        let world = World(vec![rect(-100., 64., 200., 10.)]);
+        let shape = character();
+        // flat ground is only walkable within the slack on the slope's cosine
+        let min_walkable_cos = real(1.0005);
+        let mut movement_context = MovementContext::default();
+        assert!(movement_context
+            .collisions_below(at_origin(&shape), min_walkable_cos, &world)
+            .is_grounded());
+        movement_context.tolerances_mut().slope_cos = real(0.);
+        assert!(!movement_context
+            .collisions_below(at_origin(&shape), min_walkable_cos, &world)
+            .is_grounded());
+    }
+
+    // a horizontal segment running left to right beneath or above the character
+    fn horizontal_segment(
+        y: f64,
+        line_segment: fn(Vector2<f64>, Vector2<f64>) -> LineSegment<f64>,
+    ) -> World {
+        let line_segment =
+            line_segment(vec2(real(0.), real(0.)), vec2(real(200.), real(0.)));
+        World(vec![(vec2(real(-100.), real(y)), Shape::LineSegment(line_segment))])
+    }
+
+    #[test]
+    fn one_sided_segments_block_from_one_side() {
+        let down = vec2(real(0.), real(20.));
+        let up = vec2(real(0.), real(-20.));
+        let properties = MovementProperties::default();
+        // left-solid segments running left to right are solid from above
+        let left_solid = horizontal_segment(74., LineSegment::new_left_solid);
+        let position = move_character(&left_solid, down, properties).position;
+        assert_near(position, vec2(real(0.), real(10.)));
+        let left_solid = horizontal_segment(-10., LineSegment::new_left_solid);
+        assert_near(move_character(&left_solid, up, properties).position, up);
+        // and right-solid ones from below
+        let right_solid = horizontal_segment(74., LineSegment::new_right_solid);
+        assert_near(move_character(&right_solid, down, properties).position, down);
+        let right_solid = horizontal_segment(-10., LineSegment::new_right_solid);
+        let position = move_character(&right_solid, up, properties).position;
+        assert_near(position, vec2(real(0.), real(-10.)));
+    }
+
+    // a slope rising to the right, just below the character's bottom right corner
+    fn slope(degrees: f64) -> World {
+        let direction = vec2(degrees.to_radians().cos(), -degrees.to_radians().sin());
+        let line_segment =
+            LineSegment::new_both_solid(direction * real(-100.), direction * real(100.));
+        World(vec![(vec2(real(32.), real(64.5)), Shape::LineSegment(line_segment))])
+    }
+
+    #[test]
+    fn walkable_slopes_ground_the_character() {
+        let world = slope(30.);
+        let shape = character();
+        let mut context = MovementContext::default();
+        let min_walkable_cos = real(50f64.to_radians().cos());
+        let below = context.collisions_below(at_origin(&shape), min_walkable_cos, &world);
+        assert!(below.is_grounded());
+        assert!(below.can_jump());
+        assert!((below.ground_angle().unwrap() - 30f64.to_radians()).abs() < 0.0001);
+    }
+
+    #[test]
+    fn steep_slopes_do_not_ground_the_character() {
+        let world = slope(60.);
+        let shape = character();
+        let mut context = MovementContext::default();
+        let min_walkable_cos = real(50f64.to_radians().cos());
+        let below = context.collisions_below(at_origin(&shape), min_walkable_cos, &world);
+        assert!(!below.is_grounded());
+        assert!(!below.can_jump());
+        assert_eq!(below.ground_normal(), None);
+        assert!((below.ground_angle().unwrap() - 60f64.to_radians()).abs() < 0.0001);
+    }
+
+    #[test]
+    fn collision_matrix_changes_take_effect_immediately() {
+        let world = World(vec![rect(40., 0., 20., 64.)]);
+        let shape = character();
+        let movement = vec2(real(20.), real(0.));
+        let mut context = MovementContext::default();
+        let move_once = |context: &mut MovementContext<f64>| {
+            context.position_after_allowed_movement(at_origin(&shape), movement, &world)
+        };
+        assert_near(move_once(&mut context).position, vec2(real(8.), real(0.)));
+        let matrix = context.collision_matrix_mut();
+        matrix.set_response(layer::DEFAULT, layer::DEFAULT, Response::Report);
+        assert_near(move_once(&mut context).position, movement);
+        assert_eq!(context.reported_collisions().len(), 1);
+        let matrix = context.collision_matrix_mut();
+        matrix.set_response(layer::DEFAULT, layer::DEFAULT, Response::Ignore);
+        assert_near(move_once(&mut context).position, movement);
+        assert!(context.reported_collisions().is_empty());
+    }
+
+    #[test]
+    fn round_shapes_can_override_their_layer() {
+        let shape = character();
+        let movement = vec2(real(20.), real(0.));
+        let mut context = MovementContext::default();
+        let ghost = context.collision_matrix_mut().add_layer("ghost");
+        let matrix = context.collision_matrix_mut();
+        matrix.set_response(layer::DEFAULT, ghost, Response::Ignore);
+        let circle = |edge_properties| {
+            let circle = Circle::new_with_edge_properties(real(10.), edge_properties);
+            World(vec![(vec2(real(50.), real(32.)), Shape::Circle(circle))])
+        };
+        let world = circle(EdgeProperties::default());
+        let position = context
+            .position_after_allowed_movement(at_origin(&shape), movement, &world)
+            .position;
+        assert_near(position, vec2(real(8.), real(0.)));
+        let world = circle(EdgeProperties::default().with_layer(ghost));
+        let position = context
+            .position_after_allowed_movement(at_origin(&shape), movement, &world)
+            .position;
+        assert_near(position, movement);
+    }
+
+    fn points(points: &[(f64, f64)]) -> Vec<Vector2<f64>> {
+        points.iter().map(|&(x, y)| vec2(real(x), real(y))).collect()
+    }
+
+    #[test]
+    fn characters_slide_across_chain_joints() {
+        // the character stands across a joint, pushing into the ground
+        let ground = points(&[(-100., 64.), (16., 64.), (56., 64.), (200., 64.)]);
+        let open = Chain::new_open(ground.clone());
+        let mut loop_vertices = ground.clone();
+        loop_vertices.push(vec2(real(200.), real(200.)));
+        let closed = Chain::new_closed(loop_vertices);
+        let movement = vec2(real(100.), real(1.));
+        let properties = MovementProperties::default();
+        for chain in [open, closed] {
+            let world = World(vec![(vec2(real(0.), real(0.)), Shape::Chain(chain))]);
+            let position = move_character(&world, movement, properties).position;
+            assert_near(position, vec2(real(100.), real(0.)));
+        }
+        // separate segments snag on the joint
+        let segments = ground
+            .windows(2)
+            .map(|pair| {
+                let line_segment = LineSegment::new_both_solid(pair[0], pair[1]);
+                (vec2(real(0.), real(0.)), Shape::LineSegment(line_segment))
+            })
+            .collect();
+        let position = move_character(&World(segments), movement, properties).position;
+        assert!(position.x < real(50.));
+    }
+
+    #[test]
+    fn depenetration_pushes_out_of_overlapping_shapes() {
+        let tolerances = Tolerances::default();
+        let shape = character();
+        // the floor overlaps the bottom 14 of the character, the wall its right 4
+        for &(ref other, expected) in [
+            (rect(-50., 50., 100., 20.), vec2(real(0.), real(-14.))),
+            (rect(28., -50., 40., 200.), vec2(real(-4.), real(0.))),
+        ]
+        .iter()
+        {
+            let world = World(vec![other.clone()]);
+            let mut context = MovementContext::default();
+            let correction = context
+                .depenetration(at_origin(&shape), &world)
+                .expect("No depenetration");
+            assert_near(correction, expected);
+            assert_eq!(context.num_depenetrations(), 1);
+            let corrected = ShapePosition {
+                position: correction,
+                ..at_origin(&shape)
+            };
+            let (position, ref other_shape) = *other;
+            let other = ShapePosition {
+                entity_id: 0,
+                position,
+                shape: other_shape,
+                ..at_origin(&shape)
+            };
+            assert!(overlap::shapes_overlap(&at_origin(&shape), &other, &tolerances));
+            assert!(!overlap::shapes_overlap(&corrected, &other, &tolerances));
+            assert!(context.depenetration(corrected, &world).is_none());
+        }
+    }
+
+    #[test]
+    fn reflect_mirrors_movement_into_the_surface() {
+        let normal = vec2(real(0.), real(-1.));
+        let vector = vec2(real(3.), real(4.));
+        assert_near(reflect(vector, normal, real(0.5)), vec2(real(3.), real(-2.)));
+        assert_near(reflect(vector, normal, real(1.)), vec2(real(3.), real(-4.)));
+        assert_near(reflect(vector, normal, real(0.)), vec2(real(3.), real(0.)));
+        // movement away from the surface is unchanged
+        let away = vec2(real(3.), real(-4.));
+        assert_near(reflect(away, normal, real(0.5)), away);
+    }
+
+    #[test]
+    fn response_modes_set_the_velocity_after_landing() {
+        // the floor is 10 below the character, so it lands halfway through
+        let world = World(vec![rect(-100., 74., 200., 20.)]);
+        let movement = vec2(real(30.), real(20.));
+        let land = |response_mode| {
+            let properties = MovementProperties::default();
+            move_character(&world, movement, properties.with_response_mode(response_mode))
+        };
+        let slide = land(ResponseMode::Slide);
+        assert_near(slide.position, vec2(real(30.), real(10.)));
+        assert_near(slide.velocity, vec2(real(30.), real(10.)));
+        let stop = land(ResponseMode::Stop);
+        assert_near(stop.position, vec2(real(15.), real(10.)));
+        assert_near(stop.velocity, vec2(real(0.), real(0.)));
+        let reflect = land(ResponseMode::Reflect {
+            restitution: real(0.5),
+        });
+        assert_near(reflect.position, vec2(real(30.), real(5.)));
+        assert_near(reflect.velocity, vec2(real(30.), real(-10.)));
+    }
+}
@@ -0,0 +1,165 @@
+use aabb::Aabb;
+use axis_aligned_rect::RectEdgeProperties;
+use cgmath::{vec2, Vector2};
+use collide::{Collide, Edge};
+use scalar::{real, Scalar};
+
+const TOP_LEFT: usize = 0;
+const TOP_RIGHT: usize = 1;
+const BOTTOM_RIGHT: usize = 2;
+const BOTTOM_LEFT: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct OrientedRect<S> {
+    dimensions: Vector2<S>,
+    rotation: S,
+    corners: [Vector2<S>; 4],
+    edge_properties: RectEdgeProperties,
+}
+
+impl<S: Scalar> OrientedRect<S> {
+    pub fn new_with_edge_properties(
+        dimensions: Vector2<S>,
+        rotation: S,
+        edge_properties: RectEdgeProperties,
+    ) -> Self {
+        let centre = dimensions / real(2.);
+        let (sin, cos) = rotation.sin_cos();
+        let rotate = |corner: Vector2<S>| {
+            let offset = corner - centre;
+            centre + vec2(
+                offset.x * cos - offset.y * sin,
+                offset.x * sin + offset.y * cos,
+            )
+        };
+        let corners = [
+            rotate(vec2(real(0.), real(0.))),
+            rotate(vec2(dimensions.x, real(0.))),
+            rotate(dimensions),
+            rotate(vec2(real(0.), dimensions.y)),
+        ];
+        Self {
+            dimensions,
+            rotation,
+            corners,
+            edge_properties,
+        }
+    }
+    pub fn new(dimensions: Vector2<S>, rotation: S) -> Self {
+        Self::new_with_edge_properties(dimensions, rotation, RectEdgeProperties::main())
+    }
+    #[allow(dead_code)]
+    pub fn new_character(dimensions: Vector2<S>, rotation: S) -> Self {
+        Self::new_with_edge_properties(
+            dimensions,
+            rotation,
+            RectEdgeProperties::character(),
+        )
+    }
+    #[allow(dead_code)]
+    pub fn new_floor_only(dimensions: Vector2<S>, rotation: S) -> Self {
+        Self::new_with_edge_properties(
+            dimensions,
+            rotation,
+            RectEdgeProperties::floor_only(),
+        )
+    }
+    #[allow(dead_code)]
+    pub fn dimensions(&self) -> Vector2<S> {
+        self.dimensions
+    }
+    #[allow(dead_code)]
+    pub fn rotation(&self) -> S {
+        self.rotation
+    }
+    #[allow(dead_code)]
+    pub fn edge_properties(&self) -> &RectEdgeProperties {
+        &self.edge_properties
+    }
+    pub fn corners(&self) -> &[Vector2<S>; 4] {
+        &self.corners
+    }
+    fn top(&self) -> Edge<S> {
+        Edge::new(self.corners[TOP_LEFT], self.corners[TOP_RIGHT])
+    }
+    fn right(&self) -> Edge<S> {
+        Edge::new(self.corners[TOP_RIGHT], self.corners[BOTTOM_RIGHT])
+    }
+    fn bottom(&self) -> Edge<S> {
+        Edge::new(self.corners[BOTTOM_RIGHT], self.corners[BOTTOM_LEFT])
+    }
+    fn left(&self) -> Edge<S> {
+        Edge::new(self.corners[BOTTOM_LEFT], self.corners[TOP_LEFT])
+    }
+}
+
+impl<S: Scalar> Collide<S> for OrientedRect<S> {
+    fn aabb(&self, top_left: Vector2<S>) -> Aabb<S> {
+        Aabb::from_points(&self.corners).add_vector(top_left)
+    }
+    fn for_each_left_solid_edge_facing<F: FnMut(Edge<S>)>(
+        &self,
+        direction: Vector2<S>,
+        mut f: F,
+    ) {
+        let bottom = self.bottom();
+        if bottom.is_facing(direction) {
+            f(bottom.with_properties(self.edge_properties.bottom))
+        }
+        let top = self.top();
+        if top.is_facing(direction) {
+            f(top.with_properties(self.edge_properties.top))
+        }
+        let right = self.right();
+        if right.is_facing(direction) {
+            f(right.with_properties(self.edge_properties.right))
+        }
+        let left = self.left();
+        if left.is_facing(direction) {
+            f(left.with_properties(self.edge_properties.left))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    fn assert_aabb_near(actual: Aabb<f64>, top_left: Vector2<f64>, size: Vector2<f64>) {
+        let error = (actual.top_left().x - top_left.x).abs()
+            + (actual.top_left().y - top_left.y).abs()
+            + (actual.size().x - size.x).abs()
+            + (actual.size().y - size.y).abs();
+        assert!(error < 0.000001, "{:?} is not near {:?} {:?}", actual, top_left, size);
+    }
+
+    #[test]
+    fn aabb_tightly_bounds_the_rotated_rect() {
+        let dimensions = vec2(40., 20.);
+        let top_left = vec2(100., 200.);
+        let aabb = OrientedRect::new(dimensions, 0.).aabb(top_left);
+        assert_aabb_near(aabb, top_left, dimensions);
+        // rotation is about the centre, which is 20, 10 from the top left
+        let aabb = OrientedRect::new(dimensions, FRAC_PI_2).aabb(top_left);
+        assert_aabb_near(aabb, top_left + vec2(10., -10.), vec2(20., 40.));
+        let half_size = 30. / 2f64.sqrt();
+        let aabb = OrientedRect::new(dimensions, FRAC_PI_4).aabb(top_left);
+        assert_aabb_near(
+            aabb,
+            top_left + vec2(20. - half_size, 10. - half_size),
+            vec2(half_size * 2., half_size * 2.),
+        );
+    }
+
+    #[test]
+    fn getters_return_constructor_arguments() {
+        let rect = OrientedRect::new_floor_only(vec2(40., 20.), 0.5);
+        assert_eq!(rect.dimensions(), vec2(40., 20.));
+        assert_eq!(rect.rotation(), 0.5);
+        assert_eq!(
+            rect.edge_properties().top,
+            RectEdgeProperties::floor_only().top
+        );
+    }
+}
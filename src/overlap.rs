@@ -0,0 +1,504 @@
+use aabb::Aabb;
+use cgmath::{vec2, InnerSpace, Vector2};
+use collide::{channels, Channels, Collide};
+use round::Round;
+use scalar::{real, Scalar};
+use shape::{Shape, ShapePosition};
+use std::borrow::Cow;
+use tile_map::Tile;
+use tolerances::Tolerances;
+
+// A convex polygon inflated by a radius. Points and line segments are
+// represented as polygons with 1 and 2 vertices respectively. The channels
+// are those which are solid on every side of the piece.
+#[derive(Debug, Clone)]
+pub struct ConvexPiece<'a, S: 'a + Scalar> {
+    vertices: Cow<'a, [Vector2<S>]>,
+    offset: Vector2<S>,
+    radius: S,
+    channels: Channels,
+}
+
+impl<'a, S: Scalar> ConvexPiece<'a, S> {
+    pub fn new<V>(vertices: V, offset: Vector2<S>, radius: S) -> Self
+    where
+        V: Into<Cow<'a, [Vector2<S>]>>,
+    {
+        Self {
+            vertices: vertices.into(),
+            offset,
+            radius,
+            channels: !0,
+        }
+    }
+    pub fn with_channels(self, channels: Channels) -> Self {
+        Self { channels, ..self }
+    }
+    pub fn num_vertices(&self) -> usize {
+        self.vertices.len()
+    }
+    pub fn vertex(&self, index: usize) -> Vector2<S> {
+        self.vertices[index % self.vertices.len()] + self.offset
+    }
+    pub fn point(point: Vector2<S>) -> Self {
+        Self::new(vec![point], vec2(real(0.), real(0.)), real(0.))
+    }
+    pub fn aabb(aabb: Aabb<S>) -> Self {
+        Self::new(
+            rect_vertices(aabb.top_left(), aabb.size()),
+            vec2(real(0.), real(0.)),
+            real(0.),
+        )
+    }
+    pub fn bounding_aabb(&self) -> Aabb<S> {
+        let vertices = (0..self.num_vertices())
+            .map(|i| self.vertex(i))
+            .collect::<Vec<_>>();
+        let radius = vec2(self.radius, self.radius);
+        let aabb = Aabb::from_points(&vertices);
+        Aabb::new(aabb.top_left() - radius, aabb.size() + radius * real(2.))
+    }
+    fn for_each_segment<F: FnMut(Vector2<S>, Vector2<S>)>(&self, mut f: F) {
+        match self.num_vertices() {
+            1 => f(self.vertex(0), self.vertex(0)),
+            2 => f(self.vertex(0), self.vertex(1)),
+            n => {
+                for i in 0..n {
+                    f(self.vertex(i), self.vertex(i + 1))
+                }
+            }
+        }
+    }
+    fn for_each_axis<F: FnMut(Vector2<S>)>(&self, tolerances: &Tolerances<S>, mut f: F) {
+        let num_vertices = self.num_vertices();
+        self.for_each_segment(|start, end| {
+            let vector = end - start;
+            if vector.magnitude2() > tolerances.touching_distance {
+                f(vec2(vector.y, -vector.x).normalize());
+                if num_vertices == 2 {
+                    f(vector.normalize());
+                }
+            }
+        });
+    }
+    fn project(&self, axis: Vector2<S>) -> (S, S) {
+        let first = self.vertex(0).dot(axis);
+        (1..self.num_vertices()).fold((first, first), |(min, max), i| {
+            let projection = self.vertex(i).dot(axis);
+            (min.min(projection), max.max(projection))
+        })
+    }
+}
+
+fn is_separating_axis<S: Scalar>(
+    a: &ConvexPiece<S>,
+    b: &ConvexPiece<S>,
+    axis: Vector2<S>,
+    tolerances: &Tolerances<S>,
+) -> bool {
+    let (a_min, a_max) = a.project(axis);
+    let (b_min, b_max) = b.project(axis);
+    a_max < b_min + tolerances.touching_distance
+        || b_max < a_min + tolerances.touching_distance
+}
+
+fn cores_intersect<S: Scalar>(
+    a: &ConvexPiece<S>,
+    b: &ConvexPiece<S>,
+    tolerances: &Tolerances<S>,
+) -> bool {
+    let mut has_axis = false;
+    let mut separated = false;
+    a.for_each_axis(tolerances, |axis| {
+        has_axis = true;
+        separated = separated || is_separating_axis(a, b, axis, tolerances);
+    });
+    b.for_each_axis(tolerances, |axis| {
+        has_axis = true;
+        separated = separated || is_separating_axis(a, b, axis, tolerances);
+    });
+    has_axis && !separated
+}
+
+pub fn closest_point_on_segment<S: Scalar>(
+    point: Vector2<S>,
+    start: Vector2<S>,
+    end: Vector2<S>,
+) -> Vector2<S> {
+    let vector = end - start;
+    let length2 = vector.magnitude2();
+    let along = if length2 <= real(0.) {
+        real(0.)
+    } else {
+        ((point - start).dot(vector) / length2)
+            .max(real(0.))
+            .min(real(1.))
+    };
+    start + vector * along
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ClosestPoints<S> {
+    distance2: S,
+    a: Vector2<S>,
+    b: Vector2<S>,
+}
+
+impl<S: Scalar> ClosestPoints<S> {
+    fn new(a: Vector2<S>, b: Vector2<S>) -> Self {
+        Self {
+            distance2: (a - b).magnitude2(),
+            a,
+            b,
+        }
+    }
+    fn closer(self, other: Self) -> Self {
+        if other.distance2 < self.distance2 {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+// only valid for segments which don't cross
+fn segment_closest_points<S: Scalar>(
+    a_start: Vector2<S>,
+    a_end: Vector2<S>,
+    b_start: Vector2<S>,
+    b_end: Vector2<S>,
+) -> ClosestPoints<S> {
+    ClosestPoints::new(a_start, closest_point_on_segment(a_start, b_start, b_end))
+        .closer(ClosestPoints::new(
+            a_end,
+            closest_point_on_segment(a_end, b_start, b_end),
+        ))
+        .closer(ClosestPoints::new(
+            closest_point_on_segment(b_start, a_start, a_end),
+            b_start,
+        ))
+        .closer(ClosestPoints::new(
+            closest_point_on_segment(b_end, a_start, a_end),
+            b_end,
+        ))
+}
+
+fn core_closest_points<S: Scalar>(
+    a: &ConvexPiece<S>,
+    b: &ConvexPiece<S>,
+) -> ClosestPoints<S> {
+    let mut closest: Option<ClosestPoints<S>> = None;
+    a.for_each_segment(|a_start, a_end| {
+        b.for_each_segment(|b_start, b_end| {
+            let points = segment_closest_points(a_start, a_end, b_start, b_end);
+            closest = Some(match closest {
+                Some(closest) => closest.closer(points),
+                None => points,
+            });
+        });
+    });
+    closest.expect("Empty convex piece")
+}
+
+// pieces which are merely touching are not considered to overlap
+pub fn pieces_overlap<S: Scalar>(
+    a: &ConvexPiece<S>,
+    b: &ConvexPiece<S>,
+    tolerances: &Tolerances<S>,
+) -> bool {
+    if cores_intersect(a, b, tolerances) {
+        return true;
+    }
+    let radius = a.radius + b.radius - tolerances.touching_distance;
+    radius > real(0.) && core_closest_points(a, b).distance2 < radius * radius
+}
+
+// the shortest translation which moves a out of b
+pub fn piece_penetration<S: Scalar>(
+    a: &ConvexPiece<S>,
+    b: &ConvexPiece<S>,
+    tolerances: &Tolerances<S>,
+) -> Option<Vector2<S>> {
+    let touching_distance = tolerances.touching_distance;
+    let radius = a.radius + b.radius;
+    if cores_intersect(a, b, tolerances) {
+        let mut shallowest: Option<(S, Vector2<S>)> = None;
+        let mut consider_axis = |axis: Vector2<S>| {
+            let (a_min, a_max) = a.project(axis);
+            let (b_min, b_max) = b.project(axis);
+            let (depth, direction) = if a_max - b_min < b_max - a_min {
+                (a_max - b_min + radius, -axis)
+            } else {
+                (b_max - a_min + radius, axis)
+            };
+            if shallowest.is_none_or(|(shallowest_depth, _)| depth < shallowest_depth) {
+                shallowest = Some((depth, direction));
+            }
+        };
+        a.for_each_axis(tolerances, &mut consider_axis);
+        b.for_each_axis(tolerances, &mut consider_axis);
+        return shallowest.map(|(depth, direction)| direction * depth);
+    }
+    if radius < touching_distance {
+        return None;
+    }
+    let closest_points = core_closest_points(a, b);
+    if closest_points.distance2
+        >= (radius - touching_distance) * (radius - touching_distance)
+    {
+        return None;
+    }
+    let distance = closest_points.distance2.sqrt();
+    // coincident cores have no preferred direction, so push upwards
+    let direction = if distance < touching_distance {
+        vec2(real(0.), -S::one())
+    } else {
+        (closest_points.a - closest_points.b) / distance
+    };
+    Some(direction * (radius - distance))
+}
+
+fn rect_vertices<S: Scalar>(top_left: Vector2<S>, size: Vector2<S>) -> Vec<Vector2<S>> {
+    vec![
+        top_left,
+        top_left + vec2(size.x, real(0.)),
+        top_left + size,
+        top_left + vec2(real(0.), size.y),
+    ]
+}
+
+// Pieces of tile maps are restricted to tiles intersecting the given aabb.
+pub fn for_each_convex_piece<'a, S: Scalar, F>(
+    shape: &'a Shape<S>,
+    position: Vector2<S>,
+    aabb: Aabb<S>,
+    f: &mut F,
+) where
+    F: FnMut(ConvexPiece<'a, S>),
+{
+    match shape {
+        Shape::AxisAlignedRect(rect) => f(ConvexPiece::new(
+            rect_vertices(vec2(real(0.), real(0.)), rect.dimensions()),
+            position,
+            real(0.),
+        )
+        .with_channels(solid_channels(rect))),
+        Shape::LineSegment(line_segment) => {
+            let channels = if line_segment.is_both_solid() {
+                solid_channels(line_segment)
+            } else {
+                0
+            };
+            f(ConvexPiece::new(
+                vec![line_segment.start, line_segment.end],
+                position,
+                real(0.),
+            )
+            .with_channels(channels))
+        }
+        Shape::ConvexPolygon(convex_polygon) => {
+            f(
+                ConvexPiece::new(convex_polygon.vertices(), position, real(0.))
+                    .with_channels(solid_channels(convex_polygon)),
+            )
+        }
+        Shape::OrientedRect(oriented_rect) => {
+            f(
+                ConvexPiece::new(&oriented_rect.corners()[..], position, real(0.))
+                    .with_channels(solid_channels(oriented_rect)),
+            )
+        }
+        Shape::TileMap(tile_map) => {
+            let cell_size = tile_map.cell_size();
+            tile_map.for_each_tile_in(aabb.add_vector(-position), |top_left, tile| {
+                let channels = match tile {
+                    Tile::Solid => channels::MAIN,
+                    Tile::OneWay | Tile::Empty => 0,
+                };
+                f(ConvexPiece::new(
+                    rect_vertices(top_left, cell_size),
+                    position,
+                    real(0.),
+                )
+                .with_channels(channels))
+            })
+        }
+        Shape::Chain(chain) => {
+            let channels = solid_channels(chain);
+            if chain.is_closed() {
+                for triangle in chain.triangles() {
+                    f(ConvexPiece::new(&triangle[..], position, real(0.))
+                        .with_channels(channels));
+                }
+            } else {
+                for segment in chain.vertices().windows(2) {
+                    f(ConvexPiece::new(segment, position, real(0.))
+                        .with_channels(channels));
+                }
+            }
+        }
+        Shape::Circle(circle) => f(round_piece(circle, position)),
+        Shape::Capsule(capsule) => f(round_piece(capsule, position)),
+        Shape::Compound(children) => {
+            for &(offset, ref child) in children.iter() {
+                for_each_convex_piece(child, position + offset, aabb, f);
+            }
+        }
+    }
+}
+
+fn solid_channels<S: Scalar, C: Collide<S>>(collide: &C) -> Channels {
+    let mut channels = !0;
+    collide.for_each_left_solid_edge_facing(vec2(real(0.), real(0.)), |edge| {
+        channels &= edge.properties.channels;
+    });
+    channels
+}
+
+fn round_piece<'a, S: Scalar, R: Round<S>>(
+    round: &R,
+    position: Vector2<S>,
+) -> ConvexPiece<'a, S> {
+    let rounded_segment = round.rounded_segment();
+    ConvexPiece::new(
+        vec![rounded_segment.start, rounded_segment.end],
+        position,
+        rounded_segment.radius,
+    )
+    .with_channels(round.edge_properties().channels)
+}
+
+pub fn piece_overlaps_shape<S: Scalar>(
+    piece: &ConvexPiece<S>,
+    shape_position: &ShapePosition<S>,
+    tolerances: &Tolerances<S>,
+) -> bool {
+    let aabb = piece.bounding_aabb();
+    if !aabb.is_intersecting(&shape_position.shape.aabb(shape_position.position)) {
+        return false;
+    }
+    let mut overlapping = false;
+    for_each_convex_piece(
+        shape_position.shape,
+        shape_position.position,
+        aabb,
+        &mut |shape_piece| {
+            overlapping = overlapping || pieces_overlap(piece, &shape_piece, tolerances);
+        },
+    );
+    overlapping
+}
+
+pub fn shapes_overlap<S: Scalar>(
+    a: &ShapePosition<S>,
+    b: &ShapePosition<S>,
+    tolerances: &Tolerances<S>,
+) -> bool {
+    let a_aabb = a.shape.aabb(a.position);
+    let b_aabb = b.shape.aabb(b.position);
+    if !a_aabb.is_intersecting(&b_aabb) {
+        return false;
+    }
+    let mut overlapping = false;
+    for_each_convex_piece(a.shape, a.position, b_aabb, &mut |a_piece| {
+        overlapping = overlapping || piece_overlaps_shape(&a_piece, b, tolerances);
+    });
+    overlapping
+}
+
+// the deepest penetration between pieces sharing a solid channel
+pub fn shape_penetration<S: Scalar>(
+    a: &ShapePosition<S>,
+    b: &ShapePosition<S>,
+    tolerances: &Tolerances<S>,
+) -> Option<Vector2<S>> {
+    let a_aabb = a.shape.aabb(a.position);
+    let b_aabb = b.shape.aabb(b.position);
+    if !a_aabb.is_intersecting(&b_aabb) {
+        return None;
+    }
+    let mut deepest: Option<Vector2<S>> = None;
+    for_each_convex_piece(a.shape, a.position, b_aabb, &mut |a_piece| {
+        for_each_convex_piece(b.shape, b.position, a_aabb, &mut |b_piece| {
+            if a_piece.channels & b_piece.channels == 0 {
+                return;
+            }
+            if let Some(penetration) = piece_penetration(&a_piece, &b_piece, tolerances) {
+                if deepest.is_none_or(|deepest| {
+                    penetration.magnitude2() > deepest.magnitude2()
+                }) {
+                    deepest = Some(penetration);
+                }
+            }
+        });
+    });
+    deepest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axis_aligned_rect::AxisAlignedRect;
+    use circle::Circle;
+    use layer;
+    use material;
+
+    fn at<'a>(shape: &'a Shape<f64>, x: f64, y: f64) -> ShapePosition<'a, f64> {
+        ShapePosition {
+            entity_id: 0,
+            layer: layer::DEFAULT,
+            material: material::DEFAULT,
+            position: vec2(real(x), real(y)),
+            shape,
+        }
+    }
+
+    fn square(size: f64) -> Shape<f64> {
+        Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(size), real(size))))
+    }
+
+    // pushes a out of b and checks they are left touching
+    fn push_out(a: ShapePosition<f64>, b: ShapePosition<f64>) -> Vector2<f64> {
+        let tolerances = Tolerances::default();
+        assert!(shapes_overlap(&a, &b, &tolerances));
+        let penetration = shape_penetration(&a, &b, &tolerances).expect("No penetration");
+        let a = ShapePosition {
+            position: a.position + penetration,
+            ..a
+        };
+        assert!(!shapes_overlap(&a, &b, &tolerances));
+        assert!(shape_penetration(&a, &b, &tolerances).is_none());
+        penetration
+    }
+
+    #[test]
+    fn rects_are_pushed_out_along_the_shallowest_axis() {
+        let a = square(10.);
+        let b = square(10.);
+        let penetration = push_out(at(&a, 0., 0.), at(&b, 7., 2.));
+        assert!((penetration - vec2(real(-3.), real(0.))).magnitude() < 0.000001);
+        let penetration = push_out(at(&a, 0., 0.), at(&b, 1., -8.));
+        assert!((penetration - vec2(real(0.), real(2.))).magnitude() < 0.000001);
+    }
+
+    #[test]
+    fn circles_are_pushed_out_along_the_line_between_cores() {
+        let a = Shape::Circle(Circle::new(real(5.)));
+        let b = square(10.);
+        // the centre is outside the rect, 2.5 from its corner
+        let penetration = push_out(at(&a, -1.5, -2.), at(&b, 0., 0.));
+        let expected = vec2(real(-0.6), real(-0.8)) * real(2.5);
+        assert!((penetration - expected).magnitude() < 0.000001);
+    }
+
+    #[test]
+    fn separate_shapes_have_no_penetration() {
+        let tolerances = Tolerances::default();
+        let a = square(10.);
+        let b = square(10.);
+        let touching = shape_penetration(&at(&a, 0., 0.), &at(&b, 10., 0.), &tolerances);
+        assert!(touching.is_none());
+        let apart = shape_penetration(&at(&a, 0., 0.), &at(&b, 20., 5.), &tolerances);
+        assert!(apart.is_none());
+    }
+}
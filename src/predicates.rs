@@ -0,0 +1,146 @@
+use cgmath::Vector2;
+use scalar::Scalar;
+use std::cmp::Ordering;
+use std::f64;
+
+// sign of the exact sum of the products of each pair of terms
+pub fn product_sum_sign<S: Scalar>(terms: &[(S, S)]) -> Ordering {
+    S::product_sum_sign(terms)
+}
+
+// sign of v x w
+pub fn cross_sign<S: Scalar>(v: Vector2<S>, w: Vector2<S>) -> Ordering {
+    product_sum_sign(&[(v.x, w.y), (-v.y, w.x)])
+}
+
+// sign of vector x (end - start), without rounding the subtraction.
+// With y pointing down, Less means the vector crosses the edge from its open side.
+pub fn edge_side<S: Scalar>(
+    vector: Vector2<S>,
+    start: Vector2<S>,
+    end: Vector2<S>,
+) -> Ordering {
+    product_sum_sign(&[
+        (vector.x, end.y),
+        (-vector.x, start.y),
+        (-vector.y, end.x),
+        (vector.y, start.x),
+    ])
+}
+
+// Adaptive precision: the sign of the rounded sum is returned when it is
+// outside the bound on its rounding error, and otherwise the sum is
+// recomputed as an exact floating point expansion.
+// Products which underflow are not handled.
+pub fn f64_product_sum_sign(terms: &[(f64, f64)]) -> Ordering {
+    let mut estimate = 0.;
+    let mut magnitude = 0.;
+    for &(a, b) in terms {
+        let product = a * b;
+        estimate += product;
+        magnitude += product.abs();
+    }
+    let error_bound = (terms.len() as f64 + 1.) * f64::EPSILON * magnitude;
+    if estimate > error_bound {
+        return Ordering::Greater;
+    }
+    if estimate < -error_bound {
+        return Ordering::Less;
+    }
+    let mut expansion = Vec::with_capacity(terms.len() * 2);
+    for &(a, b) in terms {
+        let (product, error) = two_product(a, b);
+        grow_expansion(&mut expansion, error);
+        grow_expansion(&mut expansion, product);
+    }
+    // components are ordered by increasing magnitude and don't overlap,
+    // so the largest non-zero component determines the sign
+    expansion
+        .iter()
+        .rev()
+        .find(|&&component| component != 0.)
+        .map_or(Ordering::Equal, |component| {
+            component.partial_cmp(&0.).unwrap_or(Ordering::Equal)
+        })
+}
+
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let sum = a + b;
+    let b_virtual = sum - a;
+    let a_virtual = sum - b_virtual;
+    (sum, (a - a_virtual) + (b - b_virtual))
+}
+
+fn two_product(a: f64, b: f64) -> (f64, f64) {
+    let product = a * b;
+    (product, a.mul_add(b, -product))
+}
+
+fn grow_expansion(expansion: &mut Vec<f64>, value: f64) {
+    let mut sum = value;
+    for component in expansion.iter_mut() {
+        let (new_sum, error) = two_sum(sum, *component);
+        *component = error;
+        sum = new_sum;
+    }
+    expansion.push(sum);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+
+    fn naive_sign(value: f64) -> Ordering {
+        value.partial_cmp(&0.).unwrap()
+    }
+
+    #[test]
+    fn cross_sign_of_almost_parallel_vectors() {
+        let v = vec2(1. + f64::EPSILON, 1.);
+        let w = vec2(1., 1. - f64::EPSILON / 2.);
+        // both products round to 1
+        assert_eq!(naive_sign(v.x * w.y - v.y * w.x), Ordering::Equal);
+        assert_eq!(cross_sign(v, w), Ordering::Greater);
+        assert_eq!(cross_sign(w, v), Ordering::Less);
+        assert_eq!(cross_sign(v, v), Ordering::Equal);
+    }
+
+    #[test]
+    fn edge_side_of_almost_collinear_points() {
+        let cases = [
+            (
+                vec2(11.52421190797308, 19.816128910402398),
+                vec2(0.05127372511495265, 0.928033175427591),
+                vec2(14.67077360886054, 26.066575976908343),
+                Ordering::Less,
+            ),
+            (
+                vec2(10.032208911595658, 17.36203658588643),
+                vec2(0.03418314975073167, 0.5944868409105872),
+                vec2(18.72983579053963, 32.949734624000584),
+                Ordering::Greater,
+            ),
+        ];
+        for &(vector, start, end, expected) in cases.iter() {
+            // rounding the products and the subtraction gives the wrong sign
+            let edge = end - start;
+            let naive = naive_sign(vector.x * edge.y - vector.y * edge.x);
+            assert_eq!(naive, expected.reverse());
+            assert_eq!(edge_side(vector, start, end), expected);
+        }
+    }
+
+    #[test]
+    fn product_sum_sign_with_cancellation() {
+        let terms = [(1e17, 1.), (1., 1.), (-1e17, 1.)];
+        let naive = terms.iter().fold(0., |sum, &(a, b)| sum + a * b);
+        assert_eq!(naive_sign(naive), Ordering::Equal);
+        assert_eq!(f64_product_sum_sign(&terms), Ordering::Greater);
+        assert_eq!(
+            f64_product_sum_sign(&[(3., 2.), (-1.5, 4.)]),
+            Ordering::Equal
+        );
+        assert_eq!(f64_product_sum_sign(&[(3., 2.), (-1., 4.)]), Ordering::Greater);
+    }
+}
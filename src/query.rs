@@ -0,0 +1,401 @@
+use aabb::Aabb;
+use best::BestMultiSet;
+use cgmath::Vector2;
+use collide::{Collision, Collisions};
+use layer::{self, CollisionMatrix, Layer, LayerMask};
+use material;
+use movement::{EntityId, ForEachShapePosition};
+use overlap::{self, ConvexPiece};
+use raycast::{Ray, RaycastHit};
+use scalar::Scalar;
+use shape::{Shape, ShapePosition};
+use std::cmp::Ordering;
+use tolerances::Tolerances;
+
+// Queries test shapes against the world without moving anything. Every
+// query filters the shapes it visits by their entity's layer, so a
+// shape's edges are never hit separately from the rest of the shape.
+
+// the entity id of shapes cast by shape_cast, which aren't entities
+pub const QUERY_ENTITY_ID: EntityId = !0;
+
+pub struct ShapeCast<S> {
+    pub fraction: S,
+    pub normal: Vector2<S>,
+    pub entity_id: EntityId,
+    // every blocking collision at the time of impact
+    pub collisions: Vec<Collision<S>>,
+}
+
+fn includes_layer(layer_mask: LayerMask, layer: Layer) -> bool {
+    layer_mask & layer::mask(layer) != 0
+}
+
+// the closest hit along the ray, or None if the direction is zero
+pub fn raycast<S: Scalar, F>(
+    origin: Vector2<S>,
+    direction: Vector2<S>,
+    max_distance: S,
+    layer_mask: LayerMask,
+    tolerances: &Tolerances<S>,
+    for_each_shape_position: &F,
+) -> Option<RaycastHit<S>>
+where
+    F: ForEachShapePosition<S>,
+{
+    let ray = Ray::new(origin, direction, max_distance, *tolerances)?;
+    let mut closest: Option<RaycastHit<S>> = None;
+    for_each_shape_position.for_each_along_segment(
+        ray.origin,
+        ray.end(),
+        |shape_position: ShapePosition<S>| {
+            if !includes_layer(layer_mask, shape_position.layer) {
+                return;
+            }
+            shape_position.for_each_ray_hit(&ray, &mut |hit| {
+                if closest.is_none_or(|closest| hit.fraction < closest.fraction) {
+                    closest = Some(hit);
+                }
+            });
+        },
+    );
+    closest
+}
+
+// every hit along the ray, nearest first
+pub fn raycast_all<S: Scalar, F>(
+    origin: Vector2<S>,
+    direction: Vector2<S>,
+    max_distance: S,
+    layer_mask: LayerMask,
+    tolerances: &Tolerances<S>,
+    for_each_shape_position: &F,
+    hits: &mut Vec<RaycastHit<S>>,
+) where
+    F: ForEachShapePosition<S>,
+{
+    hits.clear();
+    let ray = match Ray::new(origin, direction, max_distance, *tolerances) {
+        Some(ray) => ray,
+        None => return,
+    };
+    for_each_shape_position.for_each_along_segment(
+        ray.origin,
+        ray.end(),
+        |shape_position: ShapePosition<S>| {
+            if includes_layer(layer_mask, shape_position.layer) {
+                shape_position.for_each_ray_hit(&ray, &mut |hit| hits.push(hit));
+            }
+        },
+    );
+    hits.sort_by(|a, b| {
+        a.fraction
+            .partial_cmp(&b.fraction)
+            .unwrap_or(Ordering::Equal)
+    });
+    // a ray through a vertex hits both of its edges
+    let simultaneous_time = tolerances.simultaneous_time;
+    hits.dedup_by(|a, b| {
+        a.entity_id == b.entity_id
+            && a.child == b.child
+            && (a.fraction - b.fraction).abs() < simultaneous_time
+    });
+}
+
+pub fn query_point<S: Scalar, F>(
+    point: Vector2<S>,
+    layer_mask: LayerMask,
+    tolerances: &Tolerances<S>,
+    for_each_shape_position: &F,
+    entity_ids: &mut Vec<EntityId>,
+) where
+    F: ForEachShapePosition<S>,
+{
+    let piece = ConvexPiece::point(point);
+    query_piece(
+        &piece,
+        layer_mask,
+        tolerances,
+        for_each_shape_position,
+        entity_ids,
+    );
+}
+
+pub fn query_aabb<S: Scalar, F>(
+    aabb: Aabb<S>,
+    layer_mask: LayerMask,
+    tolerances: &Tolerances<S>,
+    for_each_shape_position: &F,
+    entity_ids: &mut Vec<EntityId>,
+) where
+    F: ForEachShapePosition<S>,
+{
+    let piece = ConvexPiece::aabb(aabb);
+    query_piece(
+        &piece,
+        layer_mask,
+        tolerances,
+        for_each_shape_position,
+        entity_ids,
+    );
+}
+
+pub fn query_shape_overlap<S: Scalar, F>(
+    shape: &Shape<S>,
+    position: Vector2<S>,
+    layer_mask: LayerMask,
+    tolerances: &Tolerances<S>,
+    for_each_shape_position: &F,
+    entity_ids: &mut Vec<EntityId>,
+) where
+    F: ForEachShapePosition<S>,
+{
+    let aabb = shape.aabb(position);
+    entity_ids.clear();
+    for_each_shape_position.for_each(aabb, |other_shape_position: ShapePosition<S>| {
+        if !includes_layer(layer_mask, other_shape_position.layer) {
+            return;
+        }
+        let mut overlapping = false;
+        overlap::for_each_convex_piece(shape, position, aabb, &mut |piece| {
+            overlapping = overlapping
+                || overlap::piece_overlaps_shape(
+                    &piece,
+                    &other_shape_position,
+                    tolerances,
+                );
+        });
+        if overlapping {
+            entity_ids.push(other_shape_position.entity_id);
+        }
+    });
+}
+
+fn query_piece<S: Scalar, F>(
+    piece: &ConvexPiece<S>,
+    layer_mask: LayerMask,
+    tolerances: &Tolerances<S>,
+    for_each_shape_position: &F,
+    entity_ids: &mut Vec<EntityId>,
+) where
+    F: ForEachShapePosition<S>,
+{
+    entity_ids.clear();
+    for_each_shape_position.for_each(
+        piece.bounding_aabb(),
+        |other_shape_position: ShapePosition<S>| {
+            if includes_layer(layer_mask, other_shape_position.layer)
+                && overlap::piece_overlaps_shape(piece, &other_shape_position, tolerances)
+            {
+                entity_ids.push(other_shape_position.entity_id);
+            }
+        },
+    );
+}
+
+// Finds where the shape would stop without moving it or running the slide
+// logic. Unlike the other queries, the shape is blocked according to the
+// collision matrix, as it would be if it moved.
+#[allow(clippy::too_many_arguments)]
+pub fn shape_cast<S: Scalar, F, P>(
+    shape: &Shape<S>,
+    position: Vector2<S>,
+    layer: Layer,
+    movement: Vector2<S>,
+    filter: P,
+    collision_matrix: &CollisionMatrix,
+    tolerances: &Tolerances<S>,
+    for_each_shape_position: &F,
+) -> Option<ShapeCast<S>>
+where
+    F: ForEachShapePosition<S>,
+    P: Fn(EntityId) -> bool,
+{
+    let shape_position = ShapePosition {
+        entity_id: QUERY_ENTITY_ID,
+        layer,
+        material: material::DEFAULT,
+        shape,
+        position,
+    };
+    let mut closest = BestMultiSet::new();
+    let mut reported = Vec::new();
+    for_each_shape_position.for_each(
+        shape_position.movement_aabb(movement),
+        |other_shape_position: ShapePosition<S>| {
+            if filter(other_shape_position.entity_id) {
+                shape_position.movement_collision_test(
+                    other_shape_position,
+                    movement,
+                    &mut Collisions {
+                        collision_matrix,
+                        tolerances,
+                        closest: &mut closest,
+                        reported: &mut reported,
+                    },
+                );
+            }
+        },
+    );
+    let (fraction, normal, entity_id) = {
+        let first = closest.first()?;
+        (
+            first.contact.time,
+            first.contact.normal,
+            first.stationary_entity_id,
+        )
+    };
+    Some(ShapeCast {
+        fraction,
+        normal,
+        entity_id,
+        collisions: closest.drain().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axis_aligned_rect::AxisAlignedRect;
+    use cgmath::vec2;
+    use scalar::real;
+
+    struct World(Vec<(Vector2<f64>, Shape<f64>, Layer)>);
+
+    impl ForEachShapePosition<f64> for World {
+        fn for_each<F: FnMut(ShapePosition<f64>)>(&self, aabb: Aabb<f64>, mut f: F) {
+            for (i, &(position, ref shape, layer)) in self.0.iter().enumerate() {
+                if shape.aabb(position).is_intersecting(&aabb) {
+                    f(ShapePosition {
+                        entity_id: i as EntityId,
+                        layer,
+                        material: material::DEFAULT,
+                        position,
+                        shape,
+                    });
+                }
+            }
+        }
+    }
+
+    fn world() -> World {
+        let rect = |x, layer| {
+            (
+                vec2(real(x), real(0.)),
+                Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(10.), real(10.)))),
+                layer,
+            )
+        };
+        World(vec![rect(20., 0), rect(40., 1)])
+    }
+
+    #[test]
+    fn zero_direction_hits_nothing() {
+        let tolerances = Tolerances::default();
+        let origin = vec2(real(0.), real(5.));
+        let zero = vec2(real(0.), real(0.));
+        let world = world();
+        let hit = raycast(origin, zero, real(100.), layer::ALL, &tolerances, &world);
+        assert!(hit.is_none());
+        let mut hits = Vec::new();
+        raycast_all(origin, zero, real(100.), layer::ALL, &tolerances, &world, &mut hits);
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn raycasts_filter_by_entity_layer() {
+        let tolerances = Tolerances::default();
+        let origin = vec2(real(0.), real(5.));
+        let right = vec2(real(1.), real(0.));
+        let world = world();
+        let hit = raycast(origin, right, real(100.), layer::ALL, &tolerances, &world)
+            .expect("No hit");
+        assert_eq!(hit.entity_id, 0);
+        assert_eq!(hit.point, vec2(real(20.), real(5.)));
+        assert_eq!(hit.normal, vec2(real(-1.), real(0.)));
+        let hit = raycast(origin, right, real(100.), layer::mask(1), &tolerances, &world)
+            .expect("No hit");
+        assert_eq!(hit.entity_id, 1);
+        let mut hits = Vec::new();
+        let distance = real(100.);
+        raycast_all(origin, right, distance, layer::ALL, &tolerances, &world, &mut hits);
+        assert_eq!(
+            hits.iter().map(|hit| hit.entity_id).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn point_and_aabb_queries_filter_by_entity_layer() {
+        let tolerances = Tolerances::default();
+        let world = world();
+        let mut entity_ids = Vec::new();
+        let point = vec2(real(45.), real(5.));
+        query_point(point, layer::ALL, &tolerances, &world, &mut entity_ids);
+        assert_eq!(entity_ids, vec![1]);
+        query_point(point, layer::mask(0), &tolerances, &world, &mut entity_ids);
+        assert!(entity_ids.is_empty());
+        let aabb = Aabb::new(vec2(real(25.), real(2.)), vec2(real(20.), real(2.)));
+        query_aabb(aabb, layer::ALL, &tolerances, &world, &mut entity_ids);
+        assert_eq!(entity_ids, vec![0, 1]);
+    }
+
+    #[test]
+    fn shape_cast_finds_the_first_hit() {
+        let tolerances = Tolerances::default();
+        let collision_matrix = CollisionMatrix::default();
+        let world = world();
+        let size = vec2(real(4.), real(4.));
+        let shape = Shape::AxisAlignedRect(AxisAlignedRect::new(size));
+        let position = vec2(real(0.), real(3.));
+        let movement = vec2(real(50.), real(0.));
+        let cast = |movement, filter: &dyn Fn(EntityId) -> bool| {
+            shape_cast(
+                &shape,
+                position,
+                layer::DEFAULT,
+                movement,
+                filter,
+                &collision_matrix,
+                &tolerances,
+                &world,
+            )
+        };
+        let hit = cast(movement, &|_| true).expect("No hit");
+        assert_eq!(hit.entity_id, 0);
+        assert!((hit.fraction - 16. / 50.).abs() < 0.000001);
+        assert_eq!(hit.normal, vec2(real(-1.), real(0.)));
+        assert!(!hit.collisions.is_empty());
+        assert!(hit.collisions.iter().all(|collision| {
+            collision.stationary_entity_id == 0
+                && collision.moving_entity_id == QUERY_ENTITY_ID
+        }));
+        let hit = cast(movement, &|entity_id| entity_id != 0).expect("No hit");
+        assert_eq!(hit.entity_id, 1);
+        assert!((hit.fraction - 36. / 50.).abs() < 0.000001);
+    }
+
+    #[test]
+    fn shape_cast_can_miss() {
+        let tolerances = Tolerances::default();
+        let collision_matrix = CollisionMatrix::default();
+        let world = world();
+        let size = vec2(real(4.), real(4.));
+        let shape = Shape::AxisAlignedRect(AxisAlignedRect::new(size));
+        let position = vec2(real(0.), real(3.));
+        // short of the first rect, and away from both
+        for &movement in [vec2(real(10.), real(0.)), vec2(real(0.), real(-50.))].iter() {
+            let hit = shape_cast(
+                &shape,
+                position,
+                layer::DEFAULT,
+                movement,
+                |_| true,
+                &collision_matrix,
+                &tolerances,
+                &world,
+            );
+            assert!(hit.is_none());
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use aabb::Aabb;
+use cgmath::{vec2, InnerSpace, Vector2};
+use collide::{Collide, CollidePosition, EdgeProperties, RoundPosition};
+use left_solid_edge::{vector2_cross_product, LeftSolidEdge};
+use movement::EntityId;
+use predicates;
+use scalar::{real, Scalar};
+use std::cmp::Ordering;
+use tolerances::Tolerances;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit<S> {
+    pub entity_id: EntityId,
+    pub child: Option<usize>,
+    pub point: Vector2<S>,
+    pub normal: Vector2<S>,
+    pub fraction: S,
+    pub edge_properties: EdgeProperties,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray<S> {
+    pub origin: Vector2<S>,
+    pub movement: Vector2<S>,
+    pub tolerances: Tolerances<S>,
+}
+
+impl<S: Scalar> Ray<S> {
+    // None if the direction is zero, as it has no length to scale
+    pub fn new(
+        origin: Vector2<S>,
+        direction: Vector2<S>,
+        max_distance: S,
+        tolerances: Tolerances<S>,
+    ) -> Option<Self> {
+        if direction.magnitude2() == real(0.) {
+            return None;
+        }
+        Some(Self {
+            origin,
+            movement: direction.normalize_to(max_distance),
+            tolerances,
+        })
+    }
+    pub fn end(&self) -> Vector2<S> {
+        self.origin + self.movement
+    }
+    pub fn aabb(&self) -> Aabb<S> {
+        Aabb::from_points(&[self.origin, self.end()])
+    }
+    fn edge_fraction(&self, edge: &LeftSolidEdge<S>) -> Option<S> {
+        // only edges crossed from their solid side's outside are hit
+        if predicates::edge_side(self.movement, edge.start, edge.end) != Ordering::Less {
+            return None;
+        }
+        // the ray's line must pass between the ends of the edge
+        if predicates::edge_side(self.movement, self.origin, edge.start) == Ordering::Less
+            || predicates::edge_side(self.movement, self.origin, edge.end)
+                == Ordering::Greater
+        {
+            return None;
+        }
+        let edge_vector = edge.vector();
+        let denominator = vector2_cross_product(self.movement, edge_vector);
+        if denominator >= real(0.) {
+            return None;
+        }
+        let to_start = edge.start - self.origin;
+        let fraction = vector2_cross_product(to_start, edge_vector) / denominator;
+        if fraction < real(0.) || fraction > real(1.) {
+            return None;
+        }
+        Some(fraction)
+    }
+    pub fn for_each_collide_hit<C, F>(
+        &self,
+        collide_position: &CollidePosition<S, C>,
+        mut f: F,
+    ) where
+        C: Collide<S>,
+        F: FnMut(RaycastHit<S>),
+    {
+        let rel_aabb = self.aabb().add_vector(-collide_position.position);
+        collide_position.collide.for_each_left_solid_edge_facing_in(
+            rel_aabb,
+            -self.movement,
+            |rel_edge| {
+                if rel_edge.properties.channels == 0 {
+                    return;
+                }
+                let edge = rel_edge
+                    .left_solid_edge
+                    .add_vector(collide_position.position);
+                if let Some(fraction) = self.edge_fraction(&edge) {
+                    let vector = edge.vector();
+                    f(RaycastHit {
+                        entity_id: collide_position.entity_id,
+                        child: collide_position.child,
+                        point: self.origin + self.movement * fraction,
+                        normal: vec2(vector.y, -vector.x).normalize(),
+                        fraction,
+                        edge_properties: rel_edge.properties,
+                    });
+                }
+            },
+        );
+    }
+    pub fn round_hit(&self, round_position: &RoundPosition<S>) -> Option<RaycastHit<S>> {
+        if round_position.edge_properties.channels == 0 {
+            return None;
+        }
+        round_position
+            .rounded_segment
+            .point_sweep(self.origin, self.movement, &self.tolerances)
+            .map(|(fraction, normal)| RaycastHit {
+                entity_id: round_position.entity_id,
+                child: round_position.child,
+                point: self.origin + self.movement * fraction,
+                normal,
+                fraction,
+                edge_properties: round_position.edge_properties,
+            })
+    }
+}
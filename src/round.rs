@@ -0,0 +1,354 @@
+use aabb::Aabb;
+use cgmath::{vec2, InnerSpace, Vector2};
+use collide::{channels, EdgeProperties};
+use left_solid_edge::{LeftSolidEdge, LeftSolidEdgeCollision};
+use scalar::{real, Scalar};
+use tolerances::Tolerances;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundedSegment<S> {
+    pub start: Vector2<S>,
+    pub end: Vector2<S>,
+    pub radius: S,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RoundHit<S> {
+    movement_multiplier: S,
+    normal: Vector2<S>,
+}
+
+impl<S: Scalar> RoundHit<S> {
+    fn flip(self) -> Self {
+        Self {
+            normal: -self.normal,
+            ..self
+        }
+    }
+    fn facing(self, normal: Vector2<S>, tolerances: &Tolerances<S>) -> Option<Self> {
+        if self.normal.dot(normal) > -tolerances.direction {
+            Some(self)
+        } else {
+            None
+        }
+    }
+    fn to_collision(self) -> LeftSolidEdgeCollision<S> {
+        let tangent = vec2(-self.normal.y, self.normal.x);
+        LeftSolidEdgeCollision::from_tangent(self.movement_multiplier, tangent)
+    }
+}
+
+fn earliest<S: Scalar>(
+    a: Option<RoundHit<S>>,
+    b: Option<RoundHit<S>>,
+) -> Option<RoundHit<S>> {
+    match (a, b) {
+        (Some(a), Some(b)) => if b.movement_multiplier < a.movement_multiplier {
+            Some(b)
+        } else {
+            Some(a)
+        },
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn outward_normal<S: Scalar>(vector: Vector2<S>) -> Vector2<S> {
+    vec2(vector.y, -vector.x).normalize()
+}
+
+fn point_hit_circle<S: Scalar>(
+    point: Vector2<S>,
+    movement: Vector2<S>,
+    centre: Vector2<S>,
+    radius: S,
+    tolerances: &Tolerances<S>,
+) -> Option<RoundHit<S>> {
+    let offset = point - centre;
+    let distance = offset.magnitude();
+    if distance - radius < -tolerances.round_gap || distance <= real(0.) {
+        return None;
+    }
+    let approach = offset.dot(movement);
+    if approach > -tolerances.direction * distance {
+        return None;
+    }
+    let a = movement.magnitude2();
+    let c = offset.magnitude2() - radius * radius;
+    let discriminant = approach * approach - a * c;
+    if discriminant < real(0.) {
+        return None;
+    }
+    let movement_multiplier = ((-approach - discriminant.sqrt()) / a).max(real(0.));
+    if movement_multiplier > S::one() + tolerances.contact_time {
+        return None;
+    }
+    Some(RoundHit {
+        movement_multiplier,
+        normal: (offset + movement * movement_multiplier).normalize(),
+    })
+}
+
+fn point_hit_offset_edge<S: Scalar>(
+    point: Vector2<S>,
+    movement: Vector2<S>,
+    start: Vector2<S>,
+    end: Vector2<S>,
+    normal: Vector2<S>,
+    offset: S,
+    tolerances: &Tolerances<S>,
+) -> Option<RoundHit<S>> {
+    let gap = (point - start).dot(normal) - offset;
+    if gap < -tolerances.round_gap {
+        return None;
+    }
+    let approach = movement.dot(normal);
+    if approach > -tolerances.direction {
+        return None;
+    }
+    let movement_multiplier = (gap / -approach).max(real(0.));
+    if movement_multiplier > S::one() + tolerances.contact_time {
+        return None;
+    }
+    let vector = end - start;
+    let contact = point + movement * movement_multiplier;
+    let along = (contact - start).dot(vector) / vector.magnitude2();
+    if along < real(0.) || along > real(1.) {
+        return None;
+    }
+    Some(RoundHit {
+        movement_multiplier,
+        normal,
+    })
+}
+
+impl<S: Scalar> RoundedSegment<S> {
+    pub fn new(start: Vector2<S>, end: Vector2<S>, radius: S) -> Self {
+        Self { start, end, radius }
+    }
+    pub fn from_left_solid_edge(edge: &LeftSolidEdge<S>) -> Self {
+        Self::new(edge.start, edge.end, real(0.))
+    }
+    pub fn add_vector(&self, vector: Vector2<S>) -> Self {
+        Self {
+            start: self.start + vector,
+            end: self.end + vector,
+            ..*self
+        }
+    }
+    pub fn aabb(&self) -> Aabb<S> {
+        let top_left = vec2(
+            self.start.x.min(self.end.x) - self.radius,
+            self.start.y.min(self.end.y) - self.radius,
+        );
+        let bottom_right = vec2(
+            self.start.x.max(self.end.x) + self.radius,
+            self.start.y.max(self.end.y) + self.radius,
+        );
+        Aabb::new(top_left, bottom_right - top_left)
+    }
+    fn is_point(&self) -> bool {
+        self.end == self.start
+    }
+    fn endpoints(&self) -> [Vector2<S>; 2] {
+        [self.start, self.end]
+    }
+
+    // The returned normal points away from this segment, towards the point.
+    fn point_hit(
+        &self,
+        point: Vector2<S>,
+        movement: Vector2<S>,
+        radius: S,
+        tolerances: &Tolerances<S>,
+    ) -> Option<RoundHit<S>> {
+        let mut hit = earliest(
+            point_hit_circle(point, movement, self.start, radius, tolerances),
+            point_hit_circle(point, movement, self.end, radius, tolerances),
+        );
+        if !self.is_point() {
+            let normal = outward_normal(self.end - self.start);
+            for &side_normal in [normal, -normal].iter() {
+                hit = earliest(
+                    hit,
+                    point_hit_offset_edge(
+                        point,
+                        movement,
+                        self.start,
+                        self.end,
+                        side_normal,
+                        radius,
+                        tolerances,
+                    ),
+                );
+            }
+        }
+        hit
+    }
+
+    // returns the movement multiplier and outward surface normal
+    pub fn point_sweep(
+        &self,
+        point: Vector2<S>,
+        movement: Vector2<S>,
+        tolerances: &Tolerances<S>,
+    ) -> Option<(S, Vector2<S>)> {
+        self.point_hit(point, movement, self.radius, tolerances)
+            .map(|hit| (hit.movement_multiplier, hit.normal))
+    }
+
+    pub fn collide_with_stationary_edge(
+        &self,
+        other: &LeftSolidEdge<S>,
+        movement: Vector2<S>,
+        tolerances: &Tolerances<S>,
+    ) -> Option<LeftSolidEdgeCollision<S>> {
+        let edge_normal = outward_normal(other.vector());
+        let mut hit = None;
+        for &point in self.endpoints().iter() {
+            hit = earliest(
+                hit,
+                point_hit_offset_edge(
+                    point,
+                    movement,
+                    other.start,
+                    other.end,
+                    edge_normal,
+                    self.radius,
+                    tolerances,
+                ),
+            );
+            for &vertex in [other.start, other.end].iter() {
+                hit = earliest(
+                    hit,
+                    point_hit_circle(point, movement, vertex, self.radius, tolerances)
+                        .and_then(|hit| hit.facing(edge_normal, tolerances)),
+                );
+            }
+        }
+        if !self.is_point() {
+            let normal = outward_normal(self.end - self.start);
+            for &vertex in [other.start, other.end].iter() {
+                for &side_normal in [normal, -normal].iter() {
+                    hit = earliest(
+                        hit,
+                        point_hit_offset_edge(
+                            vertex,
+                            -movement,
+                            self.start,
+                            self.end,
+                            side_normal,
+                            self.radius,
+                            tolerances,
+                        ).and_then(|hit| hit.flip().facing(edge_normal, tolerances)),
+                    );
+                }
+            }
+        }
+        hit.map(RoundHit::to_collision)
+    }
+
+    pub fn collide_with_stationary_rounded_segment(
+        &self,
+        other: &Self,
+        movement: Vector2<S>,
+        tolerances: &Tolerances<S>,
+    ) -> Option<LeftSolidEdgeCollision<S>> {
+        let radius = self.radius + other.radius;
+        let mut hit = None;
+        for &point in self.endpoints().iter() {
+            hit = earliest(hit, other.point_hit(point, movement, radius, tolerances));
+        }
+        for &point in other.endpoints().iter() {
+            hit = earliest(
+                hit,
+                self.point_hit(point, -movement, radius, tolerances)
+                    .map(RoundHit::flip),
+            );
+        }
+        hit.map(RoundHit::to_collision)
+    }
+}
+
+pub fn default_edge_properties() -> EdgeProperties {
+    EdgeProperties::new(channels::MAIN | channels::FLOOR)
+}
+
+pub trait Round<S: Scalar> {
+    fn rounded_segment(&self) -> RoundedSegment<S>;
+    fn edge_properties(&self) -> EdgeProperties;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_hit(
+        collision: Option<LeftSolidEdgeCollision<f64>>,
+        movement: Vector2<f64>,
+        movement_multiplier: f64,
+        normal: Vector2<f64>,
+    ) {
+        let collision = collision.expect("No collision");
+        assert!((collision.movement_multiplier() - movement_multiplier).abs() < 0.000001);
+        assert!((collision.normal(movement) - normal).magnitude() < 0.000001);
+    }
+
+    #[test]
+    fn circle_hits_edge_face() {
+        let circle = RoundedSegment::new(vec2(0., 0.), vec2(0., 0.), 1.);
+        let floor = LeftSolidEdge::new(vec2(-10., 5.), vec2(10., 5.));
+        let movement = vec2(0., 10.);
+        let collision =
+            circle.collide_with_stationary_edge(&floor, movement, &Tolerances::default());
+        assert_hit(collision, movement, 0.4, vec2(0., -1.));
+    }
+
+    #[test]
+    fn circle_hits_edge_vertex() {
+        // the top of a wall facing left, which the circle's centre passes above
+        let circle = RoundedSegment::new(vec2(0., 0.), vec2(0., 0.), 1.);
+        let wall = LeftSolidEdge::new(vec2(5., 10.), vec2(5., 0.6));
+        let movement = vec2(10., 0.);
+        let collision =
+            circle.collide_with_stationary_edge(&wall, movement, &Tolerances::default());
+        assert_hit(collision, movement, 0.42, vec2(-0.8, -0.6));
+    }
+
+    #[test]
+    fn capsule_side_hits_edge() {
+        // an upright capsule running into the corner of a ledge level with its centre
+        let capsule = RoundedSegment::new(vec2(0., -5.), vec2(0., 5.), 1.);
+        let ledge = LeftSolidEdge::new(vec2(5., 0.), vec2(15., 0.));
+        let movement = vec2(10., 0.);
+        let tolerances = Tolerances::default();
+        let collision =
+            capsule.collide_with_stationary_edge(&ledge, movement, &tolerances);
+        assert_hit(collision, movement, 0.4, vec2(-1., 0.));
+    }
+
+    #[test]
+    fn round_hits_round() {
+        let circle = RoundedSegment::new(vec2(0., 0.), vec2(0., 0.), 1.);
+        let tolerances = Tolerances::default();
+        let other = RoundedSegment::new(vec2(8., 0.), vec2(8., 0.), 2.);
+        let movement = vec2(10., 0.);
+        let collision =
+            circle.collide_with_stationary_rounded_segment(&other, movement, &tolerances);
+        assert_hit(collision, movement, 0.5, vec2(-1., 0.));
+        // the side of a capsule lying across the path
+        let other = RoundedSegment::new(vec2(8., -5.), vec2(8., 5.), 2.);
+        let collision =
+            circle.collide_with_stationary_rounded_segment(&other, movement, &tolerances);
+        assert_hit(collision, movement, 0.5, vec2(-1., 0.));
+        // and its rounded end, hit at 45 degrees
+        let movement = vec2(0., 10.);
+        let end = vec2(3. / 2f64.sqrt(), 8.);
+        let other = RoundedSegment::new(end, end + vec2(10., 0.), 2.);
+        let collision =
+            circle.collide_with_stationary_rounded_segment(&other, movement, &tolerances);
+        let multiplier = (8. - 3. / 2f64.sqrt()) / 10.;
+        let normal = vec2(-1., -1.) / 2f64.sqrt();
+        assert_hit(collision, movement, multiplier, normal);
+    }
+}
@@ -0,0 +1,662 @@
+use approx::ApproxEq;
+use cgmath::BaseFloat;
+use num_traits::{Float, Num, NumCast, One, ToPrimitive, Zero};
+use predicates;
+use std::cmp::Ordering;
+use std::fmt;
+use std::num::FpCategory;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub,
+               SubAssign};
+
+// everything the geometry and movement code needs from a number
+pub trait Scalar: BaseFloat + Default + fmt::Display {
+    fn from_f64(f: f64) -> Self;
+    fn as_f64(self) -> f64;
+    // sign of the exact sum of the products of each pair of terms
+    fn product_sum_sign(terms: &[(Self, Self)]) -> Ordering;
+}
+
+impl Scalar for f64 {
+    fn from_f64(f: f64) -> Self {
+        f
+    }
+    fn as_f64(self) -> f64 {
+        self
+    }
+    fn product_sum_sign(terms: &[(Self, Self)]) -> Ordering {
+        predicates::f64_product_sum_sign(terms)
+    }
+}
+
+// shorthand for constants in code which is generic over the scalar
+pub fn real<S: Scalar>(f: f64) -> S {
+    S::from_f64(f)
+}
+
+const FRACTIONAL_BITS: u32 = 32;
+const ONE_BITS: i64 = 1 << FRACTIONAL_BITS;
+
+const FIXED_PI: Fixed = Fixed::from_f64(::std::f64::consts::PI);
+const FIXED_FRAC_PI_2: Fixed = Fixed::from_f64(::std::f64::consts::FRAC_PI_2);
+const FIXED_TWO_PI: Fixed = Fixed::from_f64(2. * ::std::f64::consts::PI);
+
+// 32.32 fixed point number. Arithmetic, sqrt, sin and cos are integer
+// operations, so results are identical on every machine. The remaining
+// transcendental functions are computed via f64 and are only as portable as
+// the platform's libm.
+// Arithmetic saturates at the extreme values, which stand in for infinity
+// and are also the result of dividing by zero. Arithmetic never produces
+// nan, which is only there to satisfy Float.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    pub const fn from_f64(f: f64) -> Self {
+        let scaled = f * ONE_BITS as f64;
+        if scaled >= 0. {
+            Fixed((scaled + 0.5) as i64)
+        } else {
+            Fixed((scaled - 0.5) as i64)
+        }
+    }
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / ONE_BITS as f64
+    }
+    fn saturate(wide: i128) -> Self {
+        if wide > i64::MAX as i128 {
+            Fixed(i64::MAX)
+        } else if wide < -i64::MAX as i128 {
+            Fixed(-i64::MAX)
+        } else {
+            Fixed(wide as i64)
+        }
+    }
+    fn via_f64<F: FnOnce(f64) -> f64>(self, f: F) -> Self {
+        Fixed::from_f64(f(self.to_f64()))
+    }
+
+    pub fn abs(self) -> Self {
+        if self.0 < 0 {
+            -self
+        } else {
+            self
+        }
+    }
+    pub fn signum(self) -> Self {
+        match self.0.cmp(&0) {
+            Ordering::Less => -Fixed::one(),
+            Ordering::Equal => Fixed::zero(),
+            Ordering::Greater => Fixed::one(),
+        }
+    }
+    pub fn min(self, other: Self) -> Self {
+        Ord::min(self, other)
+    }
+    pub fn max(self, other: Self) -> Self {
+        Ord::max(self, other)
+    }
+    pub fn floor(self) -> Self {
+        Fixed(self.0 & !(ONE_BITS - 1))
+    }
+    pub fn ceil(self) -> Self {
+        -(-self).floor()
+    }
+    pub fn round(self) -> Self {
+        if self.0 < 0 {
+            -(-self).round()
+        } else {
+            Fixed(self.0 + ONE_BITS / 2).floor()
+        }
+    }
+    pub fn trunc(self) -> Self {
+        if self.0 < 0 {
+            self.ceil()
+        } else {
+            self.floor()
+        }
+    }
+    pub fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Fixed::zero();
+        }
+        // integer square root of the value scaled by one more factor of 2^32
+        let n = (self.0 as u128) << FRACTIONAL_BITS;
+        let mut root = 0u128;
+        let mut bit = 1u128 << 126;
+        while bit > n {
+            bit >>= 2;
+        }
+        let mut rest = n;
+        while bit != 0 {
+            if rest >= root + bit {
+                rest -= root + bit;
+                root = (root >> 1) + bit;
+            } else {
+                root >>= 1;
+            }
+            bit >>= 2;
+        }
+        Fixed(root as i64)
+    }
+    pub fn atan2(self, other: Self) -> Self {
+        Fixed::from_f64(self.to_f64().atan2(other.to_f64()))
+    }
+    pub fn sin(self) -> Self {
+        if !self.is_finite() {
+            return Fixed::nan();
+        }
+        // reduce to [-pi/2, pi/2], where sin is symmetric about pi/2
+        let mut x = self % FIXED_TWO_PI;
+        if x > FIXED_PI {
+            x -= FIXED_TWO_PI;
+        } else if x < -FIXED_PI {
+            x += FIXED_TWO_PI;
+        }
+        if x > FIXED_FRAC_PI_2 {
+            x = FIXED_PI - x;
+        } else if x < -FIXED_FRAC_PI_2 {
+            x = -FIXED_PI - x;
+        }
+        // taylor series up to x^13, whose error is below the resolution
+        let x2 = x * x;
+        let mut series = Fixed::one();
+        for &divisor in &[156, 110, 72, 42, 20, 6] {
+            series = Fixed::one() - x2 * series / Fixed(divisor * ONE_BITS);
+        }
+        x * series
+    }
+    pub fn cos(self) -> Self {
+        (self + FIXED_FRAC_PI_2).sin()
+    }
+    pub fn sin_cos(self) -> (Self, Self) {
+        (self.sin(), self.cos())
+    }
+}
+
+impl fmt::Debug for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&Fixed::to_f64(*self), f)
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&Fixed::to_f64(*self), f)
+    }
+}
+
+impl Scalar for Fixed {
+    fn from_f64(f: f64) -> Self {
+        Fixed::from_f64(f)
+    }
+    fn as_f64(self) -> f64 {
+        self.to_f64()
+    }
+    // products of two 32.32 numbers are exact in 64.64
+    fn product_sum_sign(terms: &[(Self, Self)]) -> Ordering {
+        terms
+            .iter()
+            .map(|&(a, b)| a.0 as i128 * b.0 as i128)
+            .sum::<i128>()
+            .cmp(&0)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Fixed::saturate(self.0 as i128 + other.0 as i128)
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Fixed::saturate(self.0 as i128 - other.0 as i128)
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        Fixed::saturate((self.0 as i128 * other.0 as i128) >> FRACTIONAL_BITS)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        if other.0 == 0 {
+            return match self.0.cmp(&0) {
+                Ordering::Less => Fixed::neg_infinity(),
+                Ordering::Equal => Fixed::zero(),
+                Ordering::Greater => Fixed::infinity(),
+            };
+        }
+        Fixed::saturate(((self.0 as i128) << FRACTIONAL_BITS) / other.0 as i128)
+    }
+}
+
+impl Rem for Fixed {
+    type Output = Self;
+    fn rem(self, other: Self) -> Self {
+        if other.0 == 0 {
+            return Fixed::zero();
+        }
+        Fixed(self.0.wrapping_rem(other.0))
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Self;
+    // nan is the only value without a negation, and stays nan
+    fn neg(self) -> Self {
+        Fixed(self.0.wrapping_neg())
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl MulAssign for Fixed {
+    fn mul_assign(&mut self, other: Self) {
+        *self = *self * other;
+    }
+}
+
+impl DivAssign for Fixed {
+    fn div_assign(&mut self, other: Self) {
+        *self = *self / other;
+    }
+}
+
+impl RemAssign for Fixed {
+    fn rem_assign(&mut self, other: Self) {
+        *self = *self % other;
+    }
+}
+
+impl Zero for Fixed {
+    fn zero() -> Self {
+        Fixed(0)
+    }
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl One for Fixed {
+    fn one() -> Self {
+        Fixed(ONE_BITS)
+    }
+}
+
+impl Num for Fixed {
+    type FromStrRadixErr = <f64 as Num>::FromStrRadixErr;
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        f64::from_str_radix(s, radix).map(Fixed::from_f64)
+    }
+}
+
+impl ToPrimitive for Fixed {
+    fn to_i64(&self) -> Option<i64> {
+        Some(self.trunc().0 >> FRACTIONAL_BITS)
+    }
+    fn to_u64(&self) -> Option<u64> {
+        self.to_i64().and_then(|i| i.to_u64())
+    }
+    fn to_f64(&self) -> Option<f64> {
+        Some(Fixed::to_f64(*self))
+    }
+}
+
+impl NumCast for Fixed {
+    fn from<T: ToPrimitive>(n: T) -> Option<Self> {
+        n.to_f64().map(Fixed::from_f64)
+    }
+}
+
+impl ApproxEq for Fixed {
+    type Epsilon = Self;
+    fn default_epsilon() -> Self {
+        Fixed(1)
+    }
+    fn default_max_relative() -> Self {
+        Fixed(1)
+    }
+    fn default_max_ulps() -> u32 {
+        1
+    }
+    fn relative_eq(&self, other: &Self, epsilon: Self, max_relative: Self) -> bool {
+        let difference = (*self - *other).abs();
+        difference <= epsilon || difference <= self.abs().max(other.abs()) * max_relative
+    }
+    fn ulps_eq(&self, other: &Self, epsilon: Self, max_ulps: u32) -> bool {
+        (*self - *other).abs() <= epsilon || (self.0 - other.0).abs() <= max_ulps as i64
+    }
+}
+
+impl Float for Fixed {
+    fn nan() -> Self {
+        Fixed(i64::MIN)
+    }
+    fn infinity() -> Self {
+        Fixed(i64::MAX)
+    }
+    fn neg_infinity() -> Self {
+        Fixed(-i64::MAX)
+    }
+    fn neg_zero() -> Self {
+        Fixed(0)
+    }
+    fn min_value() -> Self {
+        Fixed(-i64::MAX + 1)
+    }
+    fn min_positive_value() -> Self {
+        Fixed(1)
+    }
+    fn epsilon() -> Self {
+        Fixed(1)
+    }
+    fn max_value() -> Self {
+        Fixed(i64::MAX - 1)
+    }
+    fn is_nan(self) -> bool {
+        self.0 == i64::MIN
+    }
+    fn is_infinite(self) -> bool {
+        self.0 == i64::MAX || self.0 == -i64::MAX
+    }
+    fn is_finite(self) -> bool {
+        !self.is_nan() && !self.is_infinite()
+    }
+    fn is_normal(self) -> bool {
+        self.is_finite() && self.0 != 0
+    }
+    fn classify(self) -> FpCategory {
+        if self.is_nan() {
+            FpCategory::Nan
+        } else if self.is_infinite() {
+            FpCategory::Infinite
+        } else if self.0 == 0 {
+            FpCategory::Zero
+        } else {
+            FpCategory::Normal
+        }
+    }
+    fn floor(self) -> Self {
+        Fixed::floor(self)
+    }
+    fn ceil(self) -> Self {
+        Fixed::ceil(self)
+    }
+    fn round(self) -> Self {
+        Fixed::round(self)
+    }
+    fn trunc(self) -> Self {
+        Fixed::trunc(self)
+    }
+    fn fract(self) -> Self {
+        self - self.trunc()
+    }
+    fn abs(self) -> Self {
+        Fixed::abs(self)
+    }
+    fn signum(self) -> Self {
+        Fixed::signum(self)
+    }
+    fn is_sign_positive(self) -> bool {
+        self.0 >= 0
+    }
+    fn is_sign_negative(self) -> bool {
+        self.0 < 0
+    }
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        self * a + b
+    }
+    fn recip(self) -> Self {
+        Fixed::one() / self
+    }
+    fn powi(self, n: i32) -> Self {
+        let mut result = Fixed::one();
+        for _ in 0..n.abs() {
+            result *= self;
+        }
+        if n < 0 {
+            result.recip()
+        } else {
+            result
+        }
+    }
+    fn powf(self, n: Self) -> Self {
+        Fixed::from_f64(self.to_f64().powf(n.to_f64()))
+    }
+    fn sqrt(self) -> Self {
+        Fixed::sqrt(self)
+    }
+    fn exp(self) -> Self {
+        self.via_f64(f64::exp)
+    }
+    fn exp2(self) -> Self {
+        self.via_f64(f64::exp2)
+    }
+    fn ln(self) -> Self {
+        self.via_f64(f64::ln)
+    }
+    fn log(self, base: Self) -> Self {
+        Fixed::from_f64(self.to_f64().log(base.to_f64()))
+    }
+    fn log2(self) -> Self {
+        self.via_f64(f64::log2)
+    }
+    fn log10(self) -> Self {
+        self.via_f64(f64::log10)
+    }
+    fn max(self, other: Self) -> Self {
+        Fixed::max(self, other)
+    }
+    fn min(self, other: Self) -> Self {
+        Fixed::min(self, other)
+    }
+    fn abs_sub(self, other: Self) -> Self {
+        (self - other).max(Fixed::zero())
+    }
+    fn cbrt(self) -> Self {
+        self.via_f64(f64::cbrt)
+    }
+    fn hypot(self, other: Self) -> Self {
+        (self * self + other * other).sqrt()
+    }
+    fn sin(self) -> Self {
+        Fixed::sin(self)
+    }
+    fn cos(self) -> Self {
+        Fixed::cos(self)
+    }
+    fn tan(self) -> Self {
+        self.via_f64(f64::tan)
+    }
+    fn asin(self) -> Self {
+        self.via_f64(f64::asin)
+    }
+    fn acos(self) -> Self {
+        self.via_f64(f64::acos)
+    }
+    fn atan(self) -> Self {
+        self.via_f64(f64::atan)
+    }
+    fn atan2(self, other: Self) -> Self {
+        Fixed::atan2(self, other)
+    }
+    fn sin_cos(self) -> (Self, Self) {
+        Fixed::sin_cos(self)
+    }
+    fn exp_m1(self) -> Self {
+        self.via_f64(f64::exp_m1)
+    }
+    fn ln_1p(self) -> Self {
+        self.via_f64(f64::ln_1p)
+    }
+    fn sinh(self) -> Self {
+        self.via_f64(f64::sinh)
+    }
+    fn cosh(self) -> Self {
+        self.via_f64(f64::cosh)
+    }
+    fn tanh(self) -> Self {
+        self.via_f64(f64::tanh)
+    }
+    fn asinh(self) -> Self {
+        self.via_f64(f64::asinh)
+    }
+    fn acosh(self) -> Self {
+        self.via_f64(f64::acosh)
+    }
+    fn atanh(self) -> Self {
+        self.via_f64(f64::atanh)
+    }
+    fn integer_decode(self) -> (u64, i16, i8) {
+        let sign = if self.0 < 0 { -1 } else { 1 };
+        (self.0.unsigned_abs(), -(FRACTIONAL_BITS as i16), sign)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::{GameState, GameStateChanges, InputModel};
+    use movement::MovementContext;
+
+    fn fixed(f: f64) -> Fixed {
+        Fixed::from_f64(f)
+    }
+
+    #[test]
+    fn arithmetic_is_exact_for_dyadic_values() {
+        assert_eq!(fixed(1.5) + fixed(-2.25), fixed(-0.75));
+        assert_eq!(fixed(1.5) - fixed(-2.25), fixed(3.75));
+        assert_eq!(fixed(1.5) * fixed(-2.25), fixed(-3.375));
+        assert_eq!(fixed(-3.375) / fixed(1.5), fixed(-2.25));
+        assert_eq!(fixed(7.5) % fixed(2.), fixed(1.5));
+    }
+
+    #[test]
+    fn arithmetic_saturates() {
+        let max = Fixed::max_value();
+        assert_eq!(max + fixed(1.), Fixed::infinity());
+        assert_eq!(-max - fixed(1.), Fixed::neg_infinity());
+        assert_eq!(max * fixed(2.), Fixed::infinity());
+        assert_eq!(max * fixed(-2.), Fixed::neg_infinity());
+        assert_eq!(Fixed::infinity() + Fixed::infinity(), Fixed::infinity());
+        assert!(!(Fixed::neg_infinity() - Fixed::infinity()).is_nan());
+    }
+
+    #[test]
+    fn division_by_zero_saturates() {
+        assert_eq!(fixed(1.) / fixed(0.), Fixed::infinity());
+        assert_eq!(fixed(-1.) / fixed(0.), Fixed::neg_infinity());
+        assert_eq!(fixed(0.) / fixed(0.), fixed(0.));
+        assert_eq!(fixed(1.) % fixed(0.), fixed(0.));
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(-Fixed::infinity(), Fixed::neg_infinity());
+        assert_eq!(-Fixed::neg_infinity(), Fixed::infinity());
+        assert!((-Fixed::nan()).is_nan());
+        assert_eq!(fixed(-2.5).abs(), fixed(2.5));
+    }
+
+    #[test]
+    fn sqrt() {
+        assert_eq!(fixed(4.).sqrt(), fixed(2.));
+        assert_eq!(fixed(0.25).sqrt(), fixed(0.5));
+        assert_eq!(fixed(0.).sqrt(), fixed(0.));
+        assert_eq!(fixed(-1.).sqrt(), fixed(0.));
+        // rounded down to the nearest representable value
+        let root = fixed(2.).sqrt();
+        assert!(root * root <= fixed(2.));
+        assert!(Fixed(root.0 + 1) * Fixed(root.0 + 1) >= fixed(2.));
+        assert!((root.to_f64() - 2f64.sqrt()).abs() < 1. / ONE_BITS as f64);
+    }
+
+    #[test]
+    fn product_sum_sign_is_exact() {
+        let smallest = Fixed::min_positive_value();
+        // the product rounds to zero, but its sign is still found
+        assert_eq!(smallest * smallest, fixed(0.));
+        assert_eq!(
+            Fixed::product_sum_sign(&[(smallest, smallest)]),
+            Ordering::Greater
+        );
+        let big = fixed(1000000.);
+        assert_eq!(
+            Fixed::product_sum_sign(&[(big, big), (-big, big), (-smallest, smallest)]),
+            Ordering::Less
+        );
+        assert_eq!(
+            Fixed::product_sum_sign(&[(fixed(3.), fixed(2.)), (fixed(-1.5), fixed(4.))]),
+            Ordering::Equal
+        );
+    }
+
+    // runs the demo with the player running left, then right, while jumping
+    fn run_demo(frames: u64) -> Vec<i64> {
+        let mut game_state = GameState::<Fixed>::new();
+        let mut changes = GameStateChanges::default();
+        let mut input = InputModel::default();
+        let mut movement_context = MovementContext::default();
+        game_state.init_demo(&mut movement_context);
+        let mut bits = Vec::new();
+        for frame in 0..frames {
+            let left = frame < frames / 2;
+            input.set_left(if left { Fixed::one() } else { Fixed::zero() });
+            input.set_right(if left { Fixed::zero() } else { Fixed::one() });
+            input.set_jump(frame % 70 < 20);
+            game_state.update(&input, &mut changes, &mut movement_context);
+            input.after_process();
+            for update in game_state.render_updates() {
+                bits.push(update.position.x.0);
+                bits.push(update.position.y.0);
+            }
+        }
+        bits
+    }
+
+    #[test]
+    fn sin_and_cos() {
+        assert_eq!(fixed(0.).sin(), fixed(0.));
+        assert!(Fixed::infinity().sin().is_nan());
+        for i in -1000..1000 {
+            let x = i as f64 * 0.0123;
+            assert!((fixed(x).sin().to_f64() - x.sin()).abs() < 1e-8, "sin({})", x);
+            assert!((fixed(x).cos().to_f64() - x.cos()).abs() < 1e-8, "cos({})", x);
+        }
+    }
+
+    // FNV-1a, which unlike the std hasher is specified and will never change
+    fn hash(bits: &[i64]) -> u64 {
+        let mut hash = 0xcbf29ce484222325u64;
+        for bits in bits {
+            for byte in bits.to_le_bytes().iter() {
+                hash = (hash ^ *byte as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+
+    #[test]
+    fn fixed_point_runs_match_recorded_trace() {
+        let bits = run_demo(600);
+        assert_eq!(hash(&bits), 10381381729121268263);
+    }
+}
@@ -1,112 +1,395 @@
 use aabb::Aabb;
 use axis_aligned_rect;
-use best::BestMultiSet;
-use cgmath::Vector2;
-use collide::{Collide, CollidePosition, Collision};
+use capsule::Capsule;
+use chain::Chain;
+use cgmath::{vec2, Vector2};
+use circle::Circle;
+use collide::{Collide, CollidePosition, Collisions, RoundPosition};
+use convex_polygon::ConvexPolygon;
+use layer::Layer;
+use material::MaterialId;
 use line_segment::LineSegment;
 use movement::EntityId;
+use oriented_rect::OrientedRect;
+use raycast::{Ray, RaycastHit};
+use round::Round;
+use scalar::{real, Scalar};
+use tile_map::TileMap;
 
 #[derive(Debug, Clone)]
-pub enum Shape {
-    AxisAlignedRect(axis_aligned_rect::AxisAlignedRect),
-    LineSegment(LineSegment),
+pub enum Shape<S> {
+    AxisAlignedRect(axis_aligned_rect::AxisAlignedRect<S>),
+    LineSegment(LineSegment<S>),
+    ConvexPolygon(ConvexPolygon<S>),
+    OrientedRect(OrientedRect<S>),
+    TileMap(TileMap<S>),
+    Chain(Chain<S>),
+    Circle(Circle<S>),
+    Capsule(Capsule<S>),
+    Compound(Vec<(Vector2<S>, Shape<S>)>),
 }
 
 #[derive(Debug)]
-pub struct ShapePosition<'a> {
+pub struct ShapePosition<'a, S> {
     pub entity_id: EntityId,
-    pub position: Vector2<f64>,
-    pub shape: &'a Shape,
+    pub layer: Layer,
+    pub material: MaterialId,
+    pub position: Vector2<S>,
+    pub shape: &'a Shape<S>,
 }
 
-impl<'a> ShapePosition<'a> {
-    fn aabb(&self) -> Aabb {
+impl<'a, S: Scalar> ShapePosition<'a, S> {
+    fn aabb(&self) -> Aabb<S> {
         self.shape.aabb(self.position)
     }
-    pub fn movement_aabb(&self, movement: Vector2<f64>) -> Aabb {
+    pub fn movement_aabb(&self, movement: Vector2<S>) -> Aabb<S> {
         let current_aabb = self.aabb();
         let moved_aabb = self.shape.aabb(self.position + movement);
         current_aabb.union(&moved_aabb)
     }
 
-    fn stationary_collision_test<C: Collide>(
+    fn collide_position<'b, C: Collide<S>>(
         &self,
-        moving: CollidePosition<C>,
-        movement: Vector2<f64>,
-        closest_collisions: &mut BestMultiSet<Collision>,
+        collide: &'b C,
+        child: Option<usize>,
+    ) -> CollidePosition<'b, S, C> {
+        CollidePosition {
+            collide,
+            position: self.position,
+            entity_id: self.entity_id,
+            layer: self.layer,
+            material: self.material,
+            child,
+        }
+    }
+
+    fn round_position<R: Round<S>>(
+        &self,
+        round: &R,
+        child: Option<usize>,
+    ) -> RoundPosition<S> {
+        RoundPosition {
+            rounded_segment: round.rounded_segment().add_vector(self.position),
+            edge_properties: round.edge_properties(),
+            entity_id: self.entity_id,
+            layer: round.edge_properties().layer.unwrap_or(self.layer),
+            material: round.edge_properties().material.unwrap_or(self.material),
+            child,
+        }
+    }
+
+    fn for_each_child<F>(&self, children: &'a [(Vector2<S>, Shape<S>)], mut f: F)
+    where
+        F: FnMut(usize, ShapePosition<'a, S>),
+    {
+        for (index, &(offset, ref shape)) in children.iter().enumerate() {
+            f(
+                index,
+                ShapePosition {
+                    entity_id: self.entity_id,
+                    layer: self.layer,
+                    material: self.material,
+                    position: self.position + offset,
+                    shape,
+                },
+            );
+        }
+    }
+
+    fn stationary_collision_test<C: Collide<S>>(
+        &self,
+        moving: &CollidePosition<S, C>,
+        child: Option<usize>,
+        movement: Vector2<S>,
+        collisions: &mut Collisions<S>,
     ) {
-        let position = self.position;
-        let entity_id = self.entity_id;
         match self.shape {
-            Shape::AxisAlignedRect(stationary) => {
-                let collide_position = CollidePosition {
-                    collide: stationary,
-                    position,
-                    entity_id,
-                };
-                moving.movement_collision_test(
-                    collide_position,
-                    movement,
-                    closest_collisions,
-                );
+            Shape::Compound(children) => {
+                self.for_each_child(children, |index, stationary| {
+                    stationary.stationary_collision_test(
+                        moving,
+                        child.or(Some(index)),
+                        movement,
+                        collisions,
+                    )
+                })
             }
-            Shape::LineSegment(stationary) => {
-                let collide_position = CollidePosition {
-                    collide: stationary,
-                    position,
-                    entity_id,
-                };
-                moving.movement_collision_test(
-                    collide_position,
-                    movement,
-                    closest_collisions,
-                );
+            Shape::AxisAlignedRect(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::LineSegment(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::ConvexPolygon(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::OrientedRect(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::TileMap(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::Chain(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::Circle(stationary) => moving.movement_collision_test_round(
+                self.round_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::Capsule(stationary) => moving.movement_collision_test_round(
+                self.round_position(stationary, child),
+                movement,
+                collisions,
+            ),
+        }
+    }
+
+    fn stationary_collision_test_round(
+        &self,
+        moving: &RoundPosition<S>,
+        child: Option<usize>,
+        movement: Vector2<S>,
+        collisions: &mut Collisions<S>,
+    ) {
+        match self.shape {
+            Shape::Compound(children) => {
+                self.for_each_child(children, |index, stationary| {
+                    stationary.stationary_collision_test_round(
+                        moving,
+                        child.or(Some(index)),
+                        movement,
+                        collisions,
+                    )
+                })
             }
+            Shape::AxisAlignedRect(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::LineSegment(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::ConvexPolygon(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::OrientedRect(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::TileMap(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::Chain(stationary) => moving.movement_collision_test(
+                self.collide_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::Circle(stationary) => moving.movement_collision_test_round(
+                self.round_position(stationary, child),
+                movement,
+                collisions,
+            ),
+            Shape::Capsule(stationary) => moving.movement_collision_test_round(
+                self.round_position(stationary, child),
+                movement,
+                collisions,
+            ),
         }
     }
 
     pub fn movement_collision_test(
         &self,
-        stationary: ShapePosition,
-        movement: Vector2<f64>,
-        closest_collisions: &mut BestMultiSet<Collision>,
+        stationary: ShapePosition<S>,
+        movement: Vector2<S>,
+        collisions: &mut Collisions<S>,
+    ) {
+        self.movement_collision_test_child(None, &stationary, movement, collisions);
+    }
+
+    fn movement_collision_test_child(
+        &self,
+        child: Option<usize>,
+        stationary: &ShapePosition<S>,
+        movement: Vector2<S>,
+        collisions: &mut Collisions<S>,
     ) {
-        let position = self.position;
-        let entity_id = self.entity_id;
         match self.shape {
-            Shape::AxisAlignedRect(moving) => {
-                let collide_position = CollidePosition {
-                    collide: moving,
-                    position,
-                    entity_id,
-                };
-                stationary.stationary_collision_test(
-                    collide_position,
-                    movement,
-                    closest_collisions,
-                )
+            Shape::Compound(children) => {
+                self.for_each_child(children, |index, moving| {
+                    moving.movement_collision_test_child(
+                        child.or(Some(index)),
+                        stationary,
+                        movement,
+                        collisions,
+                    )
+                })
             }
-            Shape::LineSegment(moving) => {
-                let collide_position = CollidePosition {
-                    collide: moving,
-                    position,
-                    entity_id,
-                };
-                stationary.stationary_collision_test(
-                    collide_position,
-                    movement,
-                    closest_collisions,
-                )
+            Shape::AxisAlignedRect(moving) => stationary.stationary_collision_test(
+                &self.collide_position(moving, child),
+                None,
+                movement,
+                collisions,
+            ),
+            Shape::LineSegment(moving) => stationary.stationary_collision_test(
+                &self.collide_position(moving, child),
+                None,
+                movement,
+                collisions,
+            ),
+            Shape::ConvexPolygon(moving) => stationary.stationary_collision_test(
+                &self.collide_position(moving, child),
+                None,
+                movement,
+                collisions,
+            ),
+            Shape::OrientedRect(moving) => stationary.stationary_collision_test(
+                &self.collide_position(moving, child),
+                None,
+                movement,
+                collisions,
+            ),
+            Shape::TileMap(moving) => stationary.stationary_collision_test(
+                &self.collide_position(moving, child),
+                None,
+                movement,
+                collisions,
+            ),
+            Shape::Chain(moving) => stationary.stationary_collision_test(
+                &self.collide_position(moving, child),
+                None,
+                movement,
+                collisions,
+            ),
+            Shape::Circle(moving) => stationary.stationary_collision_test_round(
+                &self.round_position(moving, child),
+                None,
+                movement,
+                collisions,
+            ),
+            Shape::Capsule(moving) => stationary.stationary_collision_test_round(
+                &self.round_position(moving, child),
+                None,
+                movement,
+                collisions,
+            ),
+        }
+    }
+
+    pub fn for_each_ray_hit<F: FnMut(RaycastHit<S>)>(&self, ray: &Ray<S>, f: &mut F) {
+        self.for_each_ray_hit_child(None, ray, f);
+    }
+
+    fn for_each_ray_hit_child<F>(&self, child: Option<usize>, ray: &Ray<S>, f: &mut F)
+    where
+        F: FnMut(RaycastHit<S>),
+    {
+        match self.shape {
+            Shape::Compound(children) => {
+                self.for_each_child(children, |index, shape_position| {
+                    shape_position.for_each_ray_hit_child(child.or(Some(index)), ray, f)
+                })
+            }
+            Shape::AxisAlignedRect(shape) => {
+                ray.for_each_collide_hit(&self.collide_position(shape, child), &mut *f)
+            }
+            Shape::LineSegment(shape) => {
+                ray.for_each_collide_hit(&self.collide_position(shape, child), &mut *f)
+            }
+            Shape::ConvexPolygon(shape) => {
+                ray.for_each_collide_hit(&self.collide_position(shape, child), &mut *f)
+            }
+            Shape::OrientedRect(shape) => {
+                ray.for_each_collide_hit(&self.collide_position(shape, child), &mut *f)
+            }
+            Shape::TileMap(shape) => {
+                ray.for_each_collide_hit(&self.collide_position(shape, child), &mut *f)
+            }
+            Shape::Chain(shape) => {
+                ray.for_each_collide_hit(&self.collide_position(shape, child), &mut *f)
+            }
+            Shape::Circle(shape) => {
+                if let Some(hit) = ray.round_hit(&self.round_position(shape, child)) {
+                    f(hit);
+                }
+            }
+            Shape::Capsule(shape) => {
+                if let Some(hit) = ray.round_hit(&self.round_position(shape, child)) {
+                    f(hit);
+                }
             }
         }
     }
 }
 
-impl Shape {
-    pub fn aabb(&self, top_left: Vector2<f64>) -> Aabb {
-        match self {
-            &Shape::AxisAlignedRect(ref rect) => rect.aabb(top_left),
-            &Shape::LineSegment(ref line_segment) => line_segment.aabb(top_left),
+impl<S: Scalar> Shape<S> {
+    pub fn aabb(&self, top_left: Vector2<S>) -> Aabb<S> {
+        match *self {
+            Shape::AxisAlignedRect(ref rect) => rect.aabb(top_left),
+            Shape::LineSegment(ref line_segment) => line_segment.aabb(top_left),
+            Shape::ConvexPolygon(ref convex_polygon) => convex_polygon.aabb(top_left),
+            Shape::OrientedRect(ref oriented_rect) => oriented_rect.aabb(top_left),
+            Shape::TileMap(ref tile_map) => tile_map.aabb(top_left),
+            Shape::Chain(ref chain) => chain.aabb(top_left),
+            Shape::Circle(ref circle) => circle.aabb(top_left),
+            Shape::Capsule(ref capsule) => capsule.aabb(top_left),
+            Shape::Compound(ref children) => {
+                let mut children = children
+                    .iter()
+                    .map(|&(offset, ref shape)| shape.aabb(top_left + offset));
+                // an empty compound is a point at its position
+                let first = children
+                    .next()
+                    .unwrap_or_else(|| Aabb::new(top_left, vec2(real(0.), real(0.))));
+                children.fold(first, |acc, aabb| acc.union(&aabb))
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axis_aligned_rect::AxisAlignedRect;
+
+    #[test]
+    fn compound_aabb_is_union_of_children() {
+        let shape: Shape<f64> = Shape::Compound(vec![
+            (
+                vec2(real(0.), real(0.)),
+                Shape::AxisAlignedRect(AxisAlignedRect::new(vec2(real(10.), real(20.)))),
+            ),
+            (vec2(real(5.), real(30.)), Shape::Circle(Circle::new(real(4.)))),
+        ]);
+        let aabb = shape.aabb(vec2(real(100.), real(100.)));
+        assert_eq!(aabb.top_left(), vec2(real(100.), real(100.)));
+        assert_eq!(aabb.bottom_right_coord(), vec2(real(110.), real(134.)));
+    }
+
+    #[test]
+    fn empty_compound_aabb_is_a_point() {
+        let aabb = Shape::<f64>::Compound(Vec::new()).aabb(vec2(real(3.), real(4.)));
+        assert_eq!(aabb.top_left(), vec2(real(3.), real(4.)));
+        assert_eq!(aabb.size(), vec2(real(0.), real(0.)));
+    }
+}
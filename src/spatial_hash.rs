@@ -0,0 +1,199 @@
+use aabb::Aabb;
+use broadphase::{remove_handle, Broadphase, BroadphaseHandle, Entries};
+use cgmath::Vector2;
+use fnv::FnvHashMap;
+use scalar::Scalar;
+
+// entries covering more cells than this are kept in a list which every
+// query checks, rather than filling the grid
+const MAX_ENTRY_CELLS: i64 = 64;
+
+type Cell = (i64, i64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CellRange {
+    min: Cell,
+    max: Cell,
+}
+
+impl CellRange {
+    fn num_cells(&self) -> i64 {
+        let width = self.max.0.saturating_sub(self.min.0).saturating_add(1);
+        let height = self.max.1.saturating_sub(self.min.1).saturating_add(1);
+        width.saturating_mul(height)
+    }
+    fn for_each_cell<F: FnMut(Cell)>(&self, mut f: F) {
+        for y in self.min.1..=self.max.1 {
+            for x in self.min.0..=self.max.0 {
+                f((x, y));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry<T, S> {
+    aabb: Aabb<S>,
+    t: T,
+    // None for entries which are too big for the grid
+    cells: Option<CellRange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpatialHash<T, S> {
+    cell_size: S,
+    cells: FnvHashMap<Cell, Vec<BroadphaseHandle>>,
+    oversized: Vec<BroadphaseHandle>,
+    entries: Entries<Entry<T, S>>,
+}
+
+impl<T, S: Scalar> SpatialHash<T, S> {
+    pub fn new(cell_size: S) -> Self {
+        Self {
+            cell_size,
+            cells: Default::default(),
+            oversized: Vec::new(),
+            entries: Default::default(),
+        }
+    }
+
+    fn cell(&self, coord: Vector2<S>) -> Cell {
+        (
+            (coord.x / self.cell_size).floor().as_f64() as i64,
+            (coord.y / self.cell_size).floor().as_f64() as i64,
+        )
+    }
+
+    fn cell_range(&self, aabb: &Aabb<S>) -> CellRange {
+        CellRange {
+            min: self.cell(aabb.top_left()),
+            max: self.cell(aabb.bottom_right_coord()),
+        }
+    }
+
+    fn grid_cells(&self, aabb: &Aabb<S>) -> Option<CellRange> {
+        let range = self.cell_range(aabb);
+        if range.num_cells() <= MAX_ENTRY_CELLS {
+            Some(range)
+        } else {
+            None
+        }
+    }
+
+    fn attach(&mut self, handle: BroadphaseHandle, cells: Option<CellRange>) {
+        match cells {
+            Some(range) => {
+                let grid = &mut self.cells;
+                range.for_each_cell(|cell| grid.entry(cell).or_default().push(handle));
+            }
+            None => self.oversized.push(handle),
+        }
+    }
+
+    fn detach(&mut self, handle: BroadphaseHandle, cells: Option<CellRange>) {
+        match cells {
+            Some(range) => {
+                let grid = &mut self.cells;
+                range.for_each_cell(|cell| {
+                    let is_empty = match grid.get_mut(&cell) {
+                        Some(handles) => {
+                            remove_handle(handles, handle);
+                            handles.is_empty()
+                        }
+                        None => false,
+                    };
+                    if is_empty {
+                        grid.remove(&cell);
+                    }
+                });
+            }
+            None => remove_handle(&mut self.oversized, handle),
+        }
+    }
+
+    // Entries covering several cells are only reported from the first cell
+    // they share with the query, so nothing is reported twice.
+    fn for_each_matching<P, F>(&self, query: &Aabb<S>, predicate: P, f: &mut F)
+    where
+        P: Fn(&Aabb<S>) -> bool,
+        F: FnMut(&Aabb<S>, &T),
+    {
+        let range = self.cell_range(query);
+        if range.num_cells() > self.entries.len() as i64 {
+            for (_, entry) in self.entries.iter() {
+                if predicate(&entry.aabb) {
+                    f(&entry.aabb, &entry.t);
+                }
+            }
+            return;
+        }
+        for &handle in self.oversized.iter() {
+            let entry = self.entries.get(handle);
+            if predicate(&entry.aabb) {
+                f(&entry.aabb, &entry.t);
+            }
+        }
+        range.for_each_cell(|cell| {
+            if let Some(handles) = self.cells.get(&cell) {
+                for &handle in handles.iter() {
+                    let entry = self.entries.get(handle);
+                    let entry_range = entry.cells.expect("unexpected state");
+                    let first_shared_cell = (
+                        entry_range.min.0.max(range.min.0),
+                        entry_range.min.1.max(range.min.1),
+                    );
+                    if first_shared_cell == cell && predicate(&entry.aabb) {
+                        f(&entry.aabb, &entry.t);
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl<T, S: Scalar> Broadphase<T, S> for SpatialHash<T, S> {
+    fn insert(&mut self, aabb: Aabb<S>, t: T) -> BroadphaseHandle {
+        let cells = self.grid_cells(&aabb);
+        let handle = self.entries.insert(Entry { aabb, t, cells });
+        self.attach(handle, cells);
+        handle
+    }
+    fn update(&mut self, handle: BroadphaseHandle, aabb: Aabb<S>) {
+        let cells = self.grid_cells(&aabb);
+        let old_cells = {
+            let entry = self.entries.get_mut(handle);
+            entry.aabb = aabb;
+            entry.cells
+        };
+        if cells != old_cells {
+            self.detach(handle, old_cells);
+            self.attach(handle, cells);
+            self.entries.get_mut(handle).cells = cells;
+        }
+    }
+    fn remove(&mut self, handle: BroadphaseHandle) -> T {
+        let cells = self.entries.get(handle).cells;
+        self.detach(handle, cells);
+        self.entries.remove(handle).t
+    }
+    fn clear(&mut self) {
+        self.cells.clear();
+        self.oversized.clear();
+        self.entries.clear();
+    }
+    fn for_each_intersection<F: FnMut(&Aabb<S>, &T)>(&self, aabb: Aabb<S>, mut f: F) {
+        self.for_each_matching(&aabb, |other| other.is_intersecting(&aabb), &mut f);
+    }
+    fn for_each_segment_intersection<F: FnMut(&Aabb<S>, &T)>(
+        &self,
+        start: Vector2<S>,
+        end: Vector2<S>,
+        mut f: F,
+    ) {
+        self.for_each_matching(
+            &Aabb::from_points(&[start, end]),
+            |other| other.is_intersecting_segment(start, end),
+            &mut f,
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use aabb::Aabb;
+use broadphase::{remove_handle, Broadphase, BroadphaseHandle, Entries};
+use cgmath::Vector2;
+use scalar::Scalar;
+
+#[derive(Debug, Clone)]
+struct Entry<T, S> {
+    aabb: Aabb<S>,
+    t: T,
+    // index of the entry in the sorted order, and None for wide entries
+    rank: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SweepAndPrune<T, S> {
+    // sorted by the left side of each entry's aabb
+    order: Vec<BroadphaseHandle>,
+    // entries wider than max_width are kept in a list which every query
+    // checks, rather than in the sorted order
+    wide: Vec<BroadphaseHandle>,
+    entries: Entries<Entry<T, S>>,
+    // queries look this far to the left for entries which overlap them
+    max_width: S,
+}
+
+fn left<S: Scalar>(aabb: &Aabb<S>) -> S {
+    aabb.top_left().x
+}
+
+impl<T, S: Scalar> SweepAndPrune<T, S> {
+    pub fn new(max_width: S) -> Self {
+        Self {
+            order: Vec::new(),
+            wide: Vec::new(),
+            entries: Default::default(),
+            max_width,
+        }
+    }
+
+    fn is_wide(&self, aabb: &Aabb<S>) -> bool {
+        aabb.size().x > self.max_width
+    }
+
+    fn left_at(&self, rank: usize) -> S {
+        left(&self.entries.get(self.order[rank]).aabb)
+    }
+
+    fn update_ranks_from(&mut self, rank: usize) {
+        for rank in rank..self.order.len() {
+            self.entries.get_mut(self.order[rank]).rank = Some(rank);
+        }
+    }
+
+    fn swap_ranks(&mut self, a: usize, b: usize) {
+        self.order.swap(a, b);
+        self.entries.get_mut(self.order[a]).rank = Some(a);
+        self.entries.get_mut(self.order[b]).rank = Some(b);
+    }
+
+    fn attach(&mut self, handle: BroadphaseHandle) {
+        let aabb = self.entries.get(handle).aabb;
+        if self.is_wide(&aabb) {
+            self.entries.get_mut(handle).rank = None;
+            self.wide.push(handle);
+            return;
+        }
+        let rank = {
+            let entries = &self.entries;
+            self.order
+                .partition_point(|&handle| left(&entries.get(handle).aabb) <= left(&aabb))
+        };
+        self.order.insert(rank, handle);
+        self.update_ranks_from(rank);
+    }
+
+    fn detach(&mut self, handle: BroadphaseHandle) {
+        match self.entries.get(handle).rank {
+            Some(rank) => {
+                self.order.remove(rank);
+                self.update_ranks_from(rank);
+            }
+            None => remove_handle(&mut self.wide, handle),
+        }
+    }
+
+    fn for_each_matching<P, F>(&self, query: &Aabb<S>, predicate: P, f: &mut F)
+    where
+        P: Fn(&Aabb<S>) -> bool,
+        F: FnMut(&Aabb<S>, &T),
+    {
+        for &handle in self.wide.iter() {
+            let entry = self.entries.get(handle);
+            if predicate(&entry.aabb) {
+                f(&entry.aabb, &entry.t);
+            }
+        }
+        let min_left = left(query) - self.max_width;
+        let max_left = query.bottom_right_coord().x;
+        let first = self.order
+            .partition_point(|&handle| left(&self.entries.get(handle).aabb) < min_left);
+        for &handle in self.order[first..].iter() {
+            let entry = self.entries.get(handle);
+            if left(&entry.aabb) > max_left {
+                break;
+            }
+            if predicate(&entry.aabb) {
+                f(&entry.aabb, &entry.t);
+            }
+        }
+    }
+}
+
+impl<T, S: Scalar> Broadphase<T, S> for SweepAndPrune<T, S> {
+    fn insert(&mut self, aabb: Aabb<S>, t: T) -> BroadphaseHandle {
+        let handle = self.entries.insert(Entry { aabb, t, rank: None });
+        self.attach(handle);
+        handle
+    }
+    // entries usually move a short distance between updates, so the order is
+    // restored by moving the entry past its neighbours
+    fn update(&mut self, handle: BroadphaseHandle, aabb: Aabb<S>) {
+        let is_wide = self.is_wide(&aabb);
+        let old_rank = {
+            let entry = self.entries.get_mut(handle);
+            entry.aabb = aabb;
+            entry.rank
+        };
+        let mut rank = match old_rank {
+            Some(rank) if !is_wide => rank,
+            None if is_wide => return,
+            _ => {
+                self.detach(handle);
+                self.attach(handle);
+                return;
+            }
+        };
+        let x = left(&aabb);
+        while rank > 0 && self.left_at(rank - 1) > x {
+            self.swap_ranks(rank - 1, rank);
+            rank -= 1;
+        }
+        while rank + 1 < self.order.len() && self.left_at(rank + 1) < x {
+            self.swap_ranks(rank, rank + 1);
+            rank += 1;
+        }
+    }
+    fn remove(&mut self, handle: BroadphaseHandle) -> T {
+        self.detach(handle);
+        self.entries.remove(handle).t
+    }
+    fn clear(&mut self) {
+        self.order.clear();
+        self.wide.clear();
+        self.entries.clear();
+    }
+    fn for_each_intersection<F: FnMut(&Aabb<S>, &T)>(&self, aabb: Aabb<S>, mut f: F) {
+        self.for_each_matching(&aabb, |other| other.is_intersecting(&aabb), &mut f);
+    }
+    fn for_each_segment_intersection<F: FnMut(&Aabb<S>, &T)>(
+        &self,
+        start: Vector2<S>,
+        end: Vector2<S>,
+        mut f: F,
+    ) {
+        self.for_each_matching(
+            &Aabb::from_points(&[start, end]),
+            |other| other.is_intersecting_segment(start, end),
+            &mut f,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec2;
+
+    fn aabb(x: f64, y: f64, width: f64, height: f64) -> Aabb<f64> {
+        Aabb::new(vec2(x, y), vec2(width, height))
+    }
+
+    #[test]
+    fn wide_entries_are_kept_out_of_the_sorted_order() {
+        let mut sweep_and_prune = SweepAndPrune::new(10.);
+        let query = aabb(90., 0., 1., 1.);
+        let a = sweep_and_prune.insert(aabb(0., 0., 100., 1.), 1);
+        sweep_and_prune.insert(aabb(50., 0., 5., 1.), 2);
+        assert_eq!(sweep_and_prune.wide, vec![a]);
+        assert_eq!(sweep_and_prune.order.len(), 1);
+        let mut found = Vec::new();
+        sweep_and_prune.for_each_intersection(query, |_, &t| found.push(t));
+        assert_eq!(found, vec![1]);
+        // shrinking moves it into the sorted order without changing max_width
+        sweep_and_prune.update(a, aabb(80., 0., 5., 1.));
+        assert!(sweep_and_prune.wide.is_empty());
+        assert_eq!(sweep_and_prune.entries.get(a).rank, Some(1));
+        assert_eq!(sweep_and_prune.max_width, 10.);
+        sweep_and_prune.update(a, aabb(-80., 0., 50., 1.));
+        assert_eq!(sweep_and_prune.wide, vec![a]);
+        assert_eq!(sweep_and_prune.order.len(), 1);
+    }
+}
@@ -0,0 +1,305 @@
+use aabb::Aabb;
+use cgmath::{vec2, ElementWise, Vector2};
+use collide::{channels, Collide, Edge};
+use scalar::{real, Scalar};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tile {
+    Empty,
+    Solid,
+    OneWay,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BoundaryKind {
+    Top,
+    Bottom,
+    OneWay,
+    Left,
+    Right,
+}
+
+type Cell = (usize, usize);
+
+#[derive(Debug, Clone)]
+pub struct TileMap<S> {
+    cell_size: Vector2<S>,
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+    edges: Vec<Edge<S>>,
+    // the first and last cell of the run of tiles each edge was merged from
+    edge_cells: Vec<(Cell, Cell)>,
+    // indices into edges of the edges along each cell
+    cell_edges: Vec<Vec<usize>>,
+}
+
+impl<S: Scalar> TileMap<S> {
+    pub fn new(
+        cell_size: Vector2<S>,
+        width: usize,
+        height: usize,
+        tiles: Vec<Tile>,
+    ) -> Self {
+        assert_eq!(tiles.len(), width * height, "Tile count does not match size");
+        let mut tile_map = Self {
+            cell_size,
+            width,
+            height,
+            tiles,
+            edges: Vec::new(),
+            edge_cells: Vec::new(),
+            cell_edges: vec![Vec::new(); width * height],
+        };
+        tile_map.compute_edges();
+        tile_map
+    }
+
+    pub fn from_rows(cell_size: Vector2<S>, rows: &[&str]) -> Self {
+        let height = rows.len();
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut tiles = Vec::with_capacity(width * height);
+        for row in rows {
+            let mut chars = row.chars();
+            for _ in 0..width {
+                let tile = match chars.next() {
+                    Some('#') => Tile::Solid,
+                    Some('=') => Tile::OneWay,
+                    _ => Tile::Empty,
+                };
+                tiles.push(tile);
+            }
+        }
+        Self::new(cell_size, width, height, tiles)
+    }
+
+    pub fn tile(&self, x: isize, y: isize) -> Tile {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            Tile::Empty
+        } else {
+            self.tiles[y as usize * self.width + x as usize]
+        }
+    }
+
+    pub fn cell_size(&self) -> Vector2<S> {
+        self.cell_size
+    }
+
+    pub fn edges(&self) -> &[Edge<S>] {
+        &self.edges
+    }
+
+    // the cells overlapping the aabb, grown by margin cells on each side
+    fn cell_ranges(&self, aabb: Aabb<S>, margin: usize) -> (Range<usize>, Range<usize>) {
+        let min = aabb.top_left().div_element_wise(self.cell_size);
+        let max = aabb.bottom_right_coord().div_element_wise(self.cell_size);
+        let start = |min: S| {
+            (min.floor().max(real(0.)).as_f64() as usize).saturating_sub(margin)
+        };
+        let end = |max: S, size: usize| {
+            (max.ceil().max(real(0.)).as_f64() as usize)
+                .saturating_add(margin)
+                .min(size)
+        };
+        (
+            start(min.x)..end(max.x, self.width),
+            start(min.y)..end(max.y, self.height),
+        )
+    }
+
+    // aabb is relative to the top-left corner of the map
+    pub fn for_each_tile_in<F>(&self, aabb: Aabb<S>, mut f: F)
+    where
+        F: FnMut(Vector2<S>, Tile),
+    {
+        let (x_range, y_range) = self.cell_ranges(aabb, 0);
+        for y in y_range {
+            for x in x_range.clone() {
+                let tile = self.tiles[y * self.width + x];
+                if tile != Tile::Empty {
+                    f(self.corner(x, y), tile);
+                }
+            }
+        }
+    }
+
+    fn is_solid(&self, x: isize, y: isize) -> bool {
+        self.tile(x, y) == Tile::Solid
+    }
+
+    fn boundary_kind(&self, kind: BoundaryKind, x: isize, y: isize) -> bool {
+        match kind {
+            BoundaryKind::Top => self.is_solid(x, y) && !self.is_solid(x, y - 1),
+            BoundaryKind::Bottom => self.is_solid(x, y) && !self.is_solid(x, y + 1),
+            BoundaryKind::OneWay => {
+                self.tile(x, y) == Tile::OneWay && self.tile(x, y - 1) == Tile::Empty
+            }
+            BoundaryKind::Left => self.is_solid(x, y) && !self.is_solid(x - 1, y),
+            BoundaryKind::Right => self.is_solid(x, y) && !self.is_solid(x + 1, y),
+        }
+    }
+
+    fn corner(&self, x: usize, y: usize) -> Vector2<S> {
+        vec2(real(x as f64), real(y as f64)).mul_element_wise(self.cell_size)
+    }
+
+    fn boundary_edge(
+        &self,
+        kind: BoundaryKind,
+        first: usize,
+        last: usize,
+        line: usize,
+    ) -> Edge<S> {
+        match kind {
+            BoundaryKind::Top => {
+                Edge::new(self.corner(first, line), self.corner(last + 1, line))
+            }
+            BoundaryKind::OneWay => {
+                Edge::new(self.corner(first, line), self.corner(last + 1, line))
+                    .with_channels(channels::FLOOR)
+            }
+            BoundaryKind::Bottom => {
+                Edge::new(self.corner(last + 1, line + 1), self.corner(first, line + 1))
+            }
+            BoundaryKind::Left => {
+                Edge::new(self.corner(line, last + 1), self.corner(line, first))
+            }
+            BoundaryKind::Right => {
+                Edge::new(self.corner(line + 1, first), self.corner(line + 1, last + 1))
+            }
+        }
+    }
+
+    fn compute_edges(&mut self) {
+        let mut edges = Vec::new();
+        let horizontal = [BoundaryKind::Top, BoundaryKind::Bottom, BoundaryKind::OneWay];
+        let vertical = [BoundaryKind::Left, BoundaryKind::Right];
+        for &kind in horizontal.iter() {
+            for y in 0..self.height {
+                self.merge_runs(kind, y, self.width, |x| (x, y), &mut edges);
+            }
+        }
+        for &kind in vertical.iter() {
+            for x in 0..self.width {
+                self.merge_runs(kind, x, self.height, |y| (x, y), &mut edges);
+            }
+        }
+        for (index, &(edge, first, last)) in edges.iter().enumerate() {
+            for y in first.1..=last.1 {
+                for x in first.0..=last.0 {
+                    self.cell_edges[y * self.width + x].push(index);
+                }
+            }
+            self.edges.push(edge);
+            self.edge_cells.push((first, last));
+        }
+    }
+
+    fn merge_runs<F>(
+        &self,
+        kind: BoundaryKind,
+        line: usize,
+        length: usize,
+        coord: F,
+        edges: &mut Vec<(Edge<S>, Cell, Cell)>,
+    ) where
+        F: Fn(usize) -> (usize, usize),
+    {
+        let mut run_start = None;
+        for i in 0..length + 1 {
+            let on_boundary = i < length && {
+                let (x, y) = coord(i);
+                self.boundary_kind(kind, x as isize, y as isize)
+            };
+            match (run_start, on_boundary) {
+                (None, true) => run_start = Some(i),
+                (Some(first), false) => {
+                    edges.push((
+                        self.boundary_edge(kind, first, i - 1, line),
+                        coord(first),
+                        coord(i - 1),
+                    ));
+                    run_start = None;
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<S: Scalar> Collide<S> for TileMap<S> {
+    fn aabb(&self, top_left: Vector2<S>) -> Aabb<S> {
+        Aabb::new(top_left, self.corner(self.width, self.height))
+    }
+    fn for_each_left_solid_edge_facing<F: FnMut(Edge<S>)>(
+        &self,
+        direction: Vector2<S>,
+        mut f: F,
+    ) {
+        for edge in self.edges.iter() {
+            if edge.is_facing(direction) {
+                f(*edge);
+            }
+        }
+    }
+    // Edges spanning several cells are only visited from the first cell they
+    // share with the aabb, so nothing is visited twice. The margin keeps edges
+    // which only touch the aabb.
+    fn for_each_left_solid_edge_facing_in<F: FnMut(Edge<S>)>(
+        &self,
+        aabb: Aabb<S>,
+        direction: Vector2<S>,
+        mut f: F,
+    ) {
+        let (x_range, y_range) = self.cell_ranges(aabb, 1);
+        for y in y_range.clone() {
+            for x in x_range.clone() {
+                for &index in self.cell_edges[y * self.width + x].iter() {
+                    let (first, _) = self.edge_cells[index];
+                    let first_shared_cell =
+                        (first.0.max(x_range.start), first.1.max(y_range.start));
+                    let edge = &self.edges[index];
+                    if first_shared_cell == (x, y) && edge.is_facing(direction) {
+                        f(*edge);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_in_aabb_are_each_visited_once() {
+        let tile_map = TileMap::<f64>::from_rows(
+            vec2(real(10.), real(10.)),
+            &["..==..#", "#.####.", "######.", "..#...."],
+        );
+        let direction = vec2(real(0.), real(0.));
+        for y in -2..6 {
+            for x in -2..9 {
+                let aabb = Aabb::new(
+                    vec2(real(x as f64 * 7.), real(y as f64 * 7.)),
+                    vec2(real(13.), real(9.)),
+                );
+                let mut visited = Vec::new();
+                tile_map.for_each_left_solid_edge_facing_in(aabb, direction, |edge| {
+                    visited.push((edge.start(), edge.end()));
+                });
+                for (i, a) in visited.iter().enumerate() {
+                    assert!(!visited[i + 1..].contains(a), "edge visited twice");
+                }
+                for edge in tile_map.edges() {
+                    let edge_aabb = Aabb::from_points(&[edge.start(), edge.end()]);
+                    if edge_aabb.is_intersecting(&aabb) {
+                        assert!(visited.contains(&(edge.start(), edge.end())));
+                    }
+                }
+            }
+        }
+    }
+}
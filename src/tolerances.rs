@@ -0,0 +1,41 @@
+use scalar::{real, Scalar};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerances<S> {
+    // sine of the angle below which a movement counts as parallel to an edge
+    pub parallel: S,
+    // fraction of the attempted movement by which a shape may already be past
+    // a surface and still be stopped by it
+    pub contact_time: S,
+    // collisions whose fractions of the movement differ by less than this
+    // are treated as simultaneous
+    pub simultaneous_time: S,
+    // distance by which a point may start inside a rounded shape and still
+    // collide with it
+    pub round_gap: S,
+    // slack when comparing directions with unit surface normals
+    pub direction: S,
+    // distance within which shapes are touching rather than overlapping,
+    // and segments are flush against each other
+    pub touching_distance: S,
+    // added to the squared length of a step or corner nudge so the shape
+    // clears the ledge
+    pub bump_clearance: S,
+    // slack on the cosine of the steepest walkable slope
+    pub slope_cos: S,
+}
+
+impl<S: Scalar> Default for Tolerances<S> {
+    fn default() -> Self {
+        Self {
+            parallel: real(0.000000001),
+            contact_time: real(0.000001),
+            simultaneous_time: real(0.001),
+            round_gap: real(0.001),
+            direction: real(0.000001),
+            touching_distance: real(0.001),
+            bump_clearance: real(0.01),
+            slope_cos: real(0.001),
+        }
+    }
+}